@@ -9,12 +9,14 @@
  */
 
 
+use crate::rate_limit::Client;
 
 #[derive(Debug, Clone)]
 pub struct Configuration {
     pub base_path: String,
     pub user_agent: Option<String>,
-    pub client: reqwest::Client,
+    // Rate limited, see the rate_limit module
+    pub client: Client,
     pub basic_auth: Option<BasicAuth>,
     pub oauth_access_token: Option<String>,
     pub bearer_access_token: Option<String>,
@@ -42,7 +44,7 @@ impl Default for Configuration {
         Configuration {
             base_path: "https://api.spacetraders.io/v2".to_owned(),
             user_agent: Some("OpenAPI-Generator/2.0.0/rust".to_owned()),
-            client: reqwest::Client::new(),
+            client: Client::new(),
             basic_auth: None,
             oauth_access_token: None,
             bearer_access_token: None,
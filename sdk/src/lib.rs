@@ -5,6 +5,9 @@ extern crate serde;
 extern crate serde_json;
 extern crate url;
 extern crate reqwest;
+extern crate chrono;
 
 pub mod apis;
 pub mod models;
+pub mod rate_limit;
+pub mod wait;
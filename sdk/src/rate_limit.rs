@@ -0,0 +1,227 @@
+//! Client side throttling to stay inside the server's rate limit.
+//!
+//! The server allows a steady two requests a second with a small burst on top, and answers
+//! anything past that with 429 and a `Retry-After` header. The [`Client`] in every
+//! [`Configuration`](crate::apis::configuration::Configuration) takes a token from its
+//! [`RateLimiter`] before each request. The limiter is shared between clones, so all threads
+//! draw from the same bucket.
+//!
+//! This lives outside the generated code, which only calls `request` and `execute` on the client,
+//! so regenerating the SDK keeps it as long as `configuration.rs` still uses this `Client`.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use reqwest::{IntoUrl, Method, Request, RequestBuilder, Response, StatusCode};
+
+/// Requests a second the server allows without drawing on the burst
+pub const REQUESTS_PER_SECOND: f64 = 2.0;
+
+/// Requests that can go out back to back before we slow down to the steady rate
+pub const BURST: f64 = 10.0;
+
+/// How many times a request refused with 429 is sent again before giving up
+const RATE_LIMIT_RETRIES: u32 = 3;
+
+/// A `reqwest::Client` that keeps to the rate limit, with the methods the generated APIs use
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: reqwest::Client,
+    limiter: Arc<RateLimiter>,
+}
+
+impl Client {
+    pub fn new() -> Client {
+        Client { inner: reqwest::Client::new(), limiter: Arc::new(RateLimiter::default()) }
+    }
+
+    /// Rebuild the HTTP client so requests give up after `timeout`, or never if `None`.
+    /// The limiter carries over, so clones made before still share it.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.inner = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+
+    pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+        self.inner.request(method, url)
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.inner.get(url)
+    }
+
+    /// Send a request once the rate limiter allows it, and if the server still says we're going too
+    /// fast, wait as long as its `Retry-After` asks and try again
+    pub fn execute(&self, request: Request) -> Result<Response, reqwest::Error> {
+        let mut request = request;
+        let mut retries = 0;
+        loop {
+            let retry = request.try_clone();
+            self.limiter.acquire();
+            let resp = self.inner.execute(request)?;
+
+            match retry {
+                Some(retry) if resp.status() == StatusCode::TOO_MANY_REQUESTS && retries < RATE_LIMIT_RETRIES => {
+                    let delay = resp.headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(retry_after)
+                        .unwrap_or(Duration::from_secs(1));
+                    // The next acquire sleeps the delay out, along with anyone else sending requests
+                    self.limiter.back_off(delay);
+                    request = retry;
+                    retries += 1;
+                },
+                _ => return Ok(resp)
+            }
+        }
+    }
+
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client::new()
+    }
+}
+
+// Seconds to wait from a `Retry-After` header, which the server sends as a decimal
+fn retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<f64>().ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// A token bucket, refilled at a steady rate up to its burst size
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    // Requests let through and the total time they were held back, for callers that want to report them
+    requests: u64,
+    waited: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: f64, burst: f64) -> RateLimiter {
+        RateLimiter {
+            per_second,
+            burst,
+            state: Mutex::new(Bucket { tokens: burst, refilled_at: Instant::now(), requests: 0, waited: Duration::from_secs(0) }),
+        }
+    }
+
+    /// Take a token, sleeping until one is free. Returns how long we slept.
+    pub fn acquire(&self) -> Duration {
+        let delay = {
+            let mut bucket = self.state.lock().unwrap();
+            self.refill(&mut bucket);
+            bucket.tokens -= 1.0;
+            bucket.requests += 1;
+            let delay = if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / self.per_second)
+            } else {
+                Duration::from_secs(0)
+            };
+            bucket.waited += delay;
+            delay
+        };
+
+        // Sleep outside the lock, the negative balance already holds our place in the queue
+        if delay > Duration::from_secs(0) {
+            thread::sleep(delay);
+        }
+        delay
+    }
+
+    /// Hold every request back by `delay`, after the server told us to slow down
+    pub fn back_off(&self, delay: Duration) {
+        let mut bucket = self.state.lock().unwrap();
+        self.refill(&mut bucket);
+        bucket.tokens = bucket.tokens.min(0.0) - delay.as_secs_f64() * self.per_second;
+    }
+
+    /// How many requests have gone out so far
+    pub fn requests(&self) -> u64 {
+        self.state.lock().unwrap().requests
+    }
+
+    /// Total time requests have been held back so far
+    pub fn waited(&self) -> Duration {
+        self.state.lock().unwrap().waited
+    }
+
+    /// The steady rate requests are allowed at
+    pub fn per_second(&self) -> f64 {
+        self.per_second
+    }
+
+    fn refill(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+        bucket.refilled_at = now;
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(REQUESTS_PER_SECOND, BURST)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(duration: Duration) -> u128 {
+        duration.as_millis()
+    }
+
+    #[test]
+    fn burst_goes_out_at_once_then_the_steady_rate() {
+        let limiter = RateLimiter::new(2.0, 3.0);
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire(), Duration::from_secs(0));
+        }
+
+        // Half a second for the next token, less whatever refilled while we ran
+        let delay = millis(limiter.acquire());
+        assert!((400..=500).contains(&delay), "waited {}ms", delay);
+        assert_eq!(limiter.requests(), 4);
+        assert_eq!(limiter.waited().as_millis(), delay);
+    }
+
+    #[test]
+    fn back_off_holds_the_next_request() {
+        let limiter = RateLimiter::new(2.0, 3.0);
+        limiter.back_off(Duration::from_millis(100));
+
+        // The back off, then half a second for the token it used up
+        let delay = millis(limiter.acquire());
+        assert!((500..=600).contains(&delay), "waited {}ms", delay);
+    }
+
+    #[test]
+    fn retry_after_is_seconds() {
+        assert_eq!(retry_after("2"), Some(Duration::from_secs(2)));
+        assert_eq!(retry_after(" 0.5 "), Some(Duration::from_millis(500)));
+        assert_eq!(retry_after("-1"), None);
+        assert_eq!(retry_after("NaN"), None);
+        assert_eq!(retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+    }
+}
@@ -0,0 +1,325 @@
+//! Helpers for waiting out a ship's transit or cooldown.
+//!
+//! The server reports arrival and cooldown expiry as absolute timestamps in its own clock,
+//! so every wait goes through a [`ClockSkew`] to convert them to local time before sleeping.
+//! After waking the ship's state is fetched again, since the server is the source of truth.
+//!
+//! The `_async` versions sleep without blocking the executor, for callers that drive their
+//! own runtime.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Instant;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::apis::configuration::Configuration;
+use crate::apis::fleet_api::{self, GetShipCooldownError, GetShipNavError};
+use crate::apis::Error;
+use crate::models::{Cooldown, ShipNav, ShipNavStatus};
+
+/// Extra time to sleep past a deadline, to cover the one second resolution of server timestamps
+const WAKE_MARGIN_MILLIS: i64 = 500;
+
+/// How long to back off when the server still reports a ship as busy after we wake
+const RETRY_SECONDS: i64 = 1;
+
+/// Difference between the server's clock and ours.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ClockSkew {
+    /// Server time minus local time
+    pub offset: Duration,
+}
+
+impl ClockSkew {
+    /// Assume both clocks agree
+    pub fn none() -> ClockSkew {
+        ClockSkew::default()
+    }
+
+    /// Measure the skew from the `Date` header of a request to the API root
+    pub fn measure(configuration: &Configuration) -> Result<ClockSkew, Error<()>> {
+        // Sent straight from the builder, so take our turn first for the timing to be fair
+        configuration.client.rate_limiter().acquire();
+        let before = Utc::now();
+        let resp = configuration.client.get(configuration.base_path.as_str()).send()?;
+        let after = Utc::now();
+
+        let server = resp.headers()
+            .get(reqwest::header::DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+            .map(|date| date.with_timezone(&Utc));
+
+        match server {
+            Some(server) => {
+                let local = before + (after - before) / 2;
+                Ok(ClockSkew { offset: server - local })
+            },
+            None => Ok(ClockSkew::none())
+        }
+    }
+
+    /// The server's current time
+    pub fn server_now(&self) -> DateTime<Utc> {
+        Utc::now() + self.offset
+    }
+
+    /// Convert a server timestamp to the equivalent local time
+    pub fn to_local(&self, server_time: DateTime<Utc>) -> DateTime<Utc> {
+        server_time - self.offset
+    }
+
+    /// How long from now until a server timestamp, zero if it has already passed
+    pub fn until(&self, server_time: DateTime<Utc>) -> std::time::Duration {
+        (self.to_local(server_time) - Utc::now() + Duration::milliseconds(WAKE_MARGIN_MILLIS))
+            .to_std()
+            .unwrap_or_default()
+    }
+}
+
+/// Parse an ISO 8601 timestamp from the API
+pub fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp).ok().map(|t| t.with_timezone(&Utc))
+}
+
+/// Fetch a ship's cooldown, or `None` if it isn't cooling down.
+/// The API answers 204 with an empty body in that case, which the generated client can't parse.
+pub fn current_cooldown(configuration: &Configuration, ship_symbol: &str) -> Result<Option<Cooldown>, Error<GetShipCooldownError>> {
+    match fleet_api::get_ship_cooldown(configuration, ship_symbol) {
+        Ok(resp) if resp.data.remaining_seconds > 0 => Ok(Some(*resp.data)),
+        Ok(_) => Ok(None),
+        Err(Error::Serde(e)) if e.is_eof() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Block until a ship has arrived at its destination, returning its refreshed nav.
+pub fn wait_for_arrival(configuration: &Configuration, ship_symbol: &str, nav: &ShipNav, skew: &ClockSkew) -> Result<ShipNav, Error<GetShipNavError>> {
+    let mut nav = nav.clone();
+
+    while nav.status == ShipNavStatus::InTransit {
+        thread::sleep(arrival_delay(&nav, skew));
+        nav = *fleet_api::get_ship_nav(configuration, ship_symbol)?.data;
+    }
+
+    Ok(nav)
+}
+
+/// Block until a cooldown has expired, confirming with the server before returning.
+pub fn wait_for_cooldown(configuration: &Configuration, cooldown: &Cooldown, skew: &ClockSkew) -> Result<(), Error<GetShipCooldownError>> {
+    let mut cooldown = Some(cooldown.clone());
+
+    while let Some(current) = cooldown {
+        thread::sleep(cooldown_delay(&current, skew));
+        cooldown = current_cooldown(configuration, &current.ship_symbol)?;
+    }
+
+    Ok(())
+}
+
+/// Async version of [`wait_for_arrival`]. Sleeping doesn't block the executor, though the
+/// refresh afterwards is made with the blocking client.
+pub async fn wait_for_arrival_async(configuration: &Configuration, ship_symbol: &str, nav: &ShipNav, skew: &ClockSkew) -> Result<ShipNav, Error<GetShipNavError>> {
+    let mut nav = nav.clone();
+
+    while nav.status == ShipNavStatus::InTransit {
+        Sleep::new(arrival_delay(&nav, skew)).await;
+        nav = *fleet_api::get_ship_nav(configuration, ship_symbol)?.data;
+    }
+
+    Ok(nav)
+}
+
+/// Async version of [`wait_for_cooldown`]
+pub async fn wait_for_cooldown_async(configuration: &Configuration, cooldown: &Cooldown, skew: &ClockSkew) -> Result<(), Error<GetShipCooldownError>> {
+    let mut cooldown = Some(cooldown.clone());
+
+    while let Some(current) = cooldown {
+        Sleep::new(cooldown_delay(&current, skew)).await;
+        cooldown = current_cooldown(configuration, &current.ship_symbol)?;
+    }
+
+    Ok(())
+}
+
+fn arrival_delay(nav: &ShipNav, skew: &ClockSkew) -> std::time::Duration {
+    retry_if_passed(parse_timestamp(&nav.route.arrival).map(|arrival| skew.until(arrival)))
+}
+
+fn cooldown_delay(cooldown: &Cooldown, skew: &ClockSkew) -> std::time::Duration {
+    match parse_timestamp(&cooldown.expiration) {
+        Some(expiration) => retry_if_passed(Some(skew.until(expiration))),
+        None => retry_if_passed(Duration::seconds(i64::from(cooldown.remaining_seconds)).to_std().ok())
+    }
+}
+
+// If the deadline has already gone but the server still says busy, don't spin on it
+fn retry_if_passed(delay: Option<std::time::Duration>) -> std::time::Duration {
+    let retry = Duration::seconds(RETRY_SECONDS).to_std().unwrap_or_default();
+
+    match delay {
+        Some(delay) if delay > std::time::Duration::from_millis(WAKE_MARGIN_MILLIS as u64) => delay,
+        _ => retry
+    }
+}
+
+/// A runtime agnostic timer future, backed by a thread that wakes the task when it elapses.
+pub struct Sleep {
+    deadline: Instant,
+    waker: Option<Arc<Mutex<Waker>>>,
+}
+
+impl Sleep {
+    pub fn new(duration: std::time::Duration) -> Sleep {
+        Sleep { deadline: Instant::now() + duration, waker: None }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        match &self.waker {
+            Some(waker) => {
+                // The task may have moved to another executor thread since the last poll
+                if let Ok(mut waker) = waker.lock() {
+                    if !waker.will_wake(cx.waker()) {
+                        *waker = cx.waker().clone();
+                    }
+                }
+            },
+            None => {
+                let waker = Arc::new(Mutex::new(cx.waker().clone()));
+                let timer_waker = waker.clone();
+                let delay = self.deadline - now;
+
+                thread::spawn(move || {
+                    thread::sleep(delay);
+                    if let Ok(waker) = timer_waker.lock() {
+                        waker.wake_by_ref();
+                    }
+                });
+
+                self.waker = Some(waker);
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ShipNavFlightMode, ShipNavRoute, ShipNavRouteWaypoint, WaypointType};
+
+    // Just enough of an executor to drive a future on this thread, counting the polls it took
+    struct Unpark(thread::Thread);
+
+    impl std::task::Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        let mut polls = 0;
+        loop {
+            polls += 1;
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return (output, polls);
+            }
+            thread::park();
+        }
+    }
+
+    fn docked() -> ShipNav {
+        let waypoint = ShipNavRouteWaypoint::new("X1-AB-A1".to_string(), WaypointType::Planet, "X1-AB".to_string(), 0, 0);
+        let route = ShipNavRoute::new(waypoint.clone(), waypoint, String::new(), String::new());
+        ShipNav::new("X1-AB".to_string(), "X1-AB-A1".to_string(), route, ShipNavStatus::Docked, ShipNavFlightMode::Cruise)
+    }
+
+    const MARGIN: std::time::Duration = std::time::Duration::from_millis(WAKE_MARGIN_MILLIS as u64);
+
+    fn skew(seconds: i64) -> ClockSkew {
+        ClockSkew { offset: Duration::seconds(seconds) }
+    }
+
+    fn secs(duration: std::time::Duration) -> u64 {
+        duration.as_secs()
+    }
+
+    #[test]
+    fn server_times_convert_to_local() {
+        let server_time = Utc::now();
+        assert_eq!(skew(30).to_local(server_time), server_time - Duration::seconds(30));
+        assert!(skew(30).server_now() > Utc::now() + Duration::seconds(29));
+        assert_eq!(ClockSkew::none().to_local(server_time), server_time);
+    }
+
+    #[test]
+    fn until_allows_for_skew_and_the_wake_margin() {
+        // The server runs a minute ahead, so its deadline a minute and a half away is only half a minute off here
+        let deadline = Utc::now() + Duration::seconds(90);
+        let delay = skew(60).until(deadline);
+        assert_eq!(secs(delay), 30);
+        assert!(delay > std::time::Duration::from_secs(30));
+    }
+
+    #[test]
+    fn until_a_deadline_that_has_passed_is_no_wait() {
+        assert_eq!(skew(0).until(Utc::now() - Duration::seconds(5)), std::time::Duration::from_secs(0));
+        // Unless the server's clock is behind by more than that
+        assert_eq!(secs(skew(-10).until(Utc::now() - Duration::seconds(5))), 5);
+    }
+
+    #[test]
+    fn retry_when_the_deadline_has_passed() {
+        let retry = std::time::Duration::from_secs(RETRY_SECONDS as u64);
+        assert_eq!(retry_if_passed(None), retry);
+        assert_eq!(retry_if_passed(Some(std::time::Duration::from_secs(0))), retry);
+        assert_eq!(retry_if_passed(Some(MARGIN)), retry);
+        assert_eq!(retry_if_passed(Some(std::time::Duration::from_secs(40))), std::time::Duration::from_secs(40));
+    }
+
+    #[test]
+    fn timestamps_parse_from_any_offset() {
+        let utc = parse_timestamp("2026-03-01T12:00:00.000Z").unwrap();
+        assert_eq!(parse_timestamp("2026-03-01T14:00:00+02:00"), Some(utc));
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn sleep_wakes_the_task_once_elapsed() {
+        let started = Instant::now();
+        let ((), polls) = block_on(Sleep::new(std::time::Duration::from_millis(50)));
+        assert!(started.elapsed() >= std::time::Duration::from_millis(50));
+        assert!(polls >= 2, "polled {} times", polls);
+
+        let ((), polls) = block_on(Sleep::new(std::time::Duration::from_secs(0)));
+        assert_eq!(polls, 1);
+    }
+
+    #[test]
+    fn async_waits_return_straight_away_with_nothing_to_wait_for() {
+        // Nothing listens here, so any request would fail
+        let mut configuration = Configuration::new();
+        configuration.base_path = "http://127.0.0.1:9".to_string();
+
+        let (nav, polls) = block_on(wait_for_arrival_async(&configuration, "SHIP-1", &docked(), &ClockSkew::none()));
+        assert_eq!(nav.unwrap().status, ShipNavStatus::Docked);
+        assert_eq!(polls, 1);
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+
+use comfy_table::Table;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use spacetraders_sdk::apis::{contracts_api, fleet_api, Error as ApiError};
+use spacetraders_sdk::models::ship_refine_request::Produce;
+use spacetraders_sdk::models::{Cooldown, DeliverContractRequest, ExtractResourcesRequest, JettisonRequest, JumpShipRequest, MarketTransaction,
+                               NavigateShipRequest, PurchaseCargoRequest, SellCargoRequest, ShipCargo, ShipFuel, ShipNav, ShipRefineRequest,
+                               TradeSymbol, TransferCargoRequest};
+use spacetraders_sdk::wait;
+
+use crate::commands::{command, CommandError, CommandResult, CommandSpec, Value};
+use crate::config::ConfigWrapper;
+use crate::output::{self, Format};
+
+const REFINABLE: &str = "IRON, COPPER, SILVER, GOLD, ALUMINUM, PLATINUM, URANITE, MERITIUM or FUEL";
+
+/// One command for each thing a ship or contract can be told to do
+pub fn commands() -> Vec<CommandSpec> {
+    vec![
+        command!("dock", dock)
+            .with_path("ship dock")
+            .with_help("Dock a ship at its current waypoint")
+            .required("ship"),
+        command!("orbit", orbit)
+            .with_path("ship orbit")
+            .with_help("Put a ship into orbit around its current waypoint")
+            .required("ship"),
+        command!("navigate", navigate)
+            .with_path("ship navigate")
+            .with_help("Fly a ship to a waypoint in its current system")
+            .required("ship")
+            .required("waypoint"),
+        command!("warp", warp)
+            .with_path("ship warp")
+            .with_help("Warp a ship to a waypoint in another system")
+            .required("ship")
+            .required("waypoint"),
+        command!("jump", jump)
+            .with_path("ship jump")
+            .with_help("Jump a ship through its system's jump gate to another system")
+            .required("ship")
+            .required("system"),
+        command!("refuel", refuel)
+            .with_path("ship refuel")
+            .with_help("Fill a docked ship's tanks at the local market")
+            .required("ship"),
+        command!("extract", extract)
+            .with_path("ship extract")
+            .with_help("Mine the ship's current waypoint, optionally using a survey signature from this session")
+            .required("ship")
+            .optional("survey"),
+        command!("survey", survey)
+            .with_path("ship survey")
+            .with_help("Survey the ship's current waypoint for deposits, keeping the surveys for extract")
+            .required("ship"),
+        command!("scan", scan)
+            .with_path("ship scan")
+            .with_help("Scan for waypoints, systems or ships around a ship")
+            .required("ship")
+            .with_default("kind", "waypoints"),
+        command!("chart", chart)
+            .with_path("ship chart")
+            .with_help("Chart the ship's current waypoint")
+            .required("ship"),
+        command!("buy", buy)
+            .with_path("ship buy")
+            .with_help("Buy goods at the ship's market")
+            .required("ship")
+            .required("good")
+            .required("units"),
+        command!("sell", sell)
+            .with_path("ship sell")
+            .with_help("Sell goods from the ship's cargo at its market")
+            .required("ship")
+            .required("good")
+            .required("units"),
+        command!("jettison", jettison)
+            .with_path("ship jettison")
+            .with_help("Throw goods out of the ship's cargo hold")
+            .required("ship")
+            .required("good")
+            .required("units"),
+        command!("transfer", transfer)
+            .with_path("ship transfer")
+            .with_help("Move goods to another ship at the same waypoint")
+            .required("ship")
+            .required("to")
+            .required("good")
+            .required("units"),
+        command!("refine", refine)
+            .with_path("ship refine")
+            .with_help("Refine ore in the ship's hold into the given good")
+            .required("ship")
+            .required("produce"),
+        command!("deliver", deliver)
+            .with_path("contract deliver")
+            .with_help("Deliver goods from a docked ship towards a contract")
+            .required("contract")
+            .required("ship")
+            .required("good")
+            .required("units"),
+        command!("fulfill", fulfill)
+            .with_path("contract fulfill")
+            .with_help("Complete a contract once everything has been delivered")
+            .required("contract"),
+    ]
+}
+
+fn dock(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+
+    let data = fleet_api::dock_ship(&context.api_config, &ship).map_err(|e| failed("dock", e))?.data;
+
+    summary(context, &data, vec![nav_summary(&ship, &data.nav)])
+}
+
+fn orbit(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+
+    let data = fleet_api::orbit_ship(&context.api_config, &ship).map_err(|e| failed("orbit", e))?.data;
+
+    summary(context, &data, vec![nav_summary(&ship, &data.nav)])
+}
+
+fn navigate(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+    let waypoint = waypoint(&args, "waypoint")?;
+
+    let request = NavigateShipRequest::new(waypoint);
+    let data = fleet_api::navigate_ship(&context.api_config, &ship, Some(request)).map_err(|e| failed("navigate", e))?.data;
+
+    summary(context, &data, vec![nav_summary(&ship, &data.nav), fuel_summary(&data.fuel)])
+}
+
+fn warp(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+    let waypoint = waypoint(&args, "waypoint")?;
+
+    let request = NavigateShipRequest::new(waypoint);
+    let data = fleet_api::warp_ship(&context.api_config, &ship, Some(request)).map_err(|e| failed("warp", e))?.data;
+
+    summary(context, &data, vec![nav_summary(&ship, &data.nav), fuel_summary(&data.fuel)])
+}
+
+fn jump(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+    let system = symbol(&args, "system")?;
+    if system.split('-').count() != 2 {
+        return Err(format!("{} doesn't look like a system, expected something like X1-AB12", system).into());
+    }
+
+    let request = JumpShipRequest::new(system);
+    let data = fleet_api::jump_ship(&context.api_config, &ship, Some(request)).map_err(|e| failed("jump", e))?.data;
+
+    let mut lines = Vec::new();
+    if let Some(nav) = &data.nav {
+        lines.push(nav_summary(&ship, nav));
+    }
+    lines.push(cooldown_summary(&data.cooldown));
+    summary(context, &data, lines)
+}
+
+fn refuel(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+
+    let before = tank(context, &ship);
+    let data = fleet_api::refuel_ship(&context.api_config, &ship).map_err(|e| failed("refuel", e))?.data;
+    let (added, price) = before.map(|(fuel, price)| (Some(data.fuel.current - fuel), price)).unwrap_or_default();
+    context.ledger.record_refuel("manual", &ship, added, price, data.agent.credits);
+
+    summary(context, &data, vec![fuel_summary(&data.fuel), format!("Credits {}", data.agent.credits)])
+}
+
+/// What's in a ship's tank and what fuel costs where it is, if we've seen that market. Refuel responses
+/// don't say what was paid, so this is taken beforehand to cost it in the ledger.
+pub fn tank(context: &ConfigWrapper, ship: &str) -> Option<(i32, Option<i32>)> {
+    let ship = fleet_api::get_my_ship(&context.api_config, ship).ok()?.data;
+    Some((ship.fuel.current, context.cache.purchase_price(&ship.nav.waypoint_symbol, "FUEL")))
+}
+
+fn extract(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+
+    // Expired surveys are rejected by the server, so there's no point keeping them
+    context.surveys.retain(|s| wait::parse_timestamp(&s.expiration).map(|e| e > chrono::Utc::now()).unwrap_or(false));
+
+    let mut request = ExtractResourcesRequest::new();
+    if let Some(signature) = args.get("survey") {
+        let signature = signature.to_string();
+        match context.surveys.iter().find(|s| s.signature == signature) {
+            Some(survey) => request.survey = Some(Box::new(survey.clone())),
+            None => return Err(format!("No unexpired survey {} from this session, run survey first", signature).into())
+        }
+    }
+
+    let data = fleet_api::extract_resources(&context.api_config, &ship, Some(request)).map_err(|e| failed("extract", e))?.data;
+
+    let extracted = &data.extraction.r#yield;
+    summary(context, &data, vec![
+        format!("Extracted {} {}", extracted.units, extracted.symbol),
+        cargo_summary(&data.cargo),
+        cooldown_summary(&data.cooldown),
+    ])
+}
+
+fn survey(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+
+    let data = fleet_api::create_survey(&context.api_config, &ship).map_err(|e| failed("survey", e))?.data;
+    context.surveys.extend(data.surveys.iter().cloned());
+
+    if context.output != Format::Table {
+        return Ok(Some(output::render(context.output, Table::new(), &data.surveys)));
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["Signature", "Waypoint", "Size", "Deposits", "Expires"]);
+    for survey in &data.surveys {
+        let deposits: Vec<String> = survey.deposits.iter().map(|d| d.symbol.clone()).collect();
+        table.add_row(vec![survey.signature.clone(), survey.symbol.clone(), format!("{:?}", survey.size), deposits.join(", "), survey.expiration.clone()]);
+    }
+
+    Ok(Some(format!("{}\n{}", table, cooldown_summary(&data.cooldown))))
+}
+
+fn scan(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+    let kind = args.get("kind").unwrap().to_string().to_lowercase();
+    let api_config = context.api_config.clone();
+
+    let mut table = Table::new();
+    let (records, cooldown) = match kind.as_str() {
+        "waypoints" => {
+            let data = fleet_api::create_ship_waypoint_scan(&api_config, &ship).map_err(|e| failed("scan", e))?.data;
+            table.set_header(vec!["Waypoint", "Type", "X", "Y"]);
+            for w in &data.waypoints {
+                table.add_row(vec![w.symbol.clone(), w.r#type.to_string(), w.x.to_string(), w.y.to_string()]);
+            }
+            context.cache.record_scanned_waypoints(data.waypoints.clone());
+            (json(&data.waypoints), data.cooldown)
+        },
+        "systems" => {
+            let data = fleet_api::create_ship_system_scan(&api_config, &ship).map_err(|e| failed("scan", e))?.data;
+            table.set_header(vec!["System", "Type", "X", "Y", "Distance"]);
+            for s in &data.systems {
+                table.add_row(vec![s.symbol.clone(), s.r#type.to_string(), s.x.to_string(), s.y.to_string(), s.distance.to_string()]);
+            }
+            context.cache.record_scanned_systems(data.systems.clone());
+            (json(&data.systems), data.cooldown)
+        },
+        "ships" => {
+            let data = fleet_api::create_ship_ship_scan(&api_config, &ship).map_err(|e| failed("scan", e))?.data;
+            table.set_header(vec!["Ship", "Faction", "Role", "Status", "Waypoint"]);
+            for s in &data.ships {
+                table.add_row(vec![s.symbol.clone(), s.registration.faction_symbol.clone().unwrap_or_default(), s.registration.role.to_string(),
+                                   s.nav.status.to_string(), s.nav.waypoint_symbol.clone()]);
+            }
+            (json(&data.ships), data.cooldown)
+        },
+        _ => return Err(format!("Can't scan for {}, expected waypoints, systems or ships", kind).into())
+    };
+
+    if context.output != Format::Table {
+        let records = match records {
+            JsonValue::Array(records) => records,
+            _ => Vec::new()
+        };
+        return Ok(Some(output::render(context.output, table, &records)));
+    }
+
+    Ok(Some(format!("{}\n{}", table, cooldown_summary(&cooldown))))
+}
+
+fn chart(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+
+    let data = fleet_api::create_chart(&context.api_config, &ship).map_err(|e| failed("chart", e))?.data;
+    context.cache.record_waypoint((*data.waypoint).clone());
+
+    summary(context, &data, vec![format!("Charted {}", data.waypoint.symbol)])
+}
+
+fn buy(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+    let good = trade_symbol(&args, "good")?;
+    let units = units(&args, "units")?;
+
+    let request = PurchaseCargoRequest::new(good, units);
+    let data = fleet_api::purchase_cargo(&context.api_config, &ship, Some(request)).map_err(|e| failed("buy", e))?.data;
+    context.ledger.record_trade("manual", &data.transaction, data.agent.credits);
+
+    summary(context, &data, vec![transaction_summary(&data.transaction), cargo_summary(&data.cargo), format!("Credits {}", data.agent.credits)])
+}
+
+fn sell(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+    let good = trade_symbol(&args, "good")?;
+    let units = units(&args, "units")?;
+
+    let request = SellCargoRequest::new(good, units);
+    let data = fleet_api::sell_cargo(&context.api_config, &ship, Some(request)).map_err(|e| failed("sell", e))?.data;
+    context.ledger.record_trade("manual", &data.transaction, data.agent.credits);
+
+    summary(context, &data, vec![transaction_summary(&data.transaction), cargo_summary(&data.cargo), format!("Credits {}", data.agent.credits)])
+}
+
+fn jettison(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+    let good = trade_symbol(&args, "good")?;
+    let units = units(&args, "units")?;
+
+    let request = JettisonRequest::new(good.clone(), units);
+    let data = fleet_api::jettison(&context.api_config, &ship, Some(request)).map_err(|e| failed("jettison", e))?.data;
+
+    summary(context, &data, vec![format!("Jettisoned {} {}", units, good), cargo_summary(&data.cargo)])
+}
+
+fn transfer(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+    let to = self::ship(&args, "to")?;
+    let good = trade_symbol(&args, "good")?;
+    let units = units(&args, "units")?;
+    if ship == to {
+        return Err("Can't transfer cargo to the same ship".into());
+    }
+
+    let request = TransferCargoRequest::new(good.clone(), units, to.clone());
+    let data = fleet_api::transfer_cargo(&context.api_config, &ship, Some(request)).map_err(|e| failed("transfer", e))?.data;
+
+    summary(context, &data, vec![format!("Transferred {} {} to {}", units, good, to), cargo_summary(&data.cargo)])
+}
+
+fn refine(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = ship(&args, "ship")?;
+    let produce = symbol(&args, "produce")?;
+    let produce: Produce = serde_json::from_value(JsonValue::String(produce.clone()))
+        .map_err(|_| format!("Can't refine {}, expected {}", produce, REFINABLE))?;
+
+    let data = fleet_api::ship_refine(&context.api_config, &ship, Some(ShipRefineRequest::new(produce))).map_err(|e| failed("refine", e))?.data;
+
+    let goods = |goods: &Vec<spacetraders_sdk::models::ShipRefine200ResponseDataProducedInner>| goods.iter()
+        .map(|g| format!("{} {}", g.units.unwrap_or_default(), g.trade_symbol.clone().unwrap_or_default()))
+        .collect::<Vec<String>>()
+        .join(", ");
+    summary(context, &data, vec![
+        format!("Refined {} into {}", goods(&data.consumed), goods(&data.produced)),
+        cargo_summary(&data.cargo),
+        cooldown_summary(&data.cooldown),
+    ])
+}
+
+fn deliver(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let contract = args.get("contract").unwrap().to_string();
+    let ship = ship(&args, "ship")?;
+    let good = trade_symbol(&args, "good")?;
+    let units = units(&args, "units")?;
+
+    let request = DeliverContractRequest::new(ship, good.clone(), units);
+    let data = contracts_api::deliver_contract(&context.api_config, &contract, Some(request)).map_err(|e| failed("deliver", e))?.data;
+
+    let mut lines = vec![format!("Delivered {} {} to contract {}", units, good, contract)];
+    for goal in data.contract.terms.deliver.iter().flatten() {
+        lines.push(format!("{} to {} {}/{}", goal.trade_symbol, goal.destination_symbol, goal.units_fulfilled, goal.units_required));
+    }
+    lines.push(cargo_summary(&data.cargo));
+    summary(context, &data, lines)
+}
+
+fn fulfill(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let contract = args.get("contract").unwrap().to_string();
+
+    let data = contracts_api::fulfill_contract(&context.api_config, &contract).map_err(|e| failed("fulfill", e))?.data;
+    context.ledger.record_contract_payment(&data.contract.id, None, data.contract.terms.payment.on_fulfilled, data.agent.credits);
+
+    summary(context, &data, vec![
+        format!("Fulfilled contract {} for {} credits", contract, data.contract.terms.payment.on_fulfilled),
+        format!("Credits {}", data.agent.credits),
+    ])
+}
+
+// The response as a record for json/csv output, otherwise a line for each thing that changed
+fn summary<T: Serialize>(context: &ConfigWrapper, data: &T, lines: Vec<String>) -> CommandResult {
+    if context.output != Format::Table {
+        return Ok(Some(output::render(context.output, Table::new(), &[data])));
+    }
+    Ok(Some(lines.join("\n")))
+}
+
+fn nav_summary(ship: &str, nav: &ShipNav) -> String {
+    match nav.status {
+        spacetraders_sdk::models::ShipNavStatus::InTransit => {
+            let arrival = wait::parse_timestamp(&nav.route.arrival)
+                .map(|a| a.with_timezone(&chrono::Local).format("%H:%M:%S").to_string())
+                .unwrap_or_default();
+            format!("{} in transit to {}, arriving {}", ship, nav.route.destination.symbol, arrival)
+        },
+        _ => format!("{} {} at {}", ship, nav.status.to_string().to_lowercase().replace('_', " "), nav.waypoint_symbol)
+    }
+}
+
+fn fuel_summary(fuel: &ShipFuel) -> String {
+    match &fuel.consumed {
+        Some(consumed) if consumed.amount > 0 => format!("Fuel {}/{} (used {})", fuel.current, fuel.capacity, consumed.amount),
+        _ => format!("Fuel {}/{}", fuel.current, fuel.capacity)
+    }
+}
+
+fn cargo_summary(cargo: &ShipCargo) -> String {
+    let goods: Vec<String> = cargo.inventory.iter().map(|i| format!("{} {}", i.units, i.symbol)).collect();
+    format!("Cargo {}/{} {}", cargo.units, cargo.capacity, goods.join(", ")).trim_end().to_string()
+}
+
+fn cooldown_summary(cooldown: &Cooldown) -> String {
+    format!("Cooldown {}s", cooldown.remaining_seconds)
+}
+
+fn transaction_summary(transaction: &MarketTransaction) -> String {
+    format!("{} {} {} at {} each, {} credits in total", match transaction.r#type {
+        spacetraders_sdk::models::market_transaction::RHashType::Purchase => "Bought",
+        spacetraders_sdk::models::market_transaction::RHashType::Sell => "Sold",
+    }, transaction.units, transaction.trade_symbol, transaction.price_per_unit, transaction.total_price)
+}
+
+fn json<T: Serialize>(data: &T) -> JsonValue {
+    serde_json::to_value(data).unwrap_or(JsonValue::Null)
+}
+
+// Symbols are upper case, but nobody wants to hold shift in the REPL
+fn symbol(args: &HashMap<String, Value>, name: &str) -> Result<String, CommandError> {
+    let value = args.get(name).map(|v| v.to_string().trim().to_uppercase()).unwrap_or_default();
+    if value.is_empty() {
+        return Err(format!("Missing {}", name).into());
+    }
+    Ok(value)
+}
+
+fn ship(args: &HashMap<String, Value>, name: &str) -> Result<String, CommandError> {
+    symbol(args, name)
+}
+
+fn waypoint(args: &HashMap<String, Value>, name: &str) -> Result<String, CommandError> {
+    let waypoint = symbol(args, name)?;
+    if waypoint.split('-').count() != 3 {
+        return Err(format!("{} doesn't look like a waypoint, expected something like X1-AB12-C34", waypoint).into());
+    }
+    Ok(waypoint)
+}
+
+fn trade_symbol(args: &HashMap<String, Value>, name: &str) -> Result<String, CommandError> {
+    let good = symbol(args, name)?.replace('-', "_");
+    serde_json::from_value::<TradeSymbol>(JsonValue::String(good.clone()))
+        .map_err(|_| format!("Unknown trade good {}", good))?;
+    Ok(good)
+}
+
+fn units(args: &HashMap<String, Value>, name: &str) -> Result<i32, CommandError> {
+    let units: i32 = args.get(name).ok_or(format!("Missing {}", name))?.convert()?;
+    if units <= 0 {
+        return Err(format!("Expected at least 1 for {}, got {}", name, units).into());
+    }
+    Ok(units)
+}
+
+/// The server explains why an action was refused in the response body, which beats a bare status code
+pub fn failed<T>(action: &str, e: ApiError<T>) -> CommandError {
+    if let ApiError::ResponseError(response) = &e {
+        let message = serde_json::from_str::<JsonValue>(&response.content)
+            .ok()
+            .and_then(|body| body["error"]["message"].as_str().map(|m| m.to_string()));
+        if let Some(message) = message {
+            return format!("Failed to {} - {}", action, message).into();
+        }
+    }
+    format!("Failed to {} - {}", action, e).into()
+}
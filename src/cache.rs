@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use spacetraders_sdk::apis::configuration::Configuration;
+use spacetraders_sdk::models::{JumpGate, Market, MarketTradeGood, ScannedSystem, ScannedWaypoint, Shipyard, System, Waypoint};
+use spacetraders_sdk::models::ship_frame::Symbol as ShipFrameSymbol;
+use spacetraders_sdk::models::waypoint_trait::Symbol as WaypointTraitSymbol;
+
+const CACHE_FILE: &str = "spacetraders_cache.json";
+
+// The API caps page sizes at 20
+const PAGE_LIMIT: i32 = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MarketSnapshot {
+    pub market: Market,
+    pub fetched_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShipyardSnapshot {
+    pub shipyard: Shipyard,
+    pub fetched_at: String,
+}
+
+impl MarketSnapshot {
+    pub fn purchase_price(&self, trade_symbol: &str) -> Option<i32> {
+        self.trade_good(trade_symbol).map(|g| g.purchase_price)
+    }
+
+    pub fn sell_price(&self, trade_symbol: &str) -> Option<i32> {
+        self.trade_good(trade_symbol).map(|g| g.sell_price)
+    }
+
+    fn trade_good(&self, trade_symbol: &str) -> Option<&MarketTradeGood> {
+        self.market.trade_goods.as_ref()?.iter().find(|g| g.symbol == trade_symbol)
+    }
+
+    /// How long ago the snapshot was taken, e.g. "3h 12m"
+    pub fn age(&self) -> String {
+        let fetched_at = match DateTime::parse_from_rfc3339(&self.fetched_at) {
+            Ok(fetched_at) => fetched_at.with_timezone(&Utc),
+            Err(_) => return "an unknown time".to_string()
+        };
+        let minutes = (Utc::now() - fetched_at).num_minutes().max(0);
+        match minutes {
+            0 => "less than a minute".to_string(),
+            m if m < 60 => format!("{}m", m),
+            m if m < 24 * 60 => format!("{}h {}m", m / 60, m % 60),
+            m => format!("{}d {}h", m / (24 * 60), m / 60 % 24)
+        }
+    }
+}
+
+/// Last seen state of the universe, so automation doesn't have to re-query
+/// waypoints and markets it has already visited.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UniverseCache {
+    // Waypoints keyed by system symbol
+    pub waypoints: HashMap<String, Vec<Waypoint>>,
+    // Market snapshots keyed by waypoint symbol
+    pub markets: HashMap<String, MarketSnapshot>,
+    // Shipyard snapshots keyed by waypoint symbol
+    #[serde(default)]
+    pub shipyards: HashMap<String, ShipyardSnapshot>,
+    // Systems found by ship scans keyed by system symbol
+    #[serde(default)]
+    pub systems: HashMap<String, ScannedSystem>,
+    // Systems listed by the API, with their factions, keyed by system symbol
+    #[serde(default)]
+    pub galaxy: HashMap<String, System>,
+    // Whole pages of the system list synced so far, since single lookups also land in `galaxy`
+    #[serde(default)]
+    pub galaxy_pages: i32,
+    // Jump gate connections keyed by the gate's waypoint symbol
+    #[serde(default)]
+    pub jump_gates: HashMap<String, JumpGate>,
+}
+
+impl UniverseCache {
+    pub fn load() -> UniverseCache {
+        let path = Path::new(CACHE_FILE);
+
+        if !path.exists() {
+            return UniverseCache::default();
+        }
+
+        match File::open(path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file)).unwrap_or_default(),
+            Err(_) => UniverseCache::default()
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = std::fs::write(CACHE_FILE, serde_json::to_string(self).unwrap()) {
+            println!("Failed to save universe cache {}", e);
+        }
+    }
+
+    pub fn system_waypoints(&mut self, api_config: &Configuration, system: &str) -> Result<Vec<Waypoint>, String> {
+        if let Some(waypoints) = self.waypoints.get(system) {
+            return Ok(waypoints.clone());
+        }
+
+        let waypoints = fetch_system_waypoints(api_config, system)?;
+        self.waypoints.insert(system.to_string(), waypoints.clone());
+        self.save();
+
+        Ok(waypoints)
+    }
+
+    pub fn waypoint(&mut self, api_config: &Configuration, waypoint: &str) -> Result<Waypoint, String> {
+        let system = system_symbol(waypoint);
+
+        self.system_waypoints(api_config, &system)?
+            .into_iter()
+            .find(|w| w.symbol == waypoint)
+            .ok_or(format!("Unknown waypoint {}", waypoint))
+    }
+
+    /// Replace a waypoint's cached entry, e.g. after charting it. Only systems we already hold a
+    /// full waypoint list for are updated, so a partial list never masks the rest of a system.
+    pub fn record_waypoint(&mut self, waypoint: Waypoint) {
+        if let Some(waypoints) = self.waypoints.get_mut(&waypoint.system_symbol) {
+            match waypoints.iter_mut().find(|w| w.symbol == waypoint.symbol) {
+                Some(existing) => *existing = waypoint,
+                None => waypoints.push(waypoint)
+            }
+            self.save();
+        }
+    }
+
+    pub fn record_scanned_waypoints(&mut self, scanned: Vec<ScannedWaypoint>) {
+        for w in scanned {
+            let mut waypoint = Waypoint::new(w.symbol, w.r#type, w.system_symbol, w.x, w.y, w.orbitals, w.traits);
+            waypoint.faction = w.faction;
+            waypoint.chart = w.chart;
+            self.record_waypoint(waypoint);
+        }
+    }
+
+    pub fn record_scanned_systems(&mut self, scanned: Vec<ScannedSystem>) {
+        for system in scanned {
+            self.systems.insert(system.symbol.clone(), system);
+        }
+        self.save();
+    }
+
+    /// Page through the galaxy's systems, up to `pages` pages of 20, returning how many are now cached.
+    /// The full galaxy runs to hundreds of pages, so this picks up where an earlier sync stopped.
+    pub fn sync_galaxy(&mut self, api_config: &Configuration, pages: i32) -> Result<usize, String> {
+        for _ in 0..pages {
+            let page = self.galaxy_pages + 1;
+            match spacetraders_sdk::apis::systems_api::get_systems(api_config, Some(page), Some(PAGE_LIMIT)) {
+                Ok(resp) => {
+                    if !self.record_galaxy_page(page, resp.data, resp.meta.total) {
+                        break;
+                    }
+                },
+                Err(e) => {
+                    self.save();
+                    return Err(format!("Failed to get systems {}", e));
+                }
+            }
+        }
+
+        self.save();
+        Ok(self.galaxy.len())
+    }
+
+    // Cache a page of the system list, returning whether there are more to fetch. Only a full page moves
+    // the sync on, so the last one is fetched again next time in case the galaxy has grown.
+    fn record_galaxy_page(&mut self, page: i32, systems: Vec<System>, total: i32) -> bool {
+        let full = systems.len() as i32 >= PAGE_LIMIT;
+        for system in systems {
+            self.record_system(system);
+        }
+
+        if full {
+            self.galaxy_pages = self.galaxy_pages.max(page);
+        }
+        full && page * PAGE_LIMIT < total
+    }
+
+    fn record_system(&mut self, system: System) {
+        self.galaxy.insert(system.symbol.clone(), system);
+    }
+
+    pub fn system(&mut self, api_config: &Configuration, system: &str) -> Result<System, String> {
+        if let Some(system) = self.galaxy.get(system) {
+            return Ok(system.clone());
+        }
+
+        match spacetraders_sdk::apis::systems_api::get_system(api_config, system) {
+            Ok(resp) => {
+                self.record_system((*resp.data).clone());
+                self.save();
+                Ok(*resp.data)
+            },
+            Err(e) => Err(format!("Failed to get system {} - {}", system, e))
+        }
+    }
+
+    pub fn jump_gate(&mut self, api_config: &Configuration, waypoint: &str) -> Result<JumpGate, String> {
+        if let Some(gate) = self.jump_gates.get(waypoint) {
+            return Ok(gate.clone());
+        }
+
+        match spacetraders_sdk::apis::systems_api::get_jump_gate(api_config, &system_symbol(waypoint), waypoint) {
+            Ok(resp) => {
+                self.jump_gates.insert(waypoint.to_string(), (*resp.data).clone());
+                self.save();
+                Ok(*resp.data)
+            },
+            Err(e) => Err(format!("Failed to get jump gate {} - {}", waypoint, e))
+        }
+    }
+
+    /// Waypoints in a system nobody has charted yet
+    pub fn uncharted_waypoints(&mut self, api_config: &Configuration, system: &str) -> Result<Vec<Waypoint>, String> {
+        Ok(self.system_waypoints(api_config, system)?
+            .into_iter()
+            .filter(|w| w.chart.is_none() || has_trait(w, WaypointTraitSymbol::Uncharted))
+            .collect())
+    }
+
+    pub fn market(&mut self, api_config: &Configuration, waypoint: &str) -> Result<Market, String> {
+        match spacetraders_sdk::apis::systems_api::get_market(api_config, &system_symbol(waypoint), waypoint) {
+            Ok(resp) => {
+                let market = *resp.data;
+                self.record_market(market.clone());
+                Ok(market)
+            },
+            Err(e) => Err(format!("Failed to get market {} - {}", waypoint, e))
+        }
+    }
+
+    pub fn record_market(&mut self, market: Market) {
+        // Prices are only visible while a ship is present, so don't overwrite a priced snapshot with an unpriced one
+        if market.trade_goods.is_none() {
+            if let Some(existing) = self.markets.get(&market.symbol) {
+                if existing.market.trade_goods.is_some() {
+                    return;
+                }
+            }
+        }
+
+        self.markets.insert(market.symbol.clone(), MarketSnapshot { market, fetched_at: Utc::now().to_rfc3339() });
+        self.save();
+    }
+
+    pub fn shipyard(&mut self, api_config: &Configuration, waypoint: &str) -> Result<Shipyard, String> {
+        match spacetraders_sdk::apis::systems_api::get_shipyard(api_config, &system_symbol(waypoint), waypoint) {
+            Ok(resp) => {
+                let shipyard = *resp.data;
+                self.record_shipyard(shipyard.clone());
+                Ok(shipyard)
+            },
+            Err(e) => Err(format!("Failed to get shipyard {} - {}", waypoint, e))
+        }
+    }
+
+    pub fn record_shipyard(&mut self, shipyard: Shipyard) {
+        // Same as markets, ship prices are only listed while one of our ships is there
+        if shipyard.ships.is_none() {
+            if let Some(existing) = self.shipyards.get(&shipyard.symbol) {
+                if existing.shipyard.ships.is_some() {
+                    return;
+                }
+            }
+        }
+
+        self.shipyards.insert(shipyard.symbol.clone(), ShipyardSnapshot { shipyard, fetched_at: Utc::now().to_rfc3339() });
+        self.save();
+    }
+
+    pub fn shipyard_waypoints(&mut self, api_config: &Configuration, system: &str) -> Result<Vec<Waypoint>, String> {
+        Ok(self.system_waypoints(api_config, system)?
+            .into_iter()
+            .filter(|w| has_trait(w, WaypointTraitSymbol::Shipyard))
+            .collect())
+    }
+
+    // Cheapest price we've seen a ship with this frame listed for, in any system
+    pub fn lowest_ship_price(&self, frame: ShipFrameSymbol) -> Option<i32> {
+        self.shipyards.values()
+            .filter_map(|snapshot| snapshot.shipyard.ships.as_ref())
+            .flatten()
+            .filter(|ship| ship.frame.symbol == frame)
+            .map(|ship| ship.purchase_price)
+            .min()
+    }
+
+    /// What the market at a waypoint charged for a good when we last saw it
+    pub fn purchase_price(&self, waypoint: &str, trade_symbol: &str) -> Option<i32> {
+        self.markets.get(waypoint)?.purchase_price(trade_symbol)
+    }
+
+    // Cheapest price we've seen a good sold for in a system, and where
+    pub fn best_purchase_price(&self, system: &str, trade_symbol: &str) -> Option<(i32, String)> {
+        self.markets_in(system)
+            .filter_map(|snapshot| snapshot.purchase_price(trade_symbol).map(|price| (price, snapshot.market.symbol.clone())))
+            .min_by_key(|(price, _)| *price)
+    }
+
+    // Best price we've seen a market pay for a good in a system, and where
+    pub fn best_sell_price(&self, system: &str, trade_symbol: &str) -> Option<(i32, String)> {
+        self.markets_in(system)
+            .filter_map(|snapshot| snapshot.sell_price(trade_symbol).map(|price| (price, snapshot.market.symbol.clone())))
+            .max_by_key(|(price, _)| *price)
+    }
+
+    /// The known market in a system that would pay the most for a whole load, and what it would pay
+    pub fn best_market_for_cargo(&self, system: &str, cargo: &[(String, i32)]) -> Option<(i32, String)> {
+        self.markets_in(system)
+            .map(|snapshot| {
+                let value = cargo.iter()
+                    .filter_map(|(symbol, units)| snapshot.sell_price(symbol).map(|price| price * units))
+                    .sum::<i32>();
+                (value, snapshot.market.symbol.clone())
+            })
+            .filter(|(value, _)| *value > 0)
+            .max_by_key(|(value, _)| *value)
+    }
+
+    fn markets_in<'a>(&'a self, system: &'a str) -> impl Iterator<Item = &'a MarketSnapshot> + 'a {
+        self.markets.values().filter(move |snapshot| system_symbol(&snapshot.market.symbol) == system)
+    }
+
+    pub fn marketplaces(&mut self, api_config: &Configuration, system: &str) -> Result<Vec<Waypoint>, String> {
+        Ok(self.system_waypoints(api_config, system)?
+            .into_iter()
+            .filter(|w| has_trait(w, WaypointTraitSymbol::Marketplace))
+            .collect())
+    }
+}
+
+pub fn fetch_system_waypoints(api_config: &Configuration, system: &str) -> Result<Vec<Waypoint>, String> {
+    let mut waypoints = Vec::new();
+    let mut page = 1;
+
+    loop {
+        match spacetraders_sdk::apis::systems_api::get_system_waypoints(api_config, system, Some(page), Some(PAGE_LIMIT)) {
+            Ok(resp) => {
+                let fetched = resp.data.len();
+                waypoints.extend(resp.data);
+
+                if fetched == 0 || waypoints.len() as i32 >= resp.meta.total {
+                    return Ok(waypoints);
+                }
+                page += 1;
+            },
+            Err(e) => return Err(format!("Failed to get system waypoints {}", e))
+        }
+    }
+}
+
+pub fn has_trait(waypoint: &Waypoint, symbol: WaypointTraitSymbol) -> bool {
+    waypoint.traits.iter().any(|t| t.symbol == symbol)
+}
+
+// Waypoint symbols are `SECTOR-SYSTEM-WAYPOINT`
+pub fn system_symbol(waypoint: &str) -> String {
+    waypoint.rsplit_once('-').map(|(system, _)| system).unwrap_or(waypoint).to_string()
+}
+
+pub fn distance(a: (i32, i32), b: (i32, i32)) -> f64 {
+    let dx = (a.0 - b.0) as f64;
+    let dy = (a.1 - b.1) as f64;
+    (dx * dx + dy * dy).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use spacetraders_sdk::models::market_trade_good::Supply;
+    use spacetraders_sdk::models::SystemType;
+
+    use super::*;
+
+    fn snapshot(symbol: &str, goods: &[(&str, i32, i32)], fetched_at: String) -> MarketSnapshot {
+        let mut market = Market::new(symbol.to_string(), Vec::new(), Vec::new(), Vec::new());
+        market.trade_goods = Some(goods.iter()
+            .map(|(good, buy, sell)| MarketTradeGood::new(good.to_string(), 100, Supply::Moderate, *buy, *sell))
+            .collect());
+        MarketSnapshot { market, fetched_at }
+    }
+
+    fn taken(ago: Duration) -> MarketSnapshot {
+        snapshot("X1-AB-A1", &[], (Utc::now() - ago).to_rfc3339())
+    }
+
+    #[test]
+    fn snapshot_age_reads_as_words() {
+        assert_eq!(taken(Duration::seconds(20)).age(), "less than a minute");
+        assert_eq!(taken(Duration::seconds(5 * 60 + 30)).age(), "5m");
+        assert_eq!(taken(Duration::minutes(3 * 60 + 12)).age(), "3h 12m");
+        assert_eq!(taken(Duration::hours(2 * 24 + 5)).age(), "2d 5h");
+        assert_eq!(snapshot("X1-AB-A1", &[], "yesterday".to_string()).age(), "an unknown time");
+    }
+
+    #[test]
+    fn prices_come_from_the_systems_markets() {
+        let mut cache = UniverseCache::default();
+        for market in [
+            snapshot("X1-AB-A1", &[("FUEL", 70, 60), ("IRON_ORE", 20, 15)], Utc::now().to_rfc3339()),
+            snapshot("X1-AB-B2", &[("FUEL", 80, 75)], Utc::now().to_rfc3339()),
+            snapshot("X1-CD-A1", &[("FUEL", 10, 200)], Utc::now().to_rfc3339()),
+        ] {
+            cache.markets.insert(market.market.symbol.clone(), market);
+        }
+
+        assert_eq!(cache.purchase_price("X1-AB-B2", "FUEL"), Some(80));
+        assert_eq!(cache.purchase_price("X1-AB-B2", "IRON_ORE"), None);
+        assert_eq!(cache.best_purchase_price("X1-AB", "FUEL"), Some((70, "X1-AB-A1".to_string())));
+        assert_eq!(cache.best_sell_price("X1-AB", "FUEL"), Some((75, "X1-AB-B2".to_string())));
+        assert_eq!(cache.best_sell_price("X1-EF", "FUEL"), None);
+
+        // 10 fuel and 20 ore fetch 900 at A1 but only 750 at B2, which doesn't buy ore
+        let cargo = [("FUEL".to_string(), 10), ("IRON_ORE".to_string(), 20)];
+        assert_eq!(cache.best_market_for_cargo("X1-AB", &cargo), Some((900, "X1-AB-A1".to_string())));
+    }
+
+    fn systems(from: usize, count: usize) -> Vec<System> {
+        (from..from + count)
+            .map(|i| System::new(format!("X1-S{}", i), "X1".to_string(), SystemType::RedStar, 0, 0, Vec::new(), Vec::new()))
+            .collect()
+    }
+
+    #[test]
+    fn galaxy_sync_resumes_after_the_last_whole_page() {
+        let mut cache = UniverseCache::default();
+        assert!(cache.record_galaxy_page(1, systems(0, 20), 45));
+        assert_eq!(cache.galaxy_pages, 1);
+
+        // Systems looked up one at a time don't count towards the pages synced
+        for system in systems(100, 25) {
+            cache.record_system(system);
+        }
+        assert_eq!(cache.galaxy.len(), 45);
+        assert_eq!(cache.galaxy_pages, 1);
+
+        assert!(!cache.record_galaxy_page(3, systems(40, 5), 45));
+        assert_eq!(cache.galaxy_pages, 1);
+        assert!(cache.record_galaxy_page(2, systems(20, 20), 45));
+        assert_eq!(cache.galaxy_pages, 2);
+        assert!(!cache.record_galaxy_page(3, systems(40, 20), 60));
+        assert_eq!(cache.galaxy_pages, 3);
+    }
+
+    #[test]
+    fn waypoints_belong_to_their_system() {
+        assert_eq!(system_symbol("X1-AB12-C34"), "X1-AB12");
+        assert_eq!(system_symbol("X1"), "X1");
+        assert_eq!(distance((0, 0), (3, -4)), 5.0);
+    }
+}
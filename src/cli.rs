@@ -1,9 +1,181 @@
-use clap::Parser;
+use std::collections::HashMap;
 
+use clap::{Arg, ArgMatches, CommandFactory, Parser};
+
+use crate::commands::CommandSpec;
+use crate::config::ConfigOverrides;
+use crate::output::Format;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about=None)]
 pub struct Args {
-    #[arg(short, long, default_value = "spacetraders.json")]
-    pub config_file: String
-}
\ No newline at end of file
+    /// Config file to load and save, falls back to $SPACETRADERS_CONFIG_FILE then spacetraders.json
+    #[arg(short, long, global = true)]
+    pub config_file: Option<String>,
+
+    /// API server to use instead of the configured one
+    #[arg(long, global = true)]
+    pub base_path: Option<String>,
+
+    /// Seconds before a request gives up, 0 for never
+    #[arg(long, global = true)]
+    pub request_timeout_secs: Option<i32>,
+
+    /// Agent profile to play as
+    #[arg(short, long, global = true)]
+    pub profile: Option<String>,
+
+    /// Print results as a table, json, ndjson or csv
+    #[arg(short, long, global = true)]
+    pub output: Option<Format>,
+
+    /// Re-run a read command every given number of seconds, highlighting what changed
+    #[arg(short, long, global = true, value_name = "SECS")]
+    pub watch: Option<u64>,
+}
+
+impl Args {
+    pub fn config_file(&self) -> String {
+        self.config_file.clone()
+            .or_else(|| std::env::var("SPACETRADERS_CONFIG_FILE").ok().filter(|f| !f.is_empty()))
+            .unwrap_or_else(|| "spacetraders.json".to_string())
+    }
+
+    pub fn overrides(&self) -> ConfigOverrides {
+        ConfigOverrides {
+            base_path: self.base_path.clone(),
+            // Tokens only come from SPACETRADERS_ACCESS_TOKEN, never the command line
+            access_token: None,
+            request_timeout_secs: self.request_timeout_secs,
+            profile: self.profile.clone(),
+        }
+    }
+}
+
+/// The command line parser, with a subcommand for each command that isn't REPL only.
+/// Commands sharing the first word of their path, like `contract accept`, are grouped under it.
+pub fn command(specs: &[CommandSpec]) -> clap::Command {
+    let mut groups: Vec<(&str, Vec<&CommandSpec>)> = Vec::new();
+
+    for spec in specs.iter().filter(|s| !s.path.is_empty()) {
+        match groups.iter_mut().find(|(group, _)| *group == spec.path[0]) {
+            Some((_, members)) => members.push(spec),
+            None => groups.push((spec.path[0], vec![spec]))
+        }
+    }
+
+    let mut cli = Args::command()
+        .after_help("Starts the REPL when no command is given.\n\n\
+                     To play with a token that isn't in the config, set SPACETRADERS_ACCESS_TOKEN. There's no flag for it,\n\
+                     as other users and your shell history could see it on the command line.");
+
+    // Keep the REPL names of grouped commands working too, e.g. `factions` as well as `faction list`,
+    // unless one is a group itself, like `faction`
+    for spec in specs.iter().filter(|s| s.path.len() > 1) {
+        if groups.iter().all(|(group, _)| *group != spec.name) {
+            cli = cli.subcommand(subcommand(spec.name, spec).hide(true));
+        }
+    }
+
+    for (group, members) in groups {
+        if members.len() == 1 && members[0].path.len() == 1 {
+            cli = cli.subcommand(subcommand(group, members[0]));
+            continue;
+        }
+
+        let mut parent = clap::Command::new(group)
+            .about(format!("{} commands", group))
+            .subcommand_required(true)
+            .arg_required_else_help(true);
+        for spec in members {
+            parent = parent.subcommand(subcommand(spec.path[spec.path.len() - 1], spec));
+        }
+        cli = cli.subcommand(parent);
+    }
+
+    cli
+}
+
+fn subcommand(name: &'static str, spec: &CommandSpec) -> clap::Command {
+    let mut command = clap::Command::new(name).about(spec.help);
+
+    // Keep the REPL name working too, e.g. `show_ships` as well as `ships`
+    if spec.path.len() == 1 && name != spec.name {
+        command = command.alias(spec.name);
+    }
+
+    for param in &spec.params {
+        let mut arg = Arg::new(param.name).required(param.required);
+        if let Some(default) = param.default {
+            arg = arg.default_value(default);
+        }
+        command = command.arg(arg);
+    }
+
+    command
+}
+
+/// The command given on the command line and its arguments, or `None` to start the REPL
+pub fn subcommand_of<'a>(specs: &'a [CommandSpec], matches: &ArgMatches) -> Option<(&'a CommandSpec, HashMap<String, String>)> {
+    let mut path = Vec::new();
+    let mut matches = matches;
+    while let Some((name, sub)) = matches.subcommand() {
+        path.push(name);
+        matches = sub;
+    }
+
+    let spec = specs.iter().find(|s| !path.is_empty() && (s.path == path || path == [s.name]))?;
+    let given = spec.params.iter()
+        .filter_map(|p| matches.get_one::<String>(p.name).map(|v| (p.name.to_string(), v.clone())))
+        .collect();
+
+    Some((spec, given))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{command, CommandResult, Value};
+    use crate::config::ConfigWrapper;
+
+    fn nothing(_args: HashMap<String, Value>, _context: &mut ConfigWrapper) -> CommandResult {
+        Ok(None)
+    }
+
+    fn specs() -> Vec<CommandSpec> {
+        vec![
+            command!("factions", nothing).with_path("faction list"),
+            command!("faction", nothing).with_path("faction show").required("faction"),
+            command!("show_ships", nothing).with_path("ships"),
+            command!("exit", nothing).repl_only(),
+        ]
+    }
+
+    fn parse(line: &str) -> Option<(&'static str, HashMap<String, String>)> {
+        let specs = specs();
+        let matches = command(&specs).try_get_matches_from(line.split_whitespace()).ok()?;
+        subcommand_of(&specs, &matches).map(|(spec, given)| (spec.name, given))
+    }
+
+    #[test]
+    fn grouped_commands_are_found_by_path() {
+        assert_eq!(parse("spacetraders faction list").unwrap().0, "factions");
+
+        let (name, given) = parse("spacetraders faction show cosmic").unwrap();
+        assert_eq!(name, "faction");
+        assert_eq!(given.get("faction").map(|f| f.as_str()), Some("cosmic"));
+    }
+
+    #[test]
+    fn repl_names_work_on_the_command_line() {
+        assert_eq!(parse("spacetraders factions").unwrap().0, "factions");
+        assert_eq!(parse("spacetraders show_ships").unwrap().0, "show_ships");
+        assert_eq!(parse("spacetraders ships").unwrap().0, "show_ships");
+    }
+
+    #[test]
+    fn repl_only_commands_are_left_out() {
+        assert!(parse("spacetraders exit").is_none());
+        assert!(parse("spacetraders").is_none());
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::config::ConfigWrapper;
+
+/// One argument as it was typed, converted to whatever the handler needs.
+#[derive(Clone, Debug)]
+pub struct Value {
+    value: String,
+}
+
+impl Value {
+    pub fn new(value: &str) -> Value {
+        Value { value: value.to_string() }
+    }
+
+    pub fn convert<T>(&self) -> Result<T, CommandError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.value.parse::<T>().map_err(|e| CommandError(format!("Invalid value {} - {}", self.value, e)))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+/// A command that failed. Printed to stderr, and makes the non-interactive CLI exit non-zero.
+#[derive(Debug)]
+pub struct CommandError(pub String);
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(e: String) -> Self {
+        CommandError(e)
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(e: &str) -> Self {
+        CommandError(e.to_string())
+    }
+}
+
+pub type CommandResult = Result<Option<String>, CommandError>;
+
+pub type Handler = fn(HashMap<String, Value>, &mut ConfigWrapper) -> CommandResult;
+
+/// Build a `CommandSpec` for a handler
+macro_rules! command {
+    ($name: expr, $handler: ident) => {
+        $crate::commands::CommandSpec::new($name, $handler)
+    };
+}
+
+pub(crate) use command;
+
+#[derive(Clone, Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub required: bool,
+    pub default: Option<&'static str>,
+    // Never written to the REPL history, e.g. a token
+    pub secret: bool,
+}
+
+/// A command, described once and registered with both the REPL and the non-interactive CLI.
+pub struct CommandSpec {
+    pub name: &'static str,
+    // Subcommand path on the command line, e.g. `contract accept`. Empty for REPL only commands
+    pub path: Vec<&'static str>,
+    pub help: &'static str,
+    pub params: Vec<Param>,
+    pub handler: Handler,
+    // Only shows something, so it's safe to repeat under `watch`
+    pub read_only: bool,
+}
+
+impl CommandSpec {
+    pub fn new(name: &'static str, handler: Handler) -> CommandSpec {
+        CommandSpec { name, path: vec![name], help: "", params: Vec::new(), handler, read_only: false }
+    }
+
+    pub fn with_help(mut self, help: &'static str) -> CommandSpec {
+        self.help = help;
+        self
+    }
+
+    /// Where the command lives on the command line, e.g. `"contract accept"`. Defaults to its REPL name
+    pub fn with_path(mut self, path: &'static str) -> CommandSpec {
+        self.path = path.split_whitespace().collect();
+        self
+    }
+
+    pub fn repl_only(mut self) -> CommandSpec {
+        self.path.clear();
+        self
+    }
+
+    /// Mark a command that makes no changes, so it can be watched
+    pub fn read_only(mut self) -> CommandSpec {
+        self.read_only = true;
+        self
+    }
+
+    pub fn required(mut self, name: &'static str) -> CommandSpec {
+        self.params.push(Param { name, required: true, default: None, secret: false });
+        self
+    }
+
+    pub fn optional(mut self, name: &'static str) -> CommandSpec {
+        self.params.push(Param { name, required: false, default: None, secret: false });
+        self
+    }
+
+    pub fn with_default(mut self, name: &'static str, default: &'static str) -> CommandSpec {
+        self.params.push(Param { name, required: false, default: Some(default), secret: false });
+        self
+    }
+
+    /// Mark the parameter added last as a secret
+    pub fn secret(mut self) -> CommandSpec {
+        if let Some(param) = self.params.last_mut() {
+            param.secret = true;
+        }
+        self
+    }
+
+    /// Match arguments typed in the REPL to parameters by position
+    pub fn positional(&self, given: &[String]) -> Result<HashMap<String, Value>, CommandError> {
+        if given.len() > self.params.len() {
+            return Err(format!("Error: Command '{}' can have no more than {} arguments", self.name, self.params.len()).into());
+        }
+        if let Some(missing) = self.params.iter().skip(given.len()).find(|p| p.required) {
+            return Err(format!("Error: Missing required argument '{}' for command '{}'", missing.name, self.name).into());
+        }
+
+        Ok(self.arguments(self.params.iter().map(|p| p.name.to_string()).zip(given.iter().cloned()).collect()))
+    }
+
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_string();
+        for param in &self.params {
+            if param.required {
+                usage.push_str(&format!(" {}", param.name));
+            } else {
+                usage.push_str(&format!(" [{}]", param.name));
+            }
+        }
+        usage
+    }
+
+    /// The arguments as typed, with any secret ones blanked out
+    pub fn redact(&self, given: &[String]) -> Vec<String> {
+        given.iter().enumerate()
+            .map(|(i, word)| match self.params.get(i) {
+                Some(param) if param.secret => "<redacted>".to_string(),
+                _ => word.clone()
+            })
+            .collect()
+    }
+
+    /// Fill in defaults for missing arguments, as the REPL does
+    pub fn arguments(&self, given: HashMap<String, String>) -> HashMap<String, Value> {
+        let mut args = HashMap::new();
+
+        for param in &self.params {
+            if let Some(value) = given.get(param.name).map(|v| v.as_str()).or(param.default) {
+                args.insert(param.name.to_string(), Value::new(value));
+            }
+        }
+
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nothing(_args: HashMap<String, Value>, _context: &mut ConfigWrapper) -> CommandResult {
+        Ok(None)
+    }
+
+    fn spec() -> CommandSpec {
+        command!("buy", nothing)
+            .required("ship")
+            .required("good")
+            .with_default("units", "1")
+            .optional("market")
+    }
+
+    fn words(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|w| w.to_string()).collect()
+    }
+
+    fn shown(args: &HashMap<String, Value>, name: &str) -> Option<String> {
+        args.get(name).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn positional_arguments_fill_parameters_in_order() {
+        let args = spec().positional(&words("SHIP-1 FUEL 20 X1-AB12-C34")).unwrap();
+        assert_eq!(shown(&args, "ship").as_deref(), Some("SHIP-1"));
+        assert_eq!(shown(&args, "good").as_deref(), Some("FUEL"));
+        assert_eq!(shown(&args, "units").as_deref(), Some("20"));
+        assert_eq!(shown(&args, "market").as_deref(), Some("X1-AB12-C34"));
+    }
+
+    #[test]
+    fn missing_arguments_take_their_defaults() {
+        let args = spec().positional(&words("SHIP-1 FUEL")).unwrap();
+        assert_eq!(shown(&args, "units").as_deref(), Some("1"));
+        assert_eq!(shown(&args, "market"), None);
+    }
+
+    #[test]
+    fn wrong_argument_counts_are_errors() {
+        assert_eq!(spec().positional(&words("SHIP-1")).unwrap_err().0, "Error: Missing required argument 'good' for command 'buy'");
+        assert_eq!(spec().positional(&words("a b c d e")).unwrap_err().0, "Error: Command 'buy' can have no more than 4 arguments");
+    }
+
+    #[test]
+    fn named_arguments_skip_unknown_names() {
+        let given = HashMap::from([("good".to_string(), "IRON".to_string()), ("colour".to_string(), "red".to_string())]);
+        let args = spec().arguments(given);
+        assert_eq!(args.len(), 2);
+        assert_eq!(shown(&args, "units").as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn usage_brackets_optional_parameters() {
+        assert_eq!(spec().usage(), "buy ship good [units] [market]");
+    }
+
+    #[test]
+    fn values_convert_or_explain() {
+        assert_eq!(Value::new("42").convert::<i32>().unwrap(), 42);
+        assert!(Value::new("lots").convert::<i32>().unwrap_err().0.starts_with("Invalid value lots - "));
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let spec = command!("add_profile", nothing).required("name").required("token").secret().optional("faction");
+        assert_eq!(spec.redact(&words("main abc.def COSMIC")), words("main <redacted> COSMIC"));
+        assert_eq!(spec.redact(&words("main")), words("main"));
+    }
+}
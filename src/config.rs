@@ -1,8 +1,61 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
 use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 use spacetraders_sdk::apis::configuration::Configuration;
+use spacetraders_sdk::models::Survey;
+
+use crate::cache::UniverseCache;
+use crate::ledger::{Ledger, LEDGER_FILE};
+use crate::networth::{NetWorthHistory, NET_WORTH_FILE};
+use crate::output::Format;
+use crate::scheduler::{Schedule, SCHEDULE_FILE};
+use crate::secrets::{self, Backend, TokenStore, REDACTED};
+
+// Bump when the config file layout changes, and teach `migrate` how to upgrade the old one
+pub const CONFIG_VERSION: u64 = 2;
+
+/// Settings layered on top of the config file for this session only, from `SPACETRADERS_*`
+/// environment variables and then command line flags. Saving writes the file's own values back.
+// No Debug on anything holding a token, so one can't end up in a log
+#[derive(Clone, Default)]
+pub struct ConfigOverrides {
+    pub base_path: Option<String>,
+    pub access_token: Option<String>,
+    pub request_timeout_secs: Option<i32>,
+    pub profile: Option<String>,
+}
+
+impl ConfigOverrides {
+    pub fn from_env() -> ConfigOverrides {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+
+        ConfigOverrides {
+            base_path: var("SPACETRADERS_BASE_PATH"),
+            access_token: var("SPACETRADERS_ACCESS_TOKEN"),
+            request_timeout_secs: var("SPACETRADERS_REQUEST_TIMEOUT_SECS").and_then(|v| v.parse().ok()),
+            profile: var("SPACETRADERS_PROFILE"),
+        }
+    }
+
+    // Anything set in `other` wins
+    pub fn merge(self, other: ConfigOverrides) -> ConfigOverrides {
+        ConfigOverrides {
+            base_path: other.base_path.or(self.base_path),
+            access_token: other.access_token.or(self.access_token),
+            request_timeout_secs: other.request_timeout_secs.or(self.request_timeout_secs),
+            profile: other.profile.or(self.profile),
+        }
+    }
+}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct GameConfig {
+    pub version: u64,
+
     // General Settings
     pub request_timeout_secs: i32,
 
@@ -14,63 +67,667 @@ pub struct GameConfig {
 
     // State
     pub headquarters: String,
+
+    // Where tokens are kept: plain (in this file), keyring or encrypted
+    pub token_store: String,
+
+    // Every agent this install manages keyed by profile name. The fields above
+    // are the active agent, and are written back here when switching or saving.
+    pub profiles: BTreeMap<String, AgentProfile>,
+    pub active_profile: String,
+
+    // REPL shortcuts. An alias stands in for the start of a command line, a macro for several commands
+    pub aliases: BTreeMap<String, String>,
+    pub macros: BTreeMap<String, Macro>,
+
+    // Where this config was loaded from and is saved back to
+    #[serde(skip)]
+    pub path: PathBuf,
+    // What was applied on top of the file, and the file's values they replaced
+    #[serde(skip)]
+    overrides: ConfigOverrides,
+    #[serde(skip)]
+    replaced: ConfigOverrides,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct AgentProfile {
+    pub access_token: String,
+    pub call_sign: String,
+    pub faction: String,
+    #[serde(default)]
+    pub headquarters: String,
+}
+
+/// Commands run one after another, with `$param` replaced by the arguments the macro is given
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Macro {
+    pub params: Vec<String>,
+    pub commands: Vec<String>,
 }
 
 impl GameConfig {
     pub fn new() -> GameConfig
     {
         GameConfig{
+            version: CONFIG_VERSION,
             request_timeout_secs: 0,
             base_path: String::new(),
             access_token: String::new(),
             call_sign: String::new(),
             faction: String::new(),
-            headquarters: String::new()
+            headquarters: String::new(),
+            token_store: String::new(),
+            profiles: BTreeMap::new(),
+            active_profile: String::new(),
+            aliases: BTreeMap::new(),
+            macros: BTreeMap::new(),
+            path: PathBuf::new(),
+            overrides: ConfigOverrides::default(),
+            replaced: ConfigOverrides::default()
+        }
+    }
+
+    /// Read a config file over the defaults, upgrading it first if it's from an older version
+    pub fn load(path: &Path) -> Result<GameConfig, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {} - {}", path.display(), e))?;
+        let value: JsonValue = serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {} - {}", path.display(), e))?;
+
+        let (value, from_version) = migrate(value)?;
+
+        let mut config: GameConfig = serde_json::from_value(value)
+            .map_err(|e| format!("Failed to parse {} - {}", path.display(), e))?;
+        config.path = path.to_path_buf();
+
+        if from_version < CONFIG_VERSION {
+            let backup = format!("{}.v{}", path.display(), from_version);
+            std::fs::copy(path, &backup).map_err(|e| format!("Failed to back up {} - {}", path.display(), e))?;
+            println!("Upgraded config from version {} to {}, the old file is at {}", from_version, CONFIG_VERSION, backup);
+            config.save();
+        }
+
+        Ok(config)
+    }
+
+    pub fn apply_overrides(&mut self, overrides: ConfigOverrides) -> Result<(), String> {
+        if let Some(profile) = &overrides.profile {
+            self.replaced.profile = Some(self.active_profile.clone());
+            self.switch_profile(profile)?;
         }
+        if let Some(base_path) = &overrides.base_path {
+            self.replaced.base_path = Some(std::mem::replace(&mut self.base_path, base_path.clone()));
+        }
+        if let Some(access_token) = &overrides.access_token {
+            self.replaced.access_token = Some(std::mem::replace(&mut self.access_token, access_token.clone()));
+        }
+        if let Some(timeout) = overrides.request_timeout_secs {
+            self.replaced.request_timeout_secs = Some(std::mem::replace(&mut self.request_timeout_secs, timeout));
+        }
+
+        self.overrides = overrides;
+        Ok(())
+    }
+
+    /// A default config saved over this one's file, with this session's overrides applied again.
+    /// All but the profile, which the new config doesn't have.
+    pub fn fresh(&self) -> GameConfig {
+        let mut fresh = GameConfig { path: self.path.clone(), ..GameConfig::default() };
+        fresh.save();
+
+        // Only switching profile can fail
+        let _ = fresh.apply_overrides(ConfigOverrides { profile: None, ..self.overrides.clone() });
+        fresh
+    }
+
+    /// Write back to the file we loaded from. Tokens are left out unless they're kept in plain text,
+    /// and overridden settings are saved with the file's values, see `persisted`.
+    pub fn save(&self) {
+        self.write(self.backend() == Backend::Plain)
     }
 
-    pub fn save(new: &GameConfig) {
+    pub fn write(&self, keep_tokens: bool) {
+        if self.path.as_os_str().is_empty() {
+            return;
+        }
+
         println!("Saving config");
-        std::fs::write("../spacetraders.json", serde_json::to_string_pretty(new).unwrap()).unwrap();
+        let mut new = self.persisted();
+
+        if !keep_tokens {
+            new.access_token = String::new();
+            for profile in new.profiles.values_mut() {
+                profile.access_token = String::new();
+            }
+        }
+
+        if let Err(e) = std::fs::write(&new.path, serde_json::to_string_pretty(&new).unwrap()) {
+            println!("Failed to save config to {} - {}", new.path.display(), e);
+        }
+    }
+
+    /// The config as it should be saved. Overridden settings that haven't been changed since
+    /// get the file's values back, so an environment variable never leaks into it.
+    pub fn persisted(&self) -> GameConfig {
+        let mut new = self.clone();
+
+        if new.overrides.access_token.as_ref() == Some(&new.access_token) {
+            new.access_token = new.replaced.access_token.clone().unwrap_or_default();
+        }
+        if new.overrides.base_path.as_ref() == Some(&new.base_path) {
+            new.base_path = new.replaced.base_path.clone().unwrap_or_default();
+        }
+        if new.overrides.request_timeout_secs == Some(new.request_timeout_secs) {
+            new.request_timeout_secs = new.replaced.request_timeout_secs.unwrap_or_default();
+        }
+
+        new.store_active_profile();
+
+        if new.overrides.profile.as_ref() == Some(&new.active_profile) {
+            if let Some(profile) = new.replaced.profile.clone().filter(|p| !p.is_empty()) {
+                let _ = new.switch_profile(&profile);
+            }
+        }
+
+        new
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.token_store.parse().unwrap_or(Backend::Plain)
+    }
+
+    /// Every profile's token as it should be saved, keyed by profile name
+    pub fn tokens(&self) -> BTreeMap<String, String> {
+        self.persisted().profiles.into_iter()
+            .filter(|(_, profile)| !profile.access_token.is_empty())
+            .map(|(name, profile)| (name, profile.access_token))
+            .collect()
+    }
+
+    // Fill in any tokens missing from the file, e.g. from a keyring
+    pub fn fill_tokens(&mut self, tokens: &BTreeMap<String, String>) {
+        for (name, profile) in self.profiles.iter_mut() {
+            if let Some(token) = tokens.get(name).filter(|_| profile.access_token.is_empty()) {
+                profile.access_token = token.clone();
+            }
+        }
+
+        if self.access_token.is_empty() {
+            if let Some(token) = tokens.get(&self.active_profile) {
+                self.access_token = token.clone();
+            }
+        }
+    }
+
+    /// A copy that's safe to print or share, with every token hidden
+    pub fn redacted(&self) -> GameConfig {
+        let mut redacted = self.clone();
+
+        if !redacted.access_token.is_empty() {
+            redacted.access_token = REDACTED.to_string();
+        }
+        for profile in redacted.profiles.values_mut() {
+            if !profile.access_token.is_empty() {
+                profile.access_token = REDACTED.to_string();
+            }
+        }
+
+        redacted
+    }
+
+    fn active(&self) -> AgentProfile {
+        AgentProfile {
+            access_token: self.access_token.clone(),
+            call_sign: self.call_sign.clone(),
+            faction: self.faction.clone(),
+            headquarters: self.headquarters.clone()
+        }
+    }
+
+    /// Copy the active agent into its profile. Configs from before profiles existed get one
+    /// named after the call sign the first time this runs.
+    pub fn store_active_profile(&mut self) {
+        if self.access_token.is_empty() {
+            return;
+        }
+
+        if self.active_profile.is_empty() {
+            self.active_profile = if self.call_sign.is_empty() { "default".to_string() } else { self.call_sign.to_lowercase() };
+        }
+
+        self.profiles.insert(self.active_profile.clone(), self.active());
+    }
+
+    pub fn add_profile(&mut self, name: &str, profile: AgentProfile) -> Result<(), String> {
+        self.store_active_profile();
+
+        if self.profiles.contains_key(name) {
+            return Err(format!("A profile named {} already exists", name));
+        }
+
+        self.profiles.insert(name.to_string(), profile);
+        Ok(())
+    }
+
+    pub fn switch_profile(&mut self, name: &str) -> Result<(), String> {
+        self.store_active_profile();
+
+        let profile = self.profiles.get(name).cloned().ok_or(format!("No profile named {}", name))?;
+
+        self.access_token = profile.access_token;
+        self.call_sign = profile.call_sign;
+        self.faction = profile.faction;
+        self.headquarters = profile.headquarters;
+        self.active_profile = name.to_string();
+
+        Ok(())
+    }
+
+    pub fn remove_profile(&mut self, name: &str) -> Result<(), String> {
+        self.store_active_profile();
+
+        if name == self.active_profile {
+            return Err(format!("{} is the active profile, switch to another before removing it", name));
+        }
+
+        self.profiles.remove(name).map(|_| ()).ok_or(format!("No profile named {}", name))
     }
 }
 
 impl Default for GameConfig {
     fn default() -> Self {
         GameConfig {
+            version: CONFIG_VERSION,
             request_timeout_secs: 5,
             base_path: "https://api.spacetraders.io/v2".to_owned(),
             access_token: String::new(),
             call_sign: String::new(),
             faction: String::new(),
-            headquarters: String::new()
+            headquarters: String::new(),
+            token_store: String::new(),
+            profiles: BTreeMap::new(),
+            active_profile: String::new(),
+            aliases: BTreeMap::new(),
+            macros: BTreeMap::new(),
+            path: PathBuf::new(),
+            overrides: ConfigOverrides::default(),
+            replaced: ConfigOverrides::default()
+        }
+    }
+}
+
+/// Where a per-agent file like the ledger lives for a profile, e.g. spacetraders_ledger.main.json.
+/// Without a profile, i.e. until there's an agent to name one after, it's the file itself.
+pub fn profile_file(file: &str, profile: &str) -> PathBuf {
+    if profile.is_empty() {
+        return PathBuf::from(file);
+    }
+
+    let profile: String = profile.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    PathBuf::from(format!("{}.{}.json", file.trim_end_matches(".json"), profile))
+}
+
+/// Hand a file from before these were kept per profile to a profile that doesn't have its own yet,
+/// returning where it went if it was moved.
+pub fn adopt_legacy_file(file: &str, profile: &str) -> Result<Option<PathBuf>, String> {
+    let path = profile_file(file, profile);
+    if profile.is_empty() || path.exists() || !Path::new(file).exists() {
+        return Ok(None);
+    }
+
+    match std::fs::rename(file, &path) {
+        Ok(()) => Ok(Some(path)),
+        Err(e) => Err(format!("Failed to move {} to {} - {}", file, path.display(), e))
+    }
+}
+
+/// Upgrade a config file's JSON to the current layout, returning the version it started at.
+/// Files from before versioning count as version 1.
+fn migrate(mut value: JsonValue) -> Result<(JsonValue, u64), String> {
+    let from_version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(1);
+
+    if from_version > CONFIG_VERSION {
+        return Err(format!("Config is version {} but this build only understands up to {}", from_version, CONFIG_VERSION));
+    }
+
+    let config = value.as_object_mut().ok_or("Config must be a JSON object".to_string())?;
+
+    if from_version < 2 {
+        // Version 1 configs could hold a zero timeout, which was never applied. Now that it is
+        // zero would mean waiting forever, so let those fall back to the default
+        if config.get("request_timeout_secs").and_then(|t| t.as_i64()).unwrap_or(0) <= 0 {
+            config.remove("request_timeout_secs");
         }
     }
+
+    config.insert("version".to_string(), JsonValue::from(CONFIG_VERSION));
+
+    Ok((value, from_version))
 }
 
 #[derive(Clone)]
 pub struct ConfigWrapper {
     pub user_config: GameConfig,
-    pub api_config: Configuration
+    pub api_config: Configuration,
+    pub cache: UniverseCache,
+    pub schedule: Schedule,
+    pub ledger: Ledger,
+    pub net_worth: NetWorthHistory,
+    // How commands print their results, for this session only
+    pub output: Format,
+    // Where tokens live when they aren't in the config file
+    pub token_store: Option<Rc<dyn TokenStore>>,
+    // Surveys made this session, for extract to target
+    pub surveys: Vec<Survey>
 }
 
 impl ConfigWrapper {
     pub fn new(game_config: GameConfig) -> ConfigWrapper {
         let mut config_wrapper = ConfigWrapper {
             user_config: game_config,
-            api_config: Configuration::new()
+            api_config: Configuration::new(),
+            cache: UniverseCache::load(),
+            schedule: Schedule::default(),
+            ledger: Ledger::default(),
+            net_worth: NetWorthHistory::default(),
+            output: Format::Table,
+            token_store: None,
+            surveys: Vec::new()
         };
 
-        config_wrapper.api_config.base_path = config_wrapper.user_config.base_path.clone();
-        config_wrapper.api_config.bearer_access_token = Some(config_wrapper.user_config.access_token.clone());
+        config_wrapper.load_tokens();
+        config_wrapper.user_config.store_active_profile();
+        if let Err(e) = config_wrapper.load_agent_state() {
+            println!("{}", e);
+        }
+        config_wrapper.update();
 
         config_wrapper
     }
 
-    pub fn update(mut self) {
+    // The system our headquarters is in, looked up from the agent the first time it's needed
+    pub fn home_system(&mut self) -> Result<String, String> {
+        if self.user_config.headquarters.is_empty() {
+            match spacetraders_sdk::apis::agents_api::get_my_agent(&self.api_config) {
+                Ok(resp) => self.user_config.headquarters = resp.data.headquarters,
+                Err(e) => return Err(format!("Failed to get agent data {}", e))
+            }
+        }
+
+        Ok(crate::cache::system_symbol(&self.user_config.headquarters))
+    }
+
+    // Point the API client at the configured server as the active agent
+    pub fn update(&mut self) {
+        self.api_config.base_path = self.user_config.base_path.clone();
+        self.api_config.bearer_access_token = Some(self.user_config.access_token.clone());
+
+        let timeout = self.user_config.request_timeout_secs;
+        self.api_config.client.set_timeout(if timeout > 0 { Some(std::time::Duration::from_secs(timeout as u64)) } else { None });
+    }
+
+    /// Play with a different config from now on, e.g. after starting a new one
+    pub fn replace_config(&mut self, game_config: GameConfig) -> Result<(), String> {
+        self.user_config = game_config;
+        self.load_tokens();
+        self.user_config.store_active_profile();
+        let loaded = self.load_agent_state();
+        self.update();
+        loaded
+    }
+
+    pub fn switch_profile(&mut self, name: &str) -> Result<(), String> {
+        self.user_config.switch_profile(name)?;
+        let loaded = self.load_agent_state();
+        self.update();
+        loaded
+    }
+
+    // Each agent has its own task queues, ledger and net worth history. The universe cache is
+    // shared, as every agent sees the same waypoints and markets.
+    // Files from before that are moved to the profile first, and if one can't be the profile
+    // starts without it rather than not at all.
+    fn load_agent_state(&mut self) -> Result<(), String> {
+        let profile = self.user_config.active_profile.clone();
+        let mut errors = Vec::new();
+
+        for file in [SCHEDULE_FILE, LEDGER_FILE, NET_WORTH_FILE] {
+            match adopt_legacy_file(file, &profile) {
+                Ok(Some(path)) => println!("Moved {} to {}, it now belongs to profile {}", file, path.display(), profile),
+                Ok(None) => (),
+                Err(e) => errors.push(e)
+            }
+        }
+
+        self.schedule = Schedule::load(&profile);
+        self.ledger = Ledger::load(&profile);
+        self.net_worth = NetWorthHistory::load(&profile);
+        self.surveys.clear();
+
+        if errors.is_empty() { Ok(()) } else { Err(errors.join("\n")) }
+    }
+
+    fn load_tokens(&mut self) {
+        self.token_store = secrets::token_store(self.user_config.backend(), &self.user_config.path).map(Rc::from);
+
+        if let Some(store) = &self.token_store {
+            let mut names: Vec<String> = self.user_config.profiles.keys().cloned().collect();
+            names.push(self.user_config.active_profile.clone());
+
+            match store.load(&names) {
+                Ok(tokens) => self.user_config.fill_tokens(&tokens),
+                Err(e) => println!("{}, using any tokens in the config file", e)
+            }
+        }
+    }
+
+    /// Save the config, putting tokens in the token store. If that fails they stay in the file.
+    pub fn save(&self) {
+        match &self.token_store {
+            Some(store) => match store.store(&self.user_config.tokens()) {
+                Ok(()) => self.user_config.write(false),
+                Err(e) => {
+                    println!("{}, keeping tokens in the config file", e);
+                    self.user_config.write(true)
+                }
+            },
+            None => self.user_config.write(true)
+        }
+    }
+
+    /// Move every token to another store, and remove them from the one they were in
+    pub fn set_token_store(&mut self, backend: Backend) -> Result<(), String> {
+        if self.user_config.backend() == backend {
+            return Ok(());
+        }
+
+        self.user_config.token_store = backend.to_string();
+        let old = std::mem::replace(&mut self.token_store, secrets::token_store(backend, &self.user_config.path).map(Rc::from));
+        self.save();
+
+        match old {
+            Some(old) => old.delete(&self.user_config.tokens().into_keys().collect::<Vec<String>>()),
+            None => Ok(())
+        }
+    }
+
+    /// Remove a profile, along with its token if that's kept outside the config file
+    pub fn remove_profile(&mut self, name: &str) -> Result<(), String> {
+        self.user_config.remove_profile(name)?;
+        match &self.token_store {
+            Some(store) => store.delete(&[name.to_string()]),
+            None => Ok(())
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        self.api_config.base_path = self.user_config.base_path;
-        self.api_config.bearer_access_token = Option::from(self.user_config.access_token);
+    // A directory of its own per test, so files in one can't be adopted by another
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spacetraders-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn profile_files_are_named_after_the_profile() {
+        assert_eq!(profile_file("spacetraders_ledger.json", "main"), PathBuf::from("spacetraders_ledger.main.json"));
+        assert_eq!(profile_file("spacetraders_ledger.json", "alt 2/x"), PathBuf::from("spacetraders_ledger.alt_2_x.json"));
+        assert_eq!(profile_file("spacetraders_ledger.json", ""), PathBuf::from("spacetraders_ledger.json"));
+    }
+
+    #[test]
+    fn legacy_file_moves_to_the_profile_once() {
+        let dir = scratch("adopt");
+        let legacy = dir.join("ledger.json");
+        let legacy = legacy.to_str().unwrap();
+        std::fs::write(legacy, "old").unwrap();
+
+        let moved = adopt_legacy_file(legacy, "main").unwrap();
+        assert_eq!(moved, Some(dir.join("ledger.main.json")));
+        assert!(!Path::new(legacy).exists());
+        assert_eq!(std::fs::read_to_string(dir.join("ledger.main.json")).unwrap(), "old");
+
+        // Nothing left to move, and another profile doesn't get it either
+        assert_eq!(adopt_legacy_file(legacy, "main").unwrap(), None);
+        assert_eq!(adopt_legacy_file(legacy, "alt").unwrap(), None);
+        assert!(!dir.join("ledger.alt.json").exists());
 
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn legacy_file_leaves_a_profiles_own_file_alone() {
+        let dir = scratch("keep");
+        let legacy = dir.join("ledger.json");
+        let legacy = legacy.to_str().unwrap();
+        std::fs::write(legacy, "old").unwrap();
+        std::fs::write(dir.join("ledger.main.json"), "new").unwrap();
+
+        assert_eq!(adopt_legacy_file(legacy, "main").unwrap(), None);
+        assert_eq!(std::fs::read_to_string(dir.join("ledger.main.json")).unwrap(), "new");
+        assert!(Path::new(legacy).exists());
+
+        // Without a profile the legacy file is the one in use
+        assert_eq!(adopt_legacy_file(legacy, "").unwrap(), None);
+        assert!(Path::new(legacy).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // A config as it'd be read from a file, with two agents
+    fn from_file(dir: &Path) -> GameConfig {
+        let mut config = GameConfig {
+            path: dir.join("config.json"),
+            base_path: "https://file.example/v2".to_string(),
+            request_timeout_secs: 10,
+            access_token: "main-token".to_string(),
+            call_sign: "MAIN".to_string(),
+            active_profile: "main".to_string(),
+            ..GameConfig::default()
+        };
+        config.profiles.insert("alt".to_string(), AgentProfile { access_token: "alt-token".to_string(), call_sign: "ALT".to_string(), ..AgentProfile::default() });
+        config.store_active_profile();
+        config
+    }
+
+    #[test]
+    fn flags_beat_the_environment_which_beats_the_file() {
+        std::env::set_var("SPACETRADERS_BASE_PATH", "https://env.example/v2");
+        std::env::set_var("SPACETRADERS_REQUEST_TIMEOUT_SECS", "20");
+        let env = ConfigOverrides::from_env();
+        std::env::remove_var("SPACETRADERS_BASE_PATH");
+        std::env::remove_var("SPACETRADERS_REQUEST_TIMEOUT_SECS");
+
+        let flags = ConfigOverrides { request_timeout_secs: Some(30), profile: Some("alt".to_string()), ..ConfigOverrides::default() };
+        let merged = env.merge(flags);
+        assert_eq!(merged.base_path.as_deref(), Some("https://env.example/v2"));
+        assert_eq!(merged.request_timeout_secs, Some(30));
+
+        let dir = scratch("precedence");
+        let mut config = from_file(&dir);
+        config.apply_overrides(merged).unwrap();
+        assert_eq!(config.base_path, "https://env.example/v2");
+        assert_eq!(config.request_timeout_secs, 30);
+        assert_eq!(config.active_profile, "alt");
+        // Nothing overrode the token, so it's the profile's from the file
+        assert_eq!(config.access_token, "alt-token");
+        assert_eq!(config.call_sign, "ALT");
+
+        // Defaults sit under everything, and a fresh config keeps all but the profile
+        let fresh = config.fresh();
+        assert_eq!(fresh.base_path, "https://env.example/v2");
+        assert_eq!(fresh.request_timeout_secs, 30);
+        assert_eq!(fresh.active_profile, "");
+        assert_eq!(fresh.faction, GameConfig::default().faction);
+        assert_eq!(fresh.persisted().base_path, GameConfig::default().base_path);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn overrides_are_not_saved_to_the_file() {
+        let dir = scratch("persisted");
+        let mut config = from_file(&dir);
+        config.apply_overrides(ConfigOverrides {
+            base_path: Some("https://env.example/v2".to_string()),
+            access_token: Some("env-token".to_string()),
+            request_timeout_secs: Some(30),
+            profile: Some("alt".to_string()),
+        }).unwrap();
+
+        let saved = config.persisted();
+        assert_eq!(saved.base_path, "https://file.example/v2");
+        assert_eq!(saved.request_timeout_secs, 10);
+        assert_eq!(saved.active_profile, "main");
+        assert_eq!(saved.access_token, "main-token");
+        assert_eq!(saved.profiles["alt"].access_token, "alt-token");
+
+        // A setting changed during the session is the user's, so it's kept
+        config.request_timeout_secs = 15;
+        assert_eq!(config.persisted().request_timeout_secs, 15);
+
+        config.save();
+        let reloaded = GameConfig::load(&config.path).unwrap();
+        assert_eq!(reloaded.base_path, "https://file.example/v2");
+        assert_eq!(reloaded.access_token, "main-token");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unversioned_configs_are_upgraded_from_version_1() {
+        let (value, from) = migrate(serde_json::json!({ "request_timeout_secs": 0, "call_sign": "MAIN" })).unwrap();
+        assert_eq!(from, 1);
+        assert_eq!(value["version"], CONFIG_VERSION);
+        assert!(value.get("request_timeout_secs").is_none());
+        assert_eq!(value["call_sign"], "MAIN");
+
+        // A timeout that was set is kept
+        let (value, _) = migrate(serde_json::json!({ "request_timeout_secs": 7 })).unwrap();
+        assert_eq!(value["request_timeout_secs"], 7);
+
+        let dir = scratch("migrate");
+        let path = dir.join("config.json");
+        std::fs::write(&path, r#"{ "request_timeout_secs": 0 }"#).unwrap();
+        let config = GameConfig::load(&path).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.request_timeout_secs, GameConfig::default().request_timeout_secs);
+        assert!(dir.join("config.json.v1").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn configs_from_a_newer_build_are_refused() {
+        let error = migrate(serde_json::json!({ "version": CONFIG_VERSION + 1 })).err().unwrap();
+        assert!(error.contains("only understands up to"), "{}", error);
+
+        let (_, from) = migrate(serde_json::json!({ "version": CONFIG_VERSION })).unwrap();
+        assert_eq!(from, CONFIG_VERSION);
+    }
+}
@@ -0,0 +1,731 @@
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use spacetraders_sdk::apis::{contracts_api, fleet_api};
+use spacetraders_sdk::models::contract::RHashType as ContractType;
+use spacetraders_sdk::models::{Contract, ContractDeliverGood, Cooldown, DeliverContractRequest, ExtractResourcesRequest,
+                               JettisonRequest, NavigateShipRequest, PurchaseCargoRequest, SellCargoRequest, Ship,
+                               ShipNavFlightMode, ShipNavStatus, WaypointType};
+use spacetraders_sdk::apis::configuration::Configuration;
+use spacetraders_sdk::models::ship_mount::Symbol as ShipMountSymbol;
+use spacetraders_sdk::models::waypoint_trait::Symbol as WaypointTraitSymbol;
+use spacetraders_sdk::wait::{self, ClockSkew};
+
+use crate::cache::{self, MarketSnapshot, UniverseCache};
+use crate::config::ConfigWrapper;
+use crate::navigation::{self, FUEL_UNITS_PER_MARKET_UNIT};
+
+// Used when no market in the system has been seen selling fuel
+const DEFAULT_FUEL_PRICE: i32 = 100;
+
+// Rough figures for a starting mining drone with a single Mining Laser I, which are only used to
+// estimate how long a good with no market takes to mine: each extraction brings up about 7 units
+// and is followed by a cooldown of about 70 seconds. Fields yield a mix of goods, so the estimate
+// is on the quick side, and better lasers or more miners beat it.
+const UNITS_PER_EXTRACTION: i32 = 7;
+const EXTRACTION_COOLDOWN_SECONDS: i64 = 70;
+
+/// Drives a single ship through an accepted procurement contract: sources the goods,
+/// hauls them to the destination in as many trips as needed and fulfils the contract.
+pub struct ContractRunner<'a> {
+    context: &'a mut ConfigWrapper,
+    ship: Ship,
+    contract: Contract,
+    deadline: DateTime<Utc>,
+    skew: ClockSkew,
+}
+
+impl<'a> ContractRunner<'a> {
+    pub fn new(context: &'a mut ConfigWrapper, contract_id: &str, ship_symbol: &str) -> Result<ContractRunner<'a>, String> {
+        let contract = match contracts_api::get_contract(&context.api_config, contract_id) {
+            Ok(resp) => *resp.data,
+            Err(e) => return Err(format!("Failed to get contract {} - {}", contract_id, e))
+        };
+
+        if !contract.accepted {
+            return Err(format!("Contract {} has not been accepted", contract_id));
+        }
+        if contract.fulfilled {
+            return Err(format!("Contract {} is already fulfilled", contract_id));
+        }
+        if contract.r#type != ContractType::Procurement {
+            return Err(format!("Contract {} is not a procurement contract", contract_id));
+        }
+
+        let deadline = parse_timestamp(&contract.terms.deadline)?;
+
+        let ship = match fleet_api::get_my_ship(&context.api_config, ship_symbol) {
+            Ok(resp) => *resp.data,
+            Err(e) => return Err(format!("Failed to get ship {} - {}", ship_symbol, e))
+        };
+
+        let skew = ClockSkew::measure(&context.api_config).unwrap_or_default();
+
+        Ok(ContractRunner { context, ship, contract, deadline, skew })
+    }
+
+    pub fn run(&mut self) -> Result<String, String> {
+        while let Some(good) = self.outstanding_good() {
+            self.deliver_load(&good)?;
+        }
+
+        self.progress(format!("All goods delivered, fulfilling contract {}", self.contract.id));
+
+        match contracts_api::fulfill_contract(&self.context.api_config, &self.contract.id) {
+            Ok(resp) => {
+                self.context.ledger.record_contract_payment(&self.contract.id, Some(&self.ship.symbol),
+                                                            self.contract.terms.payment.on_fulfilled, resp.data.agent.credits);
+                Ok(format!("Contract {} fulfilled. Credits now {}", self.contract.id, resp.data.agent.credits))
+            },
+            Err(e) => Err(format!("Failed to fulfill contract {} - {}", self.contract.id, e))
+        }
+    }
+
+    fn outstanding_good(&self) -> Option<ContractDeliverGood> {
+        self.contract.terms.deliver.as_ref()?
+            .iter()
+            .find(|g| g.units_fulfilled < g.units_required)
+            .cloned()
+    }
+
+    // One round trip: fill the hold (or as much as is still required) and deliver it
+    fn deliver_load(&mut self, good: &ContractDeliverGood) -> Result<(), String> {
+        self.check_deadline()?;
+
+        let remaining = good.units_required - good.units_fulfilled;
+        let held = self.cargo_units(&good.trade_symbol);
+        let load = match plan_trips(good, self.ship.cargo.capacity, held).first() {
+            Some(load) => *load,
+            None => return Ok(())
+        };
+
+        if load.to_source > 0 {
+            self.source(&good.trade_symbol, load.to_source)?;
+        }
+
+        self.check_deadline()?;
+        self.travel_to(&good.destination_symbol)?;
+        self.dock()?;
+
+        let units = self.cargo_units(&good.trade_symbol).min(remaining);
+        if units == 0 {
+            return Err(format!("No {} in cargo to deliver", good.trade_symbol));
+        }
+
+        let req = DeliverContractRequest::new(self.ship.symbol.clone(), good.trade_symbol.clone(), units);
+        match contracts_api::deliver_contract(&self.context.api_config, &self.contract.id, Some(req)) {
+            Ok(resp) => {
+                self.contract = *resp.data.contract;
+                self.ship.cargo = resp.data.cargo;
+            },
+            Err(e) => return Err(format!("Failed to deliver {} - {}", good.trade_symbol, e))
+        }
+
+        if let Some(delivered) = self.contract.terms.deliver.as_ref()
+            .and_then(|d| d.iter().find(|g| g.trade_symbol == good.trade_symbol)) {
+            self.progress(format!("Delivered {} {}, {}/{} fulfilled", units, good.trade_symbol,
+                                  delivered.units_fulfilled, delivered.units_required));
+        }
+
+        Ok(())
+    }
+
+    fn source(&mut self, trade_symbol: &str, units: i32) -> Result<(), String> {
+        match self.find_seller(trade_symbol)? {
+            Some(waypoint) => self.buy(&waypoint, trade_symbol, units),
+            None => self.mine(trade_symbol, units)
+        }
+    }
+
+    // Prefer the cheapest known price, otherwise the closest market that trades the good
+    fn find_seller(&mut self, trade_symbol: &str) -> Result<Option<String>, String> {
+        let api_config = self.context.api_config.clone();
+        let system = self.ship.nav.system_symbol.clone();
+        let here = self.position()?;
+
+        let mut candidates = Vec::new();
+
+        for waypoint in self.context.cache.marketplaces(&api_config, &system)? {
+            let snapshot = match self.context.cache.markets.get(&waypoint.symbol) {
+                Some(snapshot) => snapshot.clone(),
+                None => {
+                    let market = self.context.cache.market(&api_config, &waypoint.symbol)?;
+                    MarketSnapshot { market, fetched_at: Utc::now().to_rfc3339() }
+                }
+            };
+
+            let sells = snapshot.market.exports.iter().chain(snapshot.market.exchange.iter())
+                .any(|g| g.symbol.to_string() == trade_symbol);
+
+            if sells {
+                let price = snapshot.purchase_price(trade_symbol).unwrap_or(i32::MAX);
+                candidates.push((price, cache::distance(here, (waypoint.x, waypoint.y)), waypoint.symbol));
+            }
+        }
+
+        candidates.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+
+        Ok(candidates.into_iter().next().map(|(_, _, symbol)| symbol))
+    }
+
+    fn buy(&mut self, waypoint: &str, trade_symbol: &str, units: i32) -> Result<(), String> {
+        self.travel_to(waypoint)?;
+        self.dock()?;
+        self.sell_surplus();
+
+        // Refresh now that a ship is present so trade volumes and prices are known
+        let api_config = self.context.api_config.clone();
+        let market = self.context.cache.market(&api_config, waypoint)?;
+        let trade_volume = market.trade_goods.as_ref()
+            .and_then(|goods| goods.iter().find(|g| g.symbol == trade_symbol))
+            .map(|g| g.trade_volume)
+            .unwrap_or(units);
+
+        let mut bought = 0;
+        while bought < units {
+            let quantity = (units - bought).min(trade_volume).min(self.free_space());
+            if quantity <= 0 {
+                break;
+            }
+
+            let req = PurchaseCargoRequest::new(trade_symbol.to_string(), quantity);
+            match fleet_api::purchase_cargo(&self.context.api_config, &self.ship.symbol, Some(req)) {
+                Ok(resp) => {
+                    self.context.ledger.record_trade(&format!("contract:{}", self.contract.id), &resp.data.transaction, resp.data.agent.credits);
+                    self.ship.cargo = resp.data.cargo;
+                    bought += quantity;
+                    self.progress(format!("Bought {} {} for {} credits", quantity, trade_symbol, resp.data.transaction.total_price));
+                },
+                Err(e) => return Err(format!("Failed to buy {} at {} - {}", trade_symbol, waypoint, e))
+            }
+        }
+
+        if bought == 0 {
+            return Err(format!("No room in cargo to buy {}", trade_symbol));
+        }
+
+        Ok(())
+    }
+
+    fn mine(&mut self, trade_symbol: &str, units: i32) -> Result<(), String> {
+        let api_config = self.context.api_config.clone();
+        let system = self.ship.nav.system_symbol.clone();
+
+        if !can_mine(&self.ship) {
+            return Err(format!("No market in {} sells {}, and {} has no mining laser to mine it", system, trade_symbol, self.ship.symbol));
+        }
+
+        // Make room for the ore, selling what we can before leaving and jettisoning the rest at the field
+        if self.surplus().next().is_some() && self.at_marketplace() {
+            self.dock()?;
+            self.sell_surplus();
+        }
+
+        let here = self.position()?;
+
+        let field = self.context.cache.system_waypoints(&api_config, &system)?
+            .into_iter()
+            .filter(|w| w.r#type == WaypointType::AsteroidField)
+            .min_by(|a, b| cache::distance(here, (a.x, a.y)).total_cmp(&cache::distance(here, (b.x, b.y))))
+            .ok_or(format!("No market sells {} and there is no asteroid field in {}", trade_symbol, system))?;
+
+        self.travel_to(&field.symbol)?;
+        self.orbit()?;
+
+        let surplus: Vec<(String, i32)> = self.surplus().collect();
+        for (symbol, units) in surplus {
+            self.jettison(&symbol, units)?;
+        }
+
+        let target = self.cargo_units(trade_symbol) + units;
+
+        if let Ok(Some(cooldown)) = wait::current_cooldown(&self.context.api_config, &self.ship.symbol) {
+            self.wait_for_cooldown(&cooldown)?;
+        }
+
+        while self.cargo_units(trade_symbol) < target {
+            self.check_deadline()?;
+
+            let cooldown = match fleet_api::extract_resources(&self.context.api_config, &self.ship.symbol, Some(ExtractResourcesRequest::new())) {
+                Ok(resp) => {
+                    let extracted = &resp.data.extraction.r#yield;
+                    self.progress(format!("Extracted {} {}", extracted.units, extracted.symbol));
+                    self.ship.cargo = resp.data.cargo.clone();
+
+                    if extracted.symbol != trade_symbol {
+                        self.jettison(&extracted.symbol.clone(), extracted.units)?;
+                    }
+                    resp.data.cooldown
+                },
+                Err(e) => return Err(format!("Failed to extract resources - {}", e))
+            };
+
+            if self.free_space() == 0 {
+                break;
+            }
+
+            self.wait_for_cooldown(&cooldown)?;
+        }
+
+        Ok(())
+    }
+
+    fn jettison(&mut self, trade_symbol: &str, units: i32) -> Result<(), String> {
+        let req = JettisonRequest::new(trade_symbol.to_string(), units);
+        match fleet_api::jettison(&self.context.api_config, &self.ship.symbol, Some(req)) {
+            Ok(resp) => {
+                self.ship.cargo = resp.data.cargo;
+                self.progress(format!("Jettisoned {} {}", units, trade_symbol));
+                Ok(())
+            },
+            Err(e) => Err(format!("Failed to jettison {} - {}", trade_symbol, e))
+        }
+    }
+
+    // Cargo that isn't for the contract
+    fn surplus(&self) -> impl Iterator<Item = (String, i32)> + '_ {
+        let wanted: Vec<&str> = self.contract.terms.deliver.iter().flatten().map(|g| g.trade_symbol.as_str()).collect();
+
+        self.ship.cargo.inventory.iter()
+            .filter(move |item| !wanted.contains(&item.symbol.as_str()))
+            .map(|item| (item.symbol.clone(), item.units))
+    }
+
+    // Sell anything that isn't part of the contract to make room, if the current market will take it
+    fn sell_surplus(&mut self) {
+        let surplus: Vec<(String, i32)> = self.surplus().collect();
+
+        for (symbol, units) in surplus {
+            let req = SellCargoRequest::new(symbol.clone(), units);
+            if let Ok(resp) = fleet_api::sell_cargo(&self.context.api_config, &self.ship.symbol, Some(req)) {
+                self.context.ledger.record_trade(&format!("contract:{}", self.contract.id), &resp.data.transaction, resp.data.agent.credits);
+                self.ship.cargo = resp.data.cargo;
+                self.progress(format!("Sold {} {} for {} credits", units, symbol, resp.data.transaction.total_price));
+            }
+        }
+    }
+
+    fn travel_to(&mut self, waypoint: &str) -> Result<(), String> {
+        if self.ship.nav.status == ShipNavStatus::InTransit {
+            self.wait_for_arrival()?;
+        }
+
+        if self.ship.nav.waypoint_symbol == waypoint {
+            return Ok(());
+        }
+
+        if let Some(stop) = self.refuel_stop(waypoint)? {
+            self.progress(format!("Stopping at {} for fuel on the way to {}", stop, waypoint));
+            self.navigate(&stop)?;
+            self.dock()?;
+        }
+
+        self.navigate(waypoint)
+    }
+
+    // Where to refuel before heading to `waypoint`, if the tank won't get us there. Fills up here
+    // first when we can, which is all most trips need.
+    fn refuel_stop(&mut self, waypoint: &str) -> Result<Option<String>, String> {
+        // Ships without a tank, like probes, fly for free
+        if self.ship.fuel.capacity == 0 {
+            return Ok(None);
+        }
+
+        let api_config = self.context.api_config.clone();
+        let here = self.position()?;
+        let destination = self.context.cache.waypoint(&api_config, waypoint)?;
+        let destination = (destination.x, destination.y);
+        let needed = navigation::fuel_required(cache::distance(here, destination), ShipNavFlightMode::Cruise);
+
+        if self.ship.fuel.current < needed && self.at_marketplace() {
+            self.dock()?;
+        }
+        if self.ship.fuel.current >= needed {
+            return Ok(None);
+        }
+
+        let stations: Vec<(String, (i32, i32))> = self.context.cache.marketplaces(&api_config, &self.ship.nav.system_symbol)?
+            .into_iter()
+            .map(|w| (w.symbol, (w.x, w.y)))
+            .collect();
+
+        match navigation::refuel_stop(here, destination, self.ship.fuel.current, self.ship.fuel.capacity, &stations) {
+            Some(stop) => Ok(Some(stop.to_string())),
+            None => Err(format!("Not enough fuel to reach {}, it needs {} and the tank has {}, and no market in range to refuel at",
+                                waypoint, needed, self.ship.fuel.current))
+        }
+    }
+
+    fn navigate(&mut self, waypoint: &str) -> Result<(), String> {
+        self.orbit()?;
+
+        let req = NavigateShipRequest::new(waypoint.to_string());
+        match fleet_api::navigate_ship(&self.context.api_config, &self.ship.symbol, Some(req)) {
+            Ok(resp) => {
+                self.ship.nav = resp.data.nav;
+                self.ship.fuel = resp.data.fuel;
+            },
+            Err(e) => return Err(format!("Failed to navigate to {} - {}", waypoint, e))
+        }
+
+        self.progress(format!("Navigating to {}, arriving at {}", waypoint, self.ship.nav.route.arrival));
+        self.wait_for_arrival()
+    }
+
+    fn wait_for_arrival(&mut self) -> Result<(), String> {
+        match wait::wait_for_arrival(&self.context.api_config, &self.ship.symbol, &self.ship.nav, &self.skew) {
+            Ok(nav) => {
+                *self.ship.nav = nav;
+                Ok(())
+            },
+            Err(e) => Err(format!("Failed to get nav status of {} - {}", self.ship.symbol, e))
+        }
+    }
+
+    fn wait_for_cooldown(&self, cooldown: &Cooldown) -> Result<(), String> {
+        wait::wait_for_cooldown(&self.context.api_config, cooldown, &self.skew)
+            .map_err(|e| format!("Failed to get cooldown of {} - {}", self.ship.symbol, e))
+    }
+
+    fn orbit(&mut self) -> Result<(), String> {
+        if self.ship.nav.status != ShipNavStatus::Docked {
+            return Ok(());
+        }
+
+        match fleet_api::orbit_ship(&self.context.api_config, &self.ship.symbol) {
+            Ok(resp) => {
+                self.ship.nav = resp.data.nav;
+                Ok(())
+            },
+            Err(e) => Err(format!("Failed to orbit - {}", e))
+        }
+    }
+
+    fn dock(&mut self) -> Result<(), String> {
+        if self.ship.nav.status != ShipNavStatus::Docked {
+            match fleet_api::dock_ship(&self.context.api_config, &self.ship.symbol) {
+                Ok(resp) => self.ship.nav = resp.data.nav,
+                Err(e) => return Err(format!("Failed to dock - {}", e))
+            }
+        }
+
+        self.refuel();
+        Ok(())
+    }
+
+    // Top up whenever we're docked at a marketplace so the next leg never runs dry
+    fn refuel(&mut self) {
+        if self.ship.fuel.current >= self.ship.fuel.capacity || !self.at_marketplace() {
+            return;
+        }
+
+        match fleet_api::refuel_ship(&self.context.api_config, &self.ship.symbol) {
+            Ok(resp) => {
+                let price = self.context.cache.purchase_price(&self.ship.nav.waypoint_symbol, "FUEL");
+                self.context.ledger.record_refuel(&format!("contract:{}", self.contract.id), &self.ship.symbol,
+                                                  Some(resp.data.fuel.current - self.ship.fuel.current), price, resp.data.agent.credits);
+                self.ship.fuel = resp.data.fuel;
+                self.progress(format!("Refuelled to {}/{}", self.ship.fuel.current, self.ship.fuel.capacity));
+            },
+            Err(e) => self.progress(format!("Failed to refuel - {}", e))
+        }
+    }
+
+    fn at_marketplace(&mut self) -> bool {
+        let api_config = self.context.api_config.clone();
+        let waypoint = self.ship.nav.waypoint_symbol.clone();
+        matches!(self.context.cache.waypoint(&api_config, &waypoint), Ok(w) if cache::has_trait(&w, WaypointTraitSymbol::Marketplace))
+    }
+
+    fn position(&mut self) -> Result<(i32, i32), String> {
+        let api_config = self.context.api_config.clone();
+        let waypoint = self.context.cache.waypoint(&api_config, &self.ship.nav.waypoint_symbol)?;
+        Ok((waypoint.x, waypoint.y))
+    }
+
+    fn cargo_units(&self, trade_symbol: &str) -> i32 {
+        self.ship.cargo.inventory.iter()
+            .filter(|item| item.symbol == trade_symbol)
+            .map(|item| item.units)
+            .sum()
+    }
+
+    fn free_space(&self) -> i32 {
+        self.ship.cargo.capacity - self.ship.cargo.units
+    }
+
+    fn check_deadline(&self) -> Result<(), String> {
+        if Utc::now() > self.deadline {
+            return Err(format!("Contract {} missed its deadline of {}", self.contract.id, self.deadline));
+        }
+        Ok(())
+    }
+
+    fn progress(&self, msg: String) {
+        println!("[{}] {}", self.ship.symbol, msg);
+    }
+}
+
+/// One trip's worth of a contract good: how much to carry to the destination, and how much of
+/// that still has to be bought or mined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Load {
+    pub units: i32,
+    pub to_source: i32,
+}
+
+/// The trips still needed to deliver a good in a hold of `capacity`, full loads first and any
+/// remainder last. `held` units are already aboard and go towards the first.
+pub fn plan_trips(good: &ContractDeliverGood, capacity: i32, held: i32) -> Vec<Load> {
+    let capacity = capacity.max(1);
+    let mut remaining = good.units_required - good.units_fulfilled;
+    let mut held = held.max(0);
+    let mut loads = Vec::new();
+
+    while remaining > 0 {
+        let units = remaining.min(capacity);
+        loads.push(Load { units, to_source: (units - held).max(0) });
+        remaining -= units;
+        held = 0;
+    }
+
+    loads
+}
+
+/// Estimated cost, duration and return of completing a contract with a given ship.
+#[derive(Debug, Clone)]
+pub struct ContractEvaluation {
+    pub payment: i32,
+    pub sourcing_cost: i32,
+    pub fuel_cost: i32,
+    pub duration: ChronoDuration,
+    pub meets_deadline: bool,
+    // Goods with no known market price, assumed to be mined
+    pub mined: Vec<String>,
+}
+
+impl ContractEvaluation {
+    pub fn profit(&self) -> i32 {
+        self.payment - self.sourcing_cost - self.fuel_cost
+    }
+
+    pub fn profit_per_hour(&self) -> f64 {
+        let hours = self.duration.num_seconds().max(1) as f64 / 3600.0;
+        self.profit() as f64 / hours
+    }
+}
+
+/// Cost a contract for a ship from what the cache knows of the markets and waypoints involved,
+/// see `estimate` for the sums.
+pub fn evaluate_contract(cache: &mut UniverseCache, api_config: &Configuration, contract: &Contract, ship: &Ship) -> Result<ContractEvaluation, String> {
+    let fuel_price = cache.best_purchase_price(&ship.nav.system_symbol, "FUEL")
+        .map(|(price, _)| price)
+        .unwrap_or(DEFAULT_FUEL_PRICE);
+
+    let start = cache.waypoint(api_config, &ship.nav.waypoint_symbol).map(|w| (w.x, w.y))?;
+    let mut routes = Vec::new();
+
+    for good in contract.terms.deliver.iter().flatten() {
+        if plan_trips(good, ship.cargo.capacity, 0).is_empty() {
+            continue;
+        }
+
+        let destination = cache.waypoint(api_config, &good.destination_symbol)?;
+        let destination = (destination.x, destination.y);
+
+        let (source, price) = match cache.best_purchase_price(&cache::system_symbol(&good.destination_symbol), &good.trade_symbol) {
+            Some((price, waypoint)) => {
+                let w = cache.waypoint(api_config, &waypoint)?;
+                ((w.x, w.y), Some(price))
+            },
+            None => {
+                let field = cache.system_waypoints(api_config, &cache::system_symbol(&good.destination_symbol))?
+                    .into_iter()
+                    .filter(|w| w.r#type == WaypointType::AsteroidField)
+                    .min_by(|a, b| cache::distance(destination, (a.x, a.y)).total_cmp(&cache::distance(destination, (b.x, b.y))))
+                    .ok_or(format!("No known source of {}", good.trade_symbol))?;
+                ((field.x, field.y), None)
+            }
+        };
+
+        routes.push(GoodRoute { good: good.clone(), source, destination, price });
+    }
+
+    estimate(contract, start, &routes, ship.cargo.capacity, ship.engine.speed, fuel_price, Utc::now())
+}
+
+// Where an outstanding contract good comes from and goes to
+#[derive(Debug, Clone)]
+struct GoodRoute {
+    good: ContractDeliverGood,
+    source: (i32, i32),
+    destination: (i32, i32),
+    // Per unit where it's cheapest to buy, or none if it has to be mined
+    price: Option<i32>,
+}
+
+// The payment, costs and time of delivering each good in turn along its route, starting from `start`
+// in a ship with this hold and engine
+fn estimate(contract: &Contract, start: (i32, i32), routes: &[GoodRoute], capacity: i32, engine_speed: f32, fuel_price: i32, now: DateTime<Utc>) -> Result<ContractEvaluation, String> {
+    let mut payment = contract.terms.payment.on_fulfilled;
+    if !contract.accepted {
+        payment += contract.terms.payment.on_accepted;
+    }
+
+    let mut evaluation = ContractEvaluation {
+        payment,
+        sourcing_cost: 0,
+        fuel_cost: 0,
+        duration: ChronoDuration::zero(),
+        meets_deadline: false,
+        mined: Vec::new(),
+    };
+
+    let mut position = start;
+    let mut fuel_units = 0;
+
+    for route in routes {
+        let good = &route.good;
+        let remaining = good.units_required - good.units_fulfilled;
+
+        match route.price {
+            Some(price) => evaluation.sourcing_cost += price * remaining,
+            None => {
+                evaluation.mined.push(good.trade_symbol.clone());
+                let extractions = (remaining + UNITS_PER_EXTRACTION - 1) / UNITS_PER_EXTRACTION;
+                evaluation.duration += ChronoDuration::seconds(extractions as i64 * EXTRACTION_COOLDOWN_SECONDS);
+            }
+        }
+
+        // First leg from wherever the ship is, then shuttle between source and destination
+        let trips = plan_trips(good, capacity, 0).len();
+        let mut legs = vec![cache::distance(position, route.source), cache::distance(route.source, route.destination)];
+        for _ in 1..trips {
+            legs.push(cache::distance(route.destination, route.source));
+            legs.push(cache::distance(route.source, route.destination));
+        }
+
+        for distance in legs {
+            evaluation.duration += navigation::flight_time(distance, engine_speed, ShipNavFlightMode::Cruise);
+            fuel_units += navigation::fuel_required(distance, ShipNavFlightMode::Cruise);
+        }
+
+        position = route.destination;
+    }
+
+    evaluation.fuel_cost = (fuel_units + FUEL_UNITS_PER_MARKET_UNIT - 1) / FUEL_UNITS_PER_MARKET_UNIT * fuel_price;
+
+    let deadline = parse_timestamp(&contract.terms.deadline)?;
+    evaluation.meets_deadline = now + evaluation.duration <= deadline;
+
+    Ok(evaluation)
+}
+
+// The ship an evaluation is costed against, defaulting to the first in the fleet
+pub fn evaluation_ship(api_config: &Configuration, ship_symbol: Option<String>) -> Result<Ship, String> {
+    match ship_symbol {
+        Some(symbol) => match fleet_api::get_my_ship(api_config, &symbol) {
+            Ok(resp) => Ok(*resp.data),
+            Err(e) => Err(format!("Failed to get ship {} - {}", symbol, e))
+        },
+        None => match fleet_api::get_my_ships(api_config, None, None) {
+            Ok(resp) => resp.data.into_iter().next().ok_or("No ships to evaluate contracts with".to_string()),
+            Err(e) => Err(format!("Failed to get ships {}", e))
+        }
+    }
+}
+
+pub fn can_mine(ship: &Ship) -> bool {
+    ship.mounts.iter().any(|m| matches!(m.symbol, ShipMountSymbol::MiningLaserI | ShipMountSymbol::MiningLaserIi | ShipMountSymbol::MiningLaserIii))
+}
+
+pub fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, String> {
+    wait::parse_timestamp(timestamp).ok_or(format!("Invalid timestamp {}", timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use spacetraders_sdk::models::{ContractPayment, ContractTerms};
+
+    use super::*;
+
+    fn good(required: i32, fulfilled: i32) -> ContractDeliverGood {
+        ContractDeliverGood::new("IRON_ORE".to_string(), "X1-AB-C1".to_string(), required, fulfilled)
+    }
+
+    fn units(loads: &[Load]) -> Vec<i32> {
+        loads.iter().map(|l| l.units).collect()
+    }
+
+    #[test]
+    fn trips_carry_a_full_hold_until_the_last() {
+        assert_eq!(units(&plan_trips(&good(90, 0), 30, 0)), vec![30, 30, 30]);
+        assert_eq!(units(&plan_trips(&good(100, 0), 30, 0)), vec![30, 30, 30, 10]);
+        // Only what's still outstanding, so a part-delivered good can finish on a partial trip
+        assert_eq!(units(&plan_trips(&good(100, 75), 30, 0)), vec![25]);
+    }
+
+    #[test]
+    fn cargo_aboard_counts_towards_the_first_trip() {
+        assert_eq!(plan_trips(&good(50, 0), 30, 12), vec![Load { units: 30, to_source: 18 }, Load { units: 20, to_source: 20 }]);
+        // Already holding the last of it, nothing to source
+        assert_eq!(plan_trips(&good(50, 40), 30, 10), vec![Load { units: 10, to_source: 0 }]);
+    }
+
+    #[test]
+    fn fulfilled_goods_need_no_trips() {
+        assert!(plan_trips(&good(50, 50), 30, 0).is_empty());
+        assert!(plan_trips(&good(50, 60), 30, 5).is_empty());
+    }
+
+    fn contract(accepted: bool, goods: Vec<ContractDeliverGood>) -> Contract {
+        let mut terms = ContractTerms::new("2026-01-01T00:10:00.000Z".to_string(), ContractPayment::new(1000, 10000));
+        terms.deliver = Some(goods);
+        Contract::new("C1".to_string(), "COSMIC".to_string(), ContractType::Procurement, terms, accepted, false, "2026-01-02T00:00:00.000Z".to_string())
+    }
+
+    // Bought or mined at the origin and delivered 100 units away
+    fn route(good: ContractDeliverGood, price: Option<i32>) -> GoodRoute {
+        GoodRoute { good, source: (0, 0), destination: (100, 0), price }
+    }
+
+    fn now() -> DateTime<Utc> {
+        parse_timestamp("2026-01-01T00:00:00.000Z").unwrap()
+    }
+
+    #[test]
+    fn bought_goods_cost_their_price_and_the_fuel_to_haul_them() {
+        let good = good(60, 0);
+        let evaluation = estimate(&contract(false, vec![good.clone()]), (0, 0), &[route(good, Some(50))], 30, 10.0, 80, now()).unwrap();
+
+        assert_eq!(evaluation.payment, 11000);
+        assert_eq!(evaluation.sourcing_cost, 3000);
+        // Two trips of 100 and the leg back between them, 300 fuel or 3 units bought at market
+        assert_eq!(evaluation.fuel_cost, 240);
+        assert_eq!(evaluation.duration, ChronoDuration::seconds(3 * 265));
+        assert_eq!(evaluation.profit(), 7760);
+        assert_eq!(evaluation.profit_per_hour(), 7760.0 * 3600.0 / 795.0);
+        assert!(evaluation.mined.is_empty());
+        assert!(!evaluation.meets_deadline);
+    }
+
+    #[test]
+    fn accepted_contracts_have_already_paid_out_on_acceptance() {
+        let good = good(60, 0);
+        let evaluation = estimate(&contract(true, vec![good.clone()]), (0, 0), &[route(good, Some(50))], 30, 10.0, 80, now()).unwrap();
+
+        assert_eq!(evaluation.payment, 10000);
+        assert_eq!(evaluation.profit(), 6760);
+    }
+
+    #[test]
+    fn mined_goods_cost_time_instead_of_credits() {
+        let good = good(20, 0);
+        let evaluation = estimate(&contract(true, vec![good.clone()]), (0, 0), &[route(good, None)], 30, 10.0, 80, now()).unwrap();
+
+        assert_eq!(evaluation.mined, vec!["IRON_ORE"]);
+        assert_eq!(evaluation.sourcing_cost, 0);
+        // Three extractions for 20 units, then a single trip
+        assert_eq!(evaluation.duration, ChronoDuration::seconds(3 * EXTRACTION_COOLDOWN_SECONDS + 265));
+        assert_eq!(evaluation.fuel_cost, 80);
+        assert!(evaluation.meets_deadline);
+    }
+}
@@ -0,0 +1,488 @@
+use std::thread;
+
+use chrono::{DateTime, Utc};
+use spacetraders_sdk::apis::fleet_api;
+use spacetraders_sdk::models::{ExtractResourcesRequest, NavigateShipRequest, SellCargoRequest, Ship, ShipCargoItem, ShipNavStatus,
+                               TransferCargoRequest, WaypointType};
+use spacetraders_sdk::wait::{self, ClockSkew};
+
+use crate::cache;
+use crate::config::ConfigWrapper;
+use crate::contracts::can_mine;
+
+/// A ship in the convoy and the server time it's next free to act, after a transit or cooldown.
+struct Member {
+    ship: Ship,
+    busy_until: Option<DateTime<Utc>>,
+}
+
+impl Member {
+    fn is_ready(&self, now: DateTime<Utc>) -> bool {
+        self.busy_until.map(|t| t <= now).unwrap_or(true)
+    }
+
+    fn free_space(&self) -> i32 {
+        self.ship.cargo.capacity - self.ship.cargo.units
+    }
+
+    fn status(&self, field: &str) -> Status {
+        Status { at_field: self.ship.nav.waypoint_symbol == field, free_space: self.free_space(), busy_until: self.busy_until }
+    }
+
+    fn progress(&self, msg: String) {
+        println!("[{}] {}", self.ship.symbol, msg);
+    }
+}
+
+/// Keeps a group of mining ships extracting at one asteroid field while a hauler ferries
+/// their cargo to market, so the miners never have to leave the field.
+pub struct Convoy<'a> {
+    context: &'a mut ConfigWrapper,
+    field: String,
+    hauler: Member,
+    miners: Vec<Member>,
+    skew: ClockSkew,
+}
+
+impl<'a> Convoy<'a> {
+    /// Mine at `field`, or the asteroid field nearest the hauler if none is given
+    pub fn new(context: &'a mut ConfigWrapper, hauler_symbol: &str, miner_symbols: &[String], field: Option<String>) -> Result<Convoy<'a>, String> {
+        if miner_symbols.is_empty() {
+            return Err("A convoy needs at least one miner".to_string());
+        }
+
+        let hauler = fetch_ship(context, hauler_symbol)?;
+
+        let mut miners = Vec::new();
+        for symbol in miner_symbols {
+            let ship = fetch_ship(context, symbol)?;
+            if !can_mine(&ship) {
+                return Err(format!("{} has no mining laser", symbol));
+            }
+            if ship.nav.system_symbol != hauler.nav.system_symbol {
+                return Err(format!("{} is not in the same system as {}", symbol, hauler_symbol));
+            }
+            // Extraction is refused until any cooldown left over from earlier work expires
+            let busy_until = wait::current_cooldown(&context.api_config, symbol).ok().flatten()
+                .and_then(|cooldown| wait::parse_timestamp(&cooldown.expiration));
+            miners.push(Member { ship, busy_until });
+        }
+
+        let field = match field {
+            Some(field) => field,
+            None => nearest_field(context, &hauler)?
+        };
+
+        let skew = ClockSkew::measure(&context.api_config).unwrap_or_default();
+
+        Ok(Convoy { context, field, hauler: Member { ship: hauler, busy_until: None }, miners, skew })
+    }
+
+    /// Run until the hauler has sold `hauls` loads (0 for no limit)
+    pub fn run(&mut self, hauls: i32) -> Result<String, String> {
+        let mut sold = 0;
+        let mut earned = 0;
+
+        let field = self.field.clone();
+        self.send(Role::Hauler, &field)?;
+        for i in 0..self.miners.len() {
+            self.send(Role::Miner(i), &field)?;
+        }
+
+        loop {
+            let now = self.skew.server_now();
+
+            if self.hauler.is_ready(now) && self.arrive(Role::Hauler)? {
+                match hauler_step(&self.hauler.status(&self.field)) {
+                    Some(Step::Sell) => {
+                        let credits = self.sell_load()?;
+                        if credits == 0 {
+                            return Err(format!("{} could not sell its cargo at {}", self.hauler.ship.symbol, self.hauler.ship.nav.waypoint_symbol));
+                        }
+                        earned += credits;
+                        sold += 1;
+
+                        if hauls > 0 && sold >= hauls {
+                            break;
+                        }
+                        self.send(Role::Hauler, &field)?;
+                    },
+                    Some(Step::Haul) => self.start_haul()?,
+                    _ => ()
+                }
+            }
+
+            for i in 0..self.miners.len() {
+                if !self.miners[i].is_ready(now) || !self.arrive(Role::Miner(i))? {
+                    continue;
+                }
+
+                // Checked again after extracting, as that may have filled the hold
+                if miner_step(&self.miners[i].status(&self.field), self.hauler_waiting(now)) == Some(Step::Extract) {
+                    self.extract(i)?;
+                }
+                if miner_step(&self.miners[i].status(&self.field), self.hauler_waiting(now)) == Some(Step::Unload) {
+                    self.unload(i)?;
+                }
+            }
+
+            if self.hauler_waiting(now) && hauler_step(&self.hauler.status(&self.field)) == Some(Step::Haul) {
+                self.start_haul()?;
+            }
+
+            let miners: Vec<Status> = self.miners.iter().map(|m| m.status(&self.field)).collect();
+            match next_event(&self.hauler.status(&self.field), &miners) {
+                Wake::At(next) => thread::sleep(self.skew.until(next)),
+                Wake::Now => (),
+                Wake::Stuck => return Err(format!("{} cannot take any more cargo from the miners", self.hauler.ship.symbol))
+            }
+        }
+
+        Ok(format!("{} sold {} loads for {} credits", self.hauler.ship.symbol, sold, earned))
+    }
+
+    fn hauler_waiting(&self, now: DateTime<Utc>) -> bool {
+        self.hauler.is_ready(now) && self.hauler.ship.nav.waypoint_symbol == self.field
+    }
+
+    fn start_haul(&mut self) -> Result<(), String> {
+        let cargo: Vec<(String, i32)> = self.hauler.ship.cargo.inventory.iter()
+            .map(|item| (item.symbol.clone(), item.units))
+            .collect();
+
+        let market = match self.context.cache.best_market_for_cargo(&self.hauler.ship.nav.system_symbol, &cargo) {
+            Some((value, market)) => {
+                self.hauler.progress(format!("Hauling to {}, expecting {} credits", market, value));
+                market
+            },
+            None => self.nearest_marketplace()?
+        };
+
+        self.send(Role::Hauler, &market)
+    }
+
+    fn nearest_marketplace(&mut self) -> Result<String, String> {
+        let api_config = self.context.api_config.clone();
+        let here = self.context.cache.waypoint(&api_config, &self.field)?;
+
+        self.context.cache.marketplaces(&api_config, &self.hauler.ship.nav.system_symbol)?
+            .into_iter()
+            .min_by(|a, b| cache::distance((here.x, here.y), (a.x, a.y)).total_cmp(&cache::distance((here.x, here.y), (b.x, b.y))))
+            .map(|w| w.symbol)
+            .ok_or(format!("There are no marketplaces in {}", self.hauler.ship.nav.system_symbol))
+    }
+
+    // Dock, sell everything the market will take in trade volume sized chunks, refuel and return
+    fn sell_load(&mut self) -> Result<i32, String> {
+        let api_config = self.context.api_config.clone();
+        let symbol = self.hauler.ship.symbol.clone();
+        let waypoint = self.hauler.ship.nav.waypoint_symbol.clone();
+
+        if self.hauler.ship.nav.status != ShipNavStatus::Docked {
+            match fleet_api::dock_ship(&api_config, &symbol) {
+                Ok(resp) => self.hauler.ship.nav = resp.data.nav,
+                Err(e) => return Err(format!("Failed to dock {} - {}", symbol, e))
+            }
+        }
+
+        let market = self.context.cache.market(&api_config, &waypoint)?;
+        let goods: Vec<(String, i32)> = self.hauler.ship.cargo.inventory.iter()
+            .map(|item| (item.symbol.clone(), item.units))
+            .collect();
+
+        let mut earned = 0;
+        for (good, units) in goods {
+            let trade_volume = market.trade_goods.as_ref()
+                .and_then(|goods| goods.iter().find(|g| g.symbol == good))
+                .map(|g| g.trade_volume)
+                .unwrap_or(units);
+
+            let mut remaining = units;
+            while remaining > 0 {
+                let quantity = remaining.min(trade_volume.max(1));
+                match fleet_api::sell_cargo(&api_config, &symbol, Some(SellCargoRequest::new(good.clone(), quantity))) {
+                    Ok(resp) => {
+                        self.context.ledger.record_trade("convoy", &resp.data.transaction, resp.data.agent.credits);
+                        self.hauler.ship.cargo = resp.data.cargo;
+                        earned += resp.data.transaction.total_price;
+                        self.hauler.progress(format!("Sold {} {} for {} credits", quantity, good, resp.data.transaction.total_price));
+                    },
+                    Err(e) => {
+                        self.hauler.progress(format!("Failed to sell {} - {}", good, e));
+                        break;
+                    }
+                }
+                remaining -= quantity;
+            }
+        }
+
+        if self.hauler.ship.fuel.current < self.hauler.ship.fuel.capacity {
+            match fleet_api::refuel_ship(&api_config, &symbol) {
+                Ok(resp) => {
+                    let added = resp.data.fuel.current - self.hauler.ship.fuel.current;
+                    let price = self.context.cache.purchase_price(&waypoint, "FUEL");
+                    self.context.ledger.record_refuel("convoy", &symbol, Some(added), price, resp.data.agent.credits);
+                    self.hauler.ship.fuel = resp.data.fuel;
+                },
+                Err(e) => self.hauler.progress(format!("Failed to refuel - {}", e))
+            }
+        }
+
+        Ok(earned)
+    }
+
+    fn extract(&mut self, i: usize) -> Result<(), String> {
+        let miner = &mut self.miners[i];
+
+        match fleet_api::extract_resources(&self.context.api_config, &miner.ship.symbol, Some(ExtractResourcesRequest::new())) {
+            Ok(resp) => {
+                let extracted = &resp.data.extraction.r#yield;
+                miner.progress(format!("Extracted {} {}", extracted.units, extracted.symbol));
+                miner.ship.cargo = resp.data.cargo.clone();
+                miner.busy_until = wait::parse_timestamp(&resp.data.cooldown.expiration);
+                Ok(())
+            },
+            Err(e) => Err(format!("Failed to extract resources with {} - {}", miner.ship.symbol, e))
+        }
+    }
+
+    // Move as much of a miner's cargo as the hauler has room for
+    fn unload(&mut self, i: usize) -> Result<(), String> {
+        let miner = &mut self.miners[i];
+        let hauler = &mut self.hauler;
+
+        let items: Vec<(String, i32)> = miner.ship.cargo.inventory.iter()
+            .map(|item| (item.symbol.clone(), item.units))
+            .collect();
+
+        for (good, units) in items {
+            let units = units.min(hauler.free_space());
+            if units <= 0 {
+                break;
+            }
+
+            let req = TransferCargoRequest::new(good.clone(), units, hauler.ship.symbol.clone());
+            match fleet_api::transfer_cargo(&self.context.api_config, &miner.ship.symbol, Some(req)) {
+                Ok(resp) => {
+                    miner.ship.cargo = resp.data.cargo;
+                    hauler.ship.cargo.units += units;
+                    match hauler.ship.cargo.inventory.iter_mut().find(|item| item.symbol == good) {
+                        Some(item) => item.units += units,
+                        None => hauler.ship.cargo.inventory.push(ShipCargoItem::new(good.clone(), good.clone(), String::new(), units))
+                    }
+                    miner.progress(format!("Transferred {} {} to {}", units, good, hauler.ship.symbol));
+                },
+                Err(e) => return Err(format!("Failed to transfer {} from {} - {}", good, miner.ship.symbol, e))
+            }
+        }
+
+        Ok(())
+    }
+
+    // Refresh a ship's nav once its transit should be over and make sure it's in orbit,
+    // returning false if it's still on the way
+    fn arrive(&mut self, role: Role) -> Result<bool, String> {
+        let api_config = self.context.api_config.clone();
+        let field = self.field.clone();
+        let member = self.member(role);
+
+        if member.ship.nav.status == ShipNavStatus::InTransit {
+            member.ship.nav = match fleet_api::get_ship_nav(&api_config, &member.ship.symbol) {
+                Ok(resp) => resp.data,
+                Err(e) => return Err(format!("Failed to get nav status of {} - {}", member.ship.symbol, e))
+            };
+            if member.ship.nav.status == ShipNavStatus::InTransit {
+                member.busy_until = wait::parse_timestamp(&member.ship.nav.route.arrival);
+                return Ok(false);
+            }
+        }
+        member.busy_until = None;
+
+        if member.ship.nav.status == ShipNavStatus::Docked && member.ship.nav.waypoint_symbol == field {
+            match fleet_api::orbit_ship(&api_config, &member.ship.symbol) {
+                Ok(resp) => member.ship.nav = resp.data.nav,
+                Err(e) => return Err(format!("Failed to orbit {} - {}", member.ship.symbol, e))
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Start a ship towards a waypoint without waiting for it to arrive
+    fn send(&mut self, role: Role, waypoint: &str) -> Result<(), String> {
+        let api_config = self.context.api_config.clone();
+        let skew = self.skew;
+        let member = self.member(role);
+
+        if member.ship.nav.waypoint_symbol == waypoint && member.ship.nav.status != ShipNavStatus::InTransit {
+            return Ok(());
+        }
+
+        if member.ship.nav.status == ShipNavStatus::InTransit {
+            if let Ok(nav) = wait::wait_for_arrival(&api_config, &member.ship.symbol, &member.ship.nav, &skew) {
+                *member.ship.nav = nav;
+            }
+            if member.ship.nav.waypoint_symbol == waypoint {
+                return Ok(());
+            }
+        }
+
+        if member.ship.nav.status == ShipNavStatus::Docked {
+            match fleet_api::orbit_ship(&api_config, &member.ship.symbol) {
+                Ok(resp) => member.ship.nav = resp.data.nav,
+                Err(e) => return Err(format!("Failed to orbit {} - {}", member.ship.symbol, e))
+            }
+        }
+
+        match fleet_api::navigate_ship(&api_config, &member.ship.symbol, Some(NavigateShipRequest::new(waypoint.to_string()))) {
+            Ok(resp) => {
+                member.ship.nav = resp.data.nav;
+                member.ship.fuel = resp.data.fuel;
+                member.busy_until = wait::parse_timestamp(&member.ship.nav.route.arrival);
+                member.progress(format!("Navigating to {}, arriving at {}", waypoint, member.ship.nav.route.arrival));
+                Ok(())
+            },
+            Err(e) => Err(format!("Failed to navigate {} to {} - {}", member.ship.symbol, waypoint, e))
+        }
+    }
+
+    fn member(&mut self, role: Role) -> &mut Member {
+        match role {
+            Role::Hauler => &mut self.hauler,
+            Role::Miner(i) => &mut self.miners[i]
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Role {
+    Hauler,
+    Miner(usize),
+}
+
+/// What the decisions below need to know about a ship in the convoy.
+#[derive(Debug, Clone, Copy)]
+struct Status {
+    at_field: bool,
+    free_space: i32,
+    busy_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Step {
+    Sell,
+    Haul,
+    Extract,
+    Unload,
+}
+
+#[derive(Debug, PartialEq)]
+enum Wake {
+    At(DateTime<Utc>),
+    Now,
+    // Nothing is coming that would let anyone act again
+    Stuck,
+}
+
+// What the hauler should do once it's free: sell what it brought away from the field, or take
+// a full hold to market
+fn hauler_step(hauler: &Status) -> Option<Step> {
+    if !hauler.at_field {
+        Some(Step::Sell)
+    } else if hauler.free_space == 0 {
+        Some(Step::Haul)
+    } else {
+        None
+    }
+}
+
+// What a free miner should do: mine while it has room, then hand its cargo to a waiting hauler
+fn miner_step(miner: &Status, hauler_waiting: bool) -> Option<Step> {
+    if miner.free_space > 0 {
+        Some(Step::Extract)
+    } else if hauler_waiting {
+        Some(Step::Unload)
+    } else {
+        None
+    }
+}
+
+// When the next ship can do something useful: a miner off cooldown with room left (or with a
+// hauler to unload to), or the hauler arriving somewhere
+fn next_event(hauler: &Status, miners: &[Status]) -> Wake {
+    let next = miners.iter()
+        .filter(|m| m.free_space > 0 || hauler.at_field)
+        .filter_map(|m| m.busy_until)
+        .chain(hauler.busy_until)
+        .min();
+
+    match next {
+        Some(next) => Wake::At(next),
+        None if miners.iter().all(|m| m.free_space == 0) && hauler.free_space > 0 => Wake::Stuck,
+        None => Wake::Now
+    }
+}
+
+fn nearest_field(context: &mut ConfigWrapper, ship: &Ship) -> Result<String, String> {
+    let api_config = context.api_config.clone();
+    let here = context.cache.waypoint(&api_config, &ship.nav.waypoint_symbol)?;
+
+    context.cache.system_waypoints(&api_config, &ship.nav.system_symbol)?
+        .into_iter()
+        .filter(|w| w.r#type == WaypointType::AsteroidField)
+        .min_by(|a, b| cache::distance((here.x, here.y), (a.x, a.y)).total_cmp(&cache::distance((here.x, here.y), (b.x, b.y))))
+        .map(|w| w.symbol)
+        .ok_or(format!("There is no asteroid field in {}", ship.nav.system_symbol))
+}
+
+fn fetch_ship(context: &ConfigWrapper, ship_symbol: &str) -> Result<Ship, String> {
+    match fleet_api::get_my_ship(&context.api_config, ship_symbol) {
+        Ok(resp) => Ok(*resp.data),
+        Err(e) => Err(format!("Failed to get ship {} - {}", ship_symbol, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn status(at_field: bool, free_space: i32, busy_secs: Option<i64>) -> Status {
+        let busy_until = busy_secs.map(|secs| DateTime::<Utc>::UNIX_EPOCH + Duration::seconds(secs));
+        Status { at_field, free_space, busy_until }
+    }
+
+    #[test]
+    fn full_miner_unloads_to_a_waiting_hauler() {
+        let miner = status(true, 0, Some(60));
+        assert_eq!(miner_step(&miner, true), Some(Step::Unload));
+        assert_eq!(miner_step(&miner, false), None);
+        assert_eq!(miner_step(&status(true, 5, None), true), Some(Step::Extract));
+
+        // With the hauler there a full miner's cooldown is still worth waking for
+        let hauler = status(true, 40, None);
+        assert_eq!(next_event(&hauler, &[miner, status(true, 5, Some(90))]), Wake::At(miner.busy_until.unwrap()));
+    }
+
+    #[test]
+    fn full_hauler_heads_to_market_and_sells_there() {
+        assert_eq!(hauler_step(&status(true, 0, None)), Some(Step::Haul));
+        assert_eq!(hauler_step(&status(true, 10, None)), None);
+        assert_eq!(hauler_step(&status(false, 0, None)), Some(Step::Sell));
+    }
+
+    #[test]
+    fn full_miners_wait_for_the_hauler_to_come_back() {
+        let miners = [status(true, 0, Some(10)), status(true, 0, Some(20))];
+
+        // Their cooldowns ending changes nothing while the hauler is away
+        let returning = status(false, 40, Some(300));
+        assert_eq!(next_event(&returning, &miners), Wake::At(returning.busy_until.unwrap()));
+
+        // And with the hauler idle with room, yet away from the field, nothing ever will
+        assert_eq!(next_event(&status(false, 40, None), &miners), Wake::Stuck);
+        // Unless it's full too, in which case it's off to sell
+        assert_eq!(next_event(&status(false, 0, None), &miners), Wake::Now);
+    }
+}
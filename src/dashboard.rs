@@ -0,0 +1,239 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{stdout, Write};
+use std::time::Duration;
+
+use chrono::{DateTime, Local, Utc};
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{ContentArrangement, Table};
+use crossterm::cursor::MoveTo;
+use crossterm::queue;
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::style::Print;
+use spacetraders_sdk::apis::{agents_api, contracts_api};
+use spacetraders_sdk::models::{Agent, Contract, Ship, ShipNavStatus};
+use spacetraders_sdk::wait::{self, ClockSkew};
+
+use crate::config::ConfigWrapper;
+use crate::networth;
+use crate::screen::{self, Screen};
+
+// Lines kept in the activity log pane
+const LOG_LENGTH: usize = 100;
+
+// Shortest refresh we allow. Each one costs a few requests plus one per ship whose cooldown
+// might have changed, so a big fleet is slowed further to keep to the rate limiter's steady rate.
+const MIN_REFRESH_SECS: u64 = 5;
+
+/// A full screen view of the agent, fleet and contracts, refreshed from the API every
+/// `refresh` seconds. Countdowns tick between refreshes without making requests.
+pub struct Dashboard<'a> {
+    context: &'a mut ConfigWrapper,
+    refresh: Duration,
+    // The refresh after allowing for the requests each one costs
+    effective: Duration,
+    requests: u64,
+    skew: ClockSkew,
+    agent: Option<Agent>,
+    ships: Vec<Ship>,
+    // Cooldown expiry by ship, in server time
+    cooldowns: HashMap<String, DateTime<Utc>>,
+    contracts: Vec<Contract>,
+    log: VecDeque<String>,
+    // Ledger entries already shown in the log
+    seen_entries: usize,
+}
+
+impl<'a> Dashboard<'a> {
+    pub fn new(context: &'a mut ConfigWrapper, refresh_secs: u64) -> Dashboard<'a> {
+        let skew = ClockSkew::measure(&context.api_config).unwrap_or_default();
+        let seen_entries = context.ledger.entries.len().saturating_sub(10);
+
+        let refresh = Duration::from_secs(refresh_secs.max(MIN_REFRESH_SECS));
+        Dashboard {
+            context,
+            refresh,
+            effective: refresh,
+            requests: 0,
+            skew,
+            agent: None,
+            ships: Vec::new(),
+            cooldowns: HashMap::new(),
+            contracts: Vec::new(),
+            log: VecDeque::new(),
+            seen_entries,
+        }
+    }
+
+    pub fn run(&mut self) -> Result<String, String> {
+        screen::run(self, "the dashboard").map(|_| "Closed the dashboard".to_string())
+    }
+
+    fn note_changes(&mut self, ship: &Ship) {
+        let previous = match self.ships.iter().find(|s| s.symbol == ship.symbol) {
+            Some(previous) => previous,
+            None => return
+        };
+
+        let mut changes = Vec::new();
+        if previous.nav.status != ship.nav.status || previous.nav.waypoint_symbol != ship.nav.waypoint_symbol {
+            changes.push(format!("{} {} at {}", ship.symbol, ship.nav.status.to_string(), ship.nav.waypoint_symbol));
+        }
+        if previous.cargo.units != ship.cargo.units {
+            changes.push(format!("{} cargo {}/{}", ship.symbol, ship.cargo.units, ship.cargo.capacity));
+        }
+
+        for change in changes {
+            self.event(change);
+        }
+    }
+
+    fn event(&mut self, msg: String) {
+        self.log.push_front(format!("{} {}", Local::now().format("%H:%M:%S"), msg));
+        self.log.truncate(LOG_LENGTH);
+    }
+
+    fn countdown(&self, server_time: DateTime<Utc>) -> String {
+        let secs = self.skew.until(server_time).as_secs();
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+
+    fn table(&self, width: u16) -> Table {
+        let mut table = Table::new();
+        table.load_preset(UTF8_FULL_CONDENSED)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_width(width);
+        table
+    }
+}
+
+impl Screen for Dashboard<'_> {
+    fn refresh_interval(&self) -> Duration {
+        self.effective
+    }
+
+    fn refresh(&mut self) {
+        let before = self.context.api_config.client.rate_limiter().requests();
+
+        match agents_api::get_my_agent(&self.context.api_config) {
+            Ok(resp) => {
+                let agent = *resp.data;
+                if let Some(previous) = &self.agent {
+                    if previous.credits != agent.credits {
+                        self.event(format!("Credits {:+} to {}", agent.credits - previous.credits, agent.credits));
+                    }
+                }
+                self.context.ledger.observe_balance(agent.credits);
+                self.agent = Some(agent);
+            },
+            Err(e) => self.event(format!("Failed to get agent data {}", e))
+        }
+
+        match networth::fetch_all_ships(self.context) {
+            Ok(ships) => {
+                for ship in &ships {
+                    self.note_changes(ship);
+                }
+                self.ships = ships;
+            },
+            Err(e) => self.event(e)
+        }
+
+        // A ship can't start another cooldown before the one it's on ends, so only ask about the rest
+        let now = self.skew.server_now();
+        let mut cooldowns: HashMap<String, DateTime<Utc>> = self.cooldowns.drain().filter(|(_, expiration)| *expiration > now).collect();
+        for ship in &self.ships {
+            if cooldowns.contains_key(&ship.symbol) {
+                continue;
+            }
+            if let Ok(Some(cooldown)) = wait::current_cooldown(&self.context.api_config, &ship.symbol) {
+                if let Some(expiration) = wait::parse_timestamp(&cooldown.expiration) {
+                    cooldowns.insert(ship.symbol.clone(), expiration);
+                }
+            }
+        }
+        cooldowns.retain(|symbol, _| self.ships.iter().any(|s| &s.symbol == symbol));
+        self.cooldowns = cooldowns;
+
+        match contracts_api::get_contracts(&self.context.api_config, None, None) {
+            Ok(resp) => self.contracts = resp.data.into_iter().filter(|c| c.accepted && !c.fulfilled).collect(),
+            Err(e) => self.event(format!("Failed to get contracts - {}", e))
+        }
+
+        // Trades, refuels and purchases made by anything else running on this agent
+        let entries: Vec<String> = self.context.ledger.entries.iter().skip(self.seen_entries)
+            .map(|e| format!("{} {} {} {}", e.ship.clone().unwrap_or_default(), e.cause, e.trade_symbol.clone().unwrap_or_default(), e.amount))
+            .collect();
+        self.seen_entries = self.context.ledger.entries.len();
+        for entry in entries {
+            self.event(entry);
+        }
+
+        let limiter = self.context.api_config.client.rate_limiter();
+        self.requests = limiter.requests() - before;
+        self.effective = self.refresh.max(screen::rate_floor(self.requests, limiter.per_second()));
+    }
+
+    fn draw(&self, width: u16, height: u16, next: Duration) -> std::io::Result<()> {
+        let mut lines = Vec::new();
+
+        lines.push(match &self.agent {
+            Some(agent) => format!(" {}  HQ {}  Credits {}  Ships {}  Contracts {}", agent.symbol, agent.headquarters, agent.credits,
+                                   self.ships.len(), self.contracts.len()),
+            None => " Waiting for agent data".to_string()
+        });
+        let mut status = format!(" Refreshing in {}s  {} requests  [r] refresh now  [q] quit", next.as_secs(), self.requests);
+        if self.effective > self.refresh {
+            status.push_str(&format!("  (slowed to {}s for the rate limit)", self.effective.as_secs()));
+        }
+        lines.push(status);
+
+        let mut ships = self.table(width);
+        ships.set_header(vec!["Ship", "Status", "Location", "Destination", "ETA", "Fuel", "Cargo", "Cooldown"]);
+        for ship in &self.ships {
+            let in_transit = ship.nav.status == ShipNavStatus::InTransit;
+            let eta = match wait::parse_timestamp(&ship.nav.route.arrival) {
+                Some(arrival) if in_transit => self.countdown(arrival),
+                _ => String::new()
+            };
+            let cooldown = self.cooldowns.get(&ship.symbol).map(|c| self.countdown(*c)).unwrap_or_default();
+
+            ships.add_row(vec![
+                ship.symbol.clone(),
+                ship.nav.status.to_string(),
+                ship.nav.waypoint_symbol.clone(),
+                if in_transit { ship.nav.route.destination.symbol.clone() } else { String::new() },
+                eta,
+                format!("{}/{}", ship.fuel.current, ship.fuel.capacity),
+                format!("{}/{}", ship.cargo.units, ship.cargo.capacity),
+                cooldown,
+            ]);
+        }
+        lines.extend(ships.to_string().lines().map(|l| l.to_string()));
+
+        let mut contracts = self.table(width);
+        contracts.set_header(vec!["Contract", "Deliver", "Progress", "Deadline"]);
+        for contract in &self.contracts {
+            for good in contract.terms.deliver.iter().flatten() {
+                contracts.add_row(vec![
+                    contract.id.clone(),
+                    format!("{} to {}", good.trade_symbol, good.destination_symbol),
+                    format!("{}/{}", good.units_fulfilled, good.units_required),
+                    wait::parse_timestamp(&contract.terms.deadline).map(|d| d.with_timezone(&Local).format("%d %b %H:%M").to_string()).unwrap_or_default(),
+                ]);
+            }
+        }
+        lines.extend(contracts.to_string().lines().map(|l| l.to_string()));
+
+        lines.push(" Activity".to_string());
+        let remaining = (height as usize).saturating_sub(lines.len());
+        lines.extend(self.log.iter().take(remaining).map(|l| format!(" {}", l)));
+
+        let mut out = stdout();
+        for (row, line) in lines.iter().take(height as usize).enumerate() {
+            let line: String = line.chars().take(width as usize).collect();
+            queue!(out, MoveTo(0, row as u16), Clear(ClearType::UntilNewLine), Print(line))?;
+        }
+        queue!(out, MoveTo(0, lines.len().min(height as usize) as u16), Clear(ClearType::FromCursorDown))?;
+        out.flush()
+    }
+}
@@ -0,0 +1,286 @@
+use spacetraders_sdk::apis::fleet_api;
+use spacetraders_sdk::models::ship_mount::Symbol as ShipMountSymbol;
+use spacetraders_sdk::models::waypoint_trait::Symbol as WaypointTraitSymbol;
+use spacetraders_sdk::models::{NavigateShipRequest, Ship, ShipNavFlightMode, ShipNavStatus, Waypoint};
+use spacetraders_sdk::wait::{self, ClockSkew};
+
+use crate::cache;
+use crate::config::ConfigWrapper;
+use crate::navigation;
+
+/// Tours the uncharted waypoints of a system, charting each one and scanning along the way.
+/// Everything learnt is written back to the universe cache.
+pub struct Explorer<'a> {
+    context: &'a mut ConfigWrapper,
+    ship: Ship,
+    skew: ClockSkew,
+    charted: i32,
+    scanned_waypoints: usize,
+    scanned_systems: usize,
+}
+
+impl<'a> Explorer<'a> {
+    pub fn new(context: &'a mut ConfigWrapper, ship_symbol: &str) -> Result<Explorer<'a>, String> {
+        let ship = match fleet_api::get_my_ship(&context.api_config, ship_symbol) {
+            Ok(resp) => *resp.data,
+            Err(e) => return Err(format!("Failed to get ship {} - {}", ship_symbol, e))
+        };
+
+        let skew = ClockSkew::measure(&context.api_config).unwrap_or_default();
+
+        Ok(Explorer { context, ship, skew, charted: 0, scanned_waypoints: 0, scanned_systems: 0 })
+    }
+
+    /// Chart up to `limit` waypoints (0 for all) in the ship's current system
+    pub fn run(&mut self, limit: usize) -> Result<String, String> {
+        let api_config = self.context.api_config.clone();
+        let system = self.ship.nav.system_symbol.clone();
+
+        let mut tour = plan_tour(self.position()?, self.context.cache.uncharted_waypoints(&api_config, &system)?);
+        if limit > 0 {
+            tour.truncate(limit);
+        }
+
+        if tour.is_empty() {
+            return Ok(format!("Every known waypoint in {} has been charted", system));
+        }
+
+        self.progress(format!("Touring {} uncharted waypoints: {}", tour.len(),
+                              tour.iter().map(|w| w.symbol.clone()).collect::<Vec<String>>().join(" -> ")));
+
+        self.scan_systems();
+
+        for waypoint in tour {
+            self.travel_to(&waypoint.symbol)?;
+            self.chart();
+            self.scan_waypoints();
+        }
+
+        Ok(format!("{} charted {} waypoints, scanned {} waypoints and {} systems",
+                   self.ship.symbol, self.charted, self.scanned_waypoints, self.scanned_systems))
+    }
+
+    fn chart(&mut self) {
+        match fleet_api::create_chart(&self.context.api_config, &self.ship.symbol) {
+            Ok(resp) => {
+                self.progress(format!("Charted {}", resp.data.waypoint.symbol));
+                self.context.cache.record_waypoint(*resp.data.waypoint);
+                self.charted += 1;
+            },
+            // Most likely someone else got here first, which is worth knowing but not fatal
+            Err(e) => self.progress(format!("Failed to chart {} - {}", self.ship.nav.waypoint_symbol, e))
+        }
+    }
+
+    fn scan_waypoints(&mut self) {
+        if !self.can_scan() {
+            return;
+        }
+
+        match fleet_api::create_ship_waypoint_scan(&self.context.api_config, &self.ship.symbol) {
+            Ok(resp) => {
+                let scanned = resp.data.waypoints;
+                self.progress(format!("Scanned {} waypoints", scanned.len()));
+                self.scanned_waypoints += scanned.len();
+                self.context.cache.record_scanned_waypoints(scanned);
+            },
+            Err(e) => self.progress(format!("Failed to scan waypoints - {}", e))
+        }
+    }
+
+    fn scan_systems(&mut self) {
+        if !self.can_scan() {
+            return;
+        }
+
+        match fleet_api::create_ship_system_scan(&self.context.api_config, &self.ship.symbol) {
+            Ok(resp) => {
+                let scanned = resp.data.systems;
+                self.progress(format!("Scanned {} systems", scanned.len()));
+                self.scanned_systems += scanned.len();
+                self.context.cache.record_scanned_systems(scanned);
+            },
+            Err(e) => self.progress(format!("Failed to scan systems - {}", e))
+        }
+    }
+
+    // Scans need a sensor array and share one cooldown, which we skip rather than wait out
+    fn can_scan(&self) -> bool {
+        let has_sensor = self.ship.mounts.iter()
+            .any(|m| matches!(m.symbol, ShipMountSymbol::SensorArrayI | ShipMountSymbol::SensorArrayIi | ShipMountSymbol::SensorArrayIii));
+
+        has_sensor && matches!(wait::current_cooldown(&self.context.api_config, &self.ship.symbol), Ok(None))
+    }
+
+    fn travel_to(&mut self, waypoint: &str) -> Result<(), String> {
+        if self.ship.nav.waypoint_symbol == waypoint && self.ship.nav.status != ShipNavStatus::InTransit {
+            return Ok(());
+        }
+
+        self.refuel();
+
+        if let Some(stop) = self.refuel_stop(waypoint)? {
+            self.progress(format!("Stopping at {} for fuel on the way to {}", stop, waypoint));
+            self.navigate(&stop)?;
+            self.refuel();
+        }
+
+        self.navigate(waypoint)
+    }
+
+    // Where to refuel on the way to `waypoint`, if what's in the tank won't get us there
+    fn refuel_stop(&mut self, waypoint: &str) -> Result<Option<String>, String> {
+        // Ships without a tank, like probes, fly for free
+        if self.ship.fuel.capacity == 0 {
+            return Ok(None);
+        }
+
+        let api_config = self.context.api_config.clone();
+        let here = self.position()?;
+        let destination = self.context.cache.waypoint(&api_config, waypoint)?;
+        let destination = (destination.x, destination.y);
+        let needed = navigation::fuel_required(cache::distance(here, destination), ShipNavFlightMode::Cruise);
+        if self.ship.fuel.current >= needed {
+            return Ok(None);
+        }
+
+        let stations: Vec<(String, (i32, i32))> = self.context.cache.marketplaces(&api_config, &self.ship.nav.system_symbol)?
+            .into_iter()
+            .map(|w| (w.symbol, (w.x, w.y)))
+            .collect();
+
+        match navigation::refuel_stop(here, destination, self.ship.fuel.current, self.ship.fuel.capacity, &stations) {
+            Some(stop) => Ok(Some(stop.to_string())),
+            None => Err(format!("Not enough fuel to reach {}, it needs {} and the tank has {}, and no market in range to refuel at",
+                                waypoint, needed, self.ship.fuel.current))
+        }
+    }
+
+    fn navigate(&mut self, waypoint: &str) -> Result<(), String> {
+        if self.ship.nav.status == ShipNavStatus::Docked {
+            match fleet_api::orbit_ship(&self.context.api_config, &self.ship.symbol) {
+                Ok(resp) => self.ship.nav = resp.data.nav,
+                Err(e) => return Err(format!("Failed to orbit - {}", e))
+            }
+        }
+
+        match fleet_api::navigate_ship(&self.context.api_config, &self.ship.symbol, Some(NavigateShipRequest::new(waypoint.to_string()))) {
+            Ok(resp) => {
+                self.ship.nav = resp.data.nav;
+                self.ship.fuel = resp.data.fuel;
+            },
+            Err(e) => return Err(format!("Failed to navigate to {} - {}", waypoint, e))
+        }
+
+        self.progress(format!("Navigating to {}, arriving at {}", waypoint, self.ship.nav.route.arrival));
+
+        match wait::wait_for_arrival(&self.context.api_config, &self.ship.symbol, &self.ship.nav, &self.skew) {
+            Ok(nav) => {
+                *self.ship.nav = nav;
+                Ok(())
+            },
+            Err(e) => Err(format!("Failed to get nav status of {} - {}", self.ship.symbol, e))
+        }
+    }
+
+    // Fill up before leaving a marketplace, since the next stops may not sell fuel
+    fn refuel(&mut self) {
+        if self.ship.fuel.capacity == 0 || self.ship.fuel.current >= self.ship.fuel.capacity {
+            return;
+        }
+
+        let api_config = self.context.api_config.clone();
+        match self.context.cache.waypoint(&api_config, &self.ship.nav.waypoint_symbol) {
+            Ok(w) if cache::has_trait(&w, WaypointTraitSymbol::Marketplace) => (),
+            _ => return
+        }
+
+        if self.ship.nav.status != ShipNavStatus::Docked {
+            match fleet_api::dock_ship(&api_config, &self.ship.symbol) {
+                Ok(resp) => self.ship.nav = resp.data.nav,
+                Err(e) => return self.progress(format!("Failed to dock - {}", e))
+            }
+        }
+
+        match fleet_api::refuel_ship(&api_config, &self.ship.symbol) {
+            Ok(resp) => {
+                let price = self.context.cache.purchase_price(&self.ship.nav.waypoint_symbol, "FUEL");
+                self.context.ledger.record_refuel("explorer", &self.ship.symbol, Some(resp.data.fuel.current - self.ship.fuel.current),
+                                                  price, resp.data.agent.credits);
+                self.ship.fuel = resp.data.fuel;
+                self.progress(format!("Refuelled to {}/{}", self.ship.fuel.current, self.ship.fuel.capacity));
+            },
+            Err(e) => self.progress(format!("Failed to refuel - {}", e))
+        }
+    }
+
+    fn position(&mut self) -> Result<(i32, i32), String> {
+        let api_config = self.context.api_config.clone();
+        let waypoint = self.context.cache.waypoint(&api_config, &self.ship.nav.waypoint_symbol)?;
+        Ok((waypoint.x, waypoint.y))
+    }
+
+    fn progress(&self, msg: String) {
+        println!("[{}] {}", self.ship.symbol, msg);
+    }
+}
+
+/// Order waypoints by always visiting the nearest unvisited one next, the first by symbol
+/// when several are as near, so the same waypoints always make the same tour
+pub fn plan_tour(start: (i32, i32), mut remaining: Vec<Waypoint>) -> Vec<Waypoint> {
+    let mut tour = Vec::with_capacity(remaining.len());
+    let mut here = start;
+
+    while !remaining.is_empty() {
+        let (next, _) = remaining.iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| cache::distance(here, (a.x, a.y)).total_cmp(&cache::distance(here, (b.x, b.y)))
+                .then_with(|| a.symbol.cmp(&b.symbol)))
+            .unwrap();
+
+        let waypoint = remaining.swap_remove(next);
+        here = (waypoint.x, waypoint.y);
+        tour.push(waypoint);
+    }
+
+    tour
+}
+
+#[cfg(test)]
+mod tests {
+    use spacetraders_sdk::models::WaypointType;
+
+    use super::*;
+
+    fn waypoint(symbol: &str, x: i32, y: i32) -> Waypoint {
+        Waypoint::new(symbol.to_string(), WaypointType::Planet, "X1-AB".to_string(), x, y, Vec::new(), Vec::new())
+    }
+
+    fn symbols(tour: &[Waypoint]) -> Vec<&str> {
+        tour.iter().map(|w| w.symbol.as_str()).collect()
+    }
+
+    #[test]
+    fn tour_visits_the_nearest_waypoint_next() {
+        let waypoints = vec![waypoint("FAR", 100, 0), waypoint("MID", 50, 0), waypoint("NEAR", 10, 0), waypoint("BEHIND", -20, 0)];
+
+        // Doubling back to the one behind is shorter than pressing on, rather than going in a straight line
+        assert_eq!(symbols(&plan_tour((0, 0), waypoints.clone())), vec!["NEAR", "BEHIND", "MID", "FAR"]);
+        assert_eq!(symbols(&plan_tour((100, 0), waypoints)), vec!["FAR", "MID", "NEAR", "BEHIND"]);
+    }
+
+    #[test]
+    fn tour_of_nothing_is_empty() {
+        assert!(plan_tour((0, 0), Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn ties_go_to_the_first_symbol_whatever_the_order() {
+        let waypoints = vec![waypoint("B", 0, 10), waypoint("C", -10, 0), waypoint("A", 10, 0)];
+        let mut reversed = waypoints.clone();
+        reversed.reverse();
+
+        assert_eq!(symbols(&plan_tour((0, 0), waypoints)), vec!["A", "B", "C"]);
+        assert_eq!(symbols(&plan_tour((0, 0), reversed)), vec!["A", "B", "C"]);
+    }
+}
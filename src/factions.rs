@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use comfy_table::Table;
+use serde_json::Value as JsonValue;
+use spacetraders_sdk::apis::factions_api;
+use spacetraders_sdk::models::register_request::Faction as RegisterFaction;
+use spacetraders_sdk::models::Faction;
+
+use crate::actions::failed;
+use crate::commands::{command, CommandError, CommandResult, CommandSpec, Value};
+use crate::config::ConfigWrapper;
+use crate::output::{self, Format};
+use crate::shell::variants;
+
+const PAGE_LIMIT: i32 = 20;
+
+// The server's limits on an agent's symbol
+const CALLSIGN_MIN: usize = 3;
+const CALLSIGN_MAX: usize = 14;
+
+pub fn commands() -> Vec<CommandSpec> {
+    vec![
+        command!("factions", list_factions)
+            .with_path("faction list")
+            .with_help("List every faction with its headquarters and traits")
+            .read_only(),
+        command!("faction", show_faction)
+            .with_path("faction show")
+            .with_help("Show a faction's description, headquarters and what each of its traits means")
+            .read_only()
+            .required("faction"),
+    ]
+}
+
+/// The faction a new agent can join, matched case-insensitively against the ones the server accepts
+pub fn registrable(name: &str) -> Result<RegisterFaction, CommandError> {
+    let symbol = name.trim().to_uppercase();
+    serde_json::from_value::<RegisterFaction>(JsonValue::String(symbol.clone()))
+        .map_err(|_| format!("Can't register with faction {}, expected one of {}", symbol, variants::<RegisterFaction>().join(", ")).into())
+}
+
+/// Check a callsign against what the server will accept, so a bad one fails before any request
+pub fn callsign(name: &str) -> Result<String, CommandError> {
+    let name = name.trim();
+    let length = name.chars().count();
+    if !(CALLSIGN_MIN..=CALLSIGN_MAX).contains(&length) {
+        return Err(format!("Callsign must be {} to {} characters long, {} is {}", CALLSIGN_MIN, CALLSIGN_MAX, name, length).into());
+    }
+    if let Some(c) = name.chars().find(|c| !c.is_ascii_alphanumeric() && *c != '-' && *c != '_') {
+        return Err(format!("Callsign can only use letters, digits, - and _, {} has '{}'", name, c).into());
+    }
+    Ok(name.to_string())
+}
+
+fn fetch_all_factions(context: &ConfigWrapper) -> Result<Vec<Faction>, CommandError> {
+    let mut factions = Vec::new();
+    let mut page = 1;
+    loop {
+        let resp = factions_api::get_factions(&context.api_config, Some(page), Some(PAGE_LIMIT))
+            .map_err(|e| failed("get factions", e))?;
+        let count = resp.data.len();
+        factions.extend(resp.data);
+        if count < PAGE_LIMIT as usize || factions.len() >= resp.meta.total as usize {
+            return Ok(factions);
+        }
+        page += 1;
+    }
+}
+
+fn list_factions(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let factions = fetch_all_factions(context)?;
+    if context.output != Format::Table {
+        return Ok(Some(output::render(context.output, Table::new(), &factions)));
+    }
+
+    let joinable = variants::<RegisterFaction>();
+    let mut table = Table::new();
+    table.set_header(vec!["Symbol", "Name", "Headquarters", "Joinable", "Traits"]);
+    for faction in &factions {
+        let traits: Vec<&str> = faction.traits.iter().map(|t| t.name.as_str()).collect();
+        table.add_row(vec![faction.symbol.clone(), faction.name.clone(), faction.headquarters.clone(),
+                           joinable.contains(&faction.symbol.as_str()).to_string(), traits.join(", ")]);
+    }
+    Ok(Some(table.to_string()))
+}
+
+fn show_faction(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let symbol = args.get("faction").unwrap().to_string().trim().to_uppercase();
+
+    let faction = factions_api::get_faction(&context.api_config, &symbol)
+        .map_err(|e| failed(&format!("get faction {}", symbol), e))?
+        .data;
+    if context.output != Format::Table {
+        return Ok(Some(output::render(context.output, Table::new(), &[faction])));
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["Trait", "Description"]);
+    for t in &faction.traits {
+        table.add_row(vec![t.name.clone(), t.description.clone()]);
+    }
+
+    Ok(Some(format!("{} ({})\nHeadquarters {}\n{}\n{}", faction.name, faction.symbol, faction.headquarters, faction.description, table)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callsigns_are_checked_before_registering() {
+        assert_eq!(callsign("  ACE_pilot-7 ").unwrap(), "ACE_pilot-7");
+        assert_eq!(callsign("AB").unwrap_err().0, "Callsign must be 3 to 14 characters long, AB is 2");
+        assert!(callsign("FIFTEEN_LETTERS").is_err());
+        assert_eq!(callsign("ACE PILOT").unwrap_err().0, "Callsign can only use letters, digits, - and _, ACE PILOT has ' '");
+    }
+
+    #[test]
+    fn factions_match_regardless_of_case() {
+        assert_eq!(registrable(" cosmic ").unwrap(), RegisterFaction::Cosmic);
+        assert!(registrable("NOBODY").unwrap_err().0.starts_with("Can't register with faction NOBODY, expected one of COSMIC, "));
+    }
+}
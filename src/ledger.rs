@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use spacetraders_sdk::models::market_transaction::RHashType as TransactionType;
+use spacetraders_sdk::models::{MarketTransaction, ShipyardTransaction};
+
+use crate::config::profile_file;
+use crate::navigation::FUEL_UNITS_PER_MARKET_UNIT;
+
+pub const LEDGER_FILE: &str = "spacetraders_ledger.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Cause {
+    Trade,
+    Refuel,
+    ShipPurchase,
+    ContractPayment,
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cause::Trade => write!(f, "trade"),
+            Cause::Refuel => write!(f, "refuel"),
+            Cause::ShipPurchase => write!(f, "ship purchase"),
+            Cause::ContractPayment => write!(f, "contract payment"),
+        }
+    }
+}
+
+/// One change to our credits.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LedgerEntry {
+    pub timestamp: String,
+    pub cause: Cause,
+    pub ship: Option<String>,
+    // What the ship was doing at the time, e.g. `contract:<id>`, `convoy` or `manual`
+    pub activity: String,
+    pub trade_symbol: Option<String>,
+    pub units: i32,
+    // Positive for income, negative for spending
+    pub amount: i32,
+    // Agent credits after the change
+    pub balance: i32,
+    // The amount is a guess, see `record_refuel`
+    #[serde(default)]
+    pub estimated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Grouping {
+    Ship,
+    Good,
+    Day,
+    Activity,
+}
+
+impl FromStr for Grouping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ship" => Ok(Grouping::Ship),
+            "good" => Ok(Grouping::Good),
+            "day" => Ok(Grouping::Day),
+            "activity" => Ok(Grouping::Activity),
+            _ => Err(format!("Unknown grouping {}, expected ship, good, day or activity", s))
+        }
+    }
+}
+
+/// Income and spending for one group in a report.
+#[derive(Debug, Clone, Default)]
+pub struct ProfitAndLoss {
+    pub income: i64,
+    pub expenses: i64,
+    pub entries: usize,
+}
+
+impl ProfitAndLoss {
+    pub fn net(&self) -> i64 {
+        self.income - self.expenses
+    }
+}
+
+/// Every credit change we've caused, persisted so reports cover more than one session.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Ledger {
+    pub entries: Vec<LedgerEntry>,
+    // Credits when we last heard from the server, for changes that don't report their own amount
+    last_balance: Option<i32>,
+    // The profile's own file, see `profile_file`
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Ledger {
+    pub fn load(profile: &str) -> Ledger {
+        let path = profile_file(LEDGER_FILE, profile);
+
+        let mut loaded = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file)).unwrap_or_default(),
+            Err(_) => Ledger::default()
+        };
+        loaded.path = path;
+        loaded
+    }
+
+    pub fn save(&self) {
+        if self.path.as_os_str().is_empty() {
+            return;
+        }
+
+        if let Err(e) = std::fs::write(&self.path, serde_json::to_string(self).unwrap()) {
+            println!("Failed to save ledger {}", e);
+        }
+    }
+
+    /// Note the current credits without recording a change, e.g. after fetching the agent
+    pub fn observe_balance(&mut self, balance: i32) {
+        if self.last_balance != Some(balance) {
+            self.last_balance = Some(balance);
+            self.save();
+        }
+    }
+
+    pub fn record_trade(&mut self, activity: &str, transaction: &MarketTransaction, balance: i32) {
+        let amount = match transaction.r#type {
+            TransactionType::Purchase => -transaction.total_price,
+            TransactionType::Sell => transaction.total_price,
+        };
+
+        self.record(LedgerEntry {
+            timestamp: transaction.timestamp.clone(),
+            cause: Cause::Trade,
+            ship: Some(transaction.ship_symbol.clone()),
+            activity: activity.to_string(),
+            trade_symbol: Some(transaction.trade_symbol.clone()),
+            units: transaction.units,
+            amount,
+            balance,
+            estimated: false,
+        });
+    }
+
+    /// Refuel responses carry no transaction, so the cost is worked out from the fuel `added` to the tank
+    /// and the market's `price` for it. Without both it falls back to the drop in credits since we last
+    /// saw them, which is marked as estimated: it's 0 if we never saw them, and takes in anything else
+    /// spent since, e.g. from the website or another client.
+    pub fn record_refuel(&mut self, activity: &str, ship: &str, added: Option<i32>, price: Option<i32>, balance: i32) {
+        let (units, amount, estimated) = refuel_cost(added, price, self.last_balance.map(|last| balance - last));
+
+        self.record(LedgerEntry {
+            timestamp: Utc::now().to_rfc3339(),
+            cause: Cause::Refuel,
+            ship: Some(ship.to_string()),
+            activity: activity.to_string(),
+            trade_symbol: Some("FUEL".to_string()),
+            units,
+            amount,
+            balance,
+            estimated,
+        });
+    }
+
+    pub fn record_ship_purchase(&mut self, activity: &str, ship: &str, transaction: &ShipyardTransaction, balance: i32) {
+        self.record(LedgerEntry {
+            timestamp: transaction.timestamp.clone(),
+            cause: Cause::ShipPurchase,
+            ship: Some(ship.to_string()),
+            activity: activity.to_string(),
+            trade_symbol: None,
+            units: 1,
+            amount: -transaction.price,
+            balance,
+            estimated: false,
+        });
+    }
+
+    pub fn record_contract_payment(&mut self, contract: &str, ship: Option<&str>, amount: i32, balance: i32) {
+        self.record(LedgerEntry {
+            timestamp: Utc::now().to_rfc3339(),
+            cause: Cause::ContractPayment,
+            ship: ship.map(|s| s.to_string()),
+            activity: format!("contract:{}", contract),
+            trade_symbol: None,
+            units: 0,
+            amount,
+            balance,
+            estimated: false,
+        });
+    }
+
+    fn record(&mut self, entry: LedgerEntry) {
+        self.last_balance = Some(entry.balance);
+        self.entries.push(entry);
+        self.save();
+    }
+
+    /// Income and spending grouped by ship, good, day or activity, in key order
+    pub fn report(&self, grouping: Grouping) -> BTreeMap<String, ProfitAndLoss> {
+        let mut report: BTreeMap<String, ProfitAndLoss> = BTreeMap::new();
+
+        for entry in &self.entries {
+            let key = match grouping {
+                Grouping::Ship => entry.ship.clone().unwrap_or_else(|| "-".to_string()),
+                Grouping::Good => entry.trade_symbol.clone().unwrap_or_else(|| entry.cause.to_string()),
+                Grouping::Day => entry.timestamp.chars().take(10).collect(),
+                Grouping::Activity => entry.activity.clone(),
+            };
+
+            let pnl = report.entry(key).or_default();
+            if entry.amount >= 0 {
+                pnl.income += i64::from(entry.amount);
+            } else {
+                pnl.expenses -= i64::from(entry.amount);
+            }
+            pnl.entries += 1;
+        }
+
+        report
+    }
+}
+
+// Market units of fuel a refuel bought, what they cost and whether that's a guess
+fn refuel_cost(added: Option<i32>, price: Option<i32>, balance_change: Option<i32>) -> (i32, i32, bool) {
+    // Part units are charged in full
+    let units = added.map(|added| (added.max(0) + FUEL_UNITS_PER_MARKET_UNIT - 1) / FUEL_UNITS_PER_MARKET_UNIT);
+
+    match (units, price) {
+        (Some(units), Some(price)) => (units, -units * price, false),
+        _ => {
+            let amount = balance_change.unwrap_or(0);
+            let units = units.or_else(|| price.filter(|p| *p > 0).map(|p| -amount / p)).unwrap_or(0);
+            (units, amount, true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ship: &str, good: Option<&str>, day: &str, amount: i32) -> LedgerEntry {
+        LedgerEntry {
+            timestamp: format!("{}T12:00:00Z", day),
+            cause: if good.is_some() { Cause::Trade } else { Cause::ContractPayment },
+            ship: Some(ship.to_string()),
+            activity: "manual".to_string(),
+            trade_symbol: good.map(|g| g.to_string()),
+            units: 1,
+            amount,
+            balance: 0,
+            estimated: false,
+        }
+    }
+
+    #[test]
+    fn refuels_are_costed_from_fuel_added_and_price() {
+        // 250 fuel is three market units, the last one charged in full
+        assert_eq!(refuel_cost(Some(250), Some(72), Some(-5000)), (3, -216, false));
+        assert_eq!(refuel_cost(Some(0), Some(72), None), (0, 0, false));
+    }
+
+    #[test]
+    fn refuels_without_a_price_are_estimated_from_credits() {
+        assert_eq!(refuel_cost(Some(250), None, Some(-220)), (3, -220, true));
+        assert_eq!(refuel_cost(None, Some(70), Some(-210)), (3, -210, true));
+        assert_eq!(refuel_cost(None, None, None), (0, 0, true));
+    }
+
+    #[test]
+    fn refuel_falls_back_to_the_last_balance_seen() {
+        let mut ledger = Ledger::default();
+        ledger.observe_balance(1000);
+        ledger.record_refuel("manual", "SHIP-1", None, None, 900);
+
+        let refuel = ledger.entries.last().unwrap();
+        assert_eq!((refuel.amount, refuel.estimated), (-100, true));
+        assert_eq!(ledger.last_balance, Some(900));
+    }
+
+    #[test]
+    fn reports_split_income_from_expenses() {
+        let ledger = Ledger {
+            entries: vec![
+                entry("SHIP-1", Some("IRON_ORE"), "2026-01-01", 500),
+                entry("SHIP-1", Some("FUEL"), "2026-01-01", -120),
+                entry("SHIP-2", Some("IRON_ORE"), "2026-01-02", 300),
+                entry("SHIP-2", None, "2026-01-02", 10000),
+            ],
+            ..Ledger::default()
+        };
+
+        let by_ship = ledger.report(Grouping::Ship);
+        assert_eq!((by_ship["SHIP-1"].income, by_ship["SHIP-1"].expenses, by_ship["SHIP-1"].net()), (500, 120, 380));
+        assert_eq!(by_ship["SHIP-2"].entries, 2);
+
+        let by_good = ledger.report(Grouping::Good);
+        assert_eq!(by_good["IRON_ORE"].income, 800);
+        assert_eq!(by_good["contract payment"].income, 10000);
+
+        let by_day: Vec<String> = ledger.report(Grouping::Day).into_keys().collect();
+        assert_eq!(by_day, vec!["2026-01-01", "2026-01-02"]);
+    }
+
+    #[test]
+    fn groupings_parse_case_insensitively() {
+        assert_eq!("Ship".parse::<Grouping>(), Ok(Grouping::Ship));
+        assert!("colour".parse::<Grouping>().is_err());
+    }
+}
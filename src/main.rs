@@ -1,32 +1,58 @@
 mod config;
 mod cli;
+mod cache;
+mod contracts;
+mod navigation;
+mod scheduler;
+mod refinery;
+mod convoy;
+mod explorer;
+mod ledger;
+mod networth;
+mod secrets;
+mod commands;
+mod output;
+mod script;
+mod dashboard;
+mod map;
+mod mapexport;
+mod actions;
+mod shell;
+mod screen;
+mod factions;
+mod watch;
 
-use std::any::Any;
 use std::collections::HashMap;
 
-use std::fs::File;
-use std::io::{BufReader};
 use std::path::Path;
-use repl_rs::{Command, Parameter, Repl, Result as ReplResult, Value};
 use config::GameConfig;
 use inquire::{Confirm};
 use reqwest::StatusCode;
 
-use spacetraders_sdk::models::register_request::Faction;
-use spacetraders_sdk::models::RegisterRequest;
+use spacetraders_sdk::models::{PurchaseShipRequest, RegisterRequest, ShipModule, ShipType, ShipyardShip, TradeGood};
+use spacetraders_sdk::wait;
+use spacetraders_sdk::models::ship_module::Symbol as ShipModuleSymbol;
 use chrono::{DateTime, Utc};
-use clap::Parser;
+use clap::FromArgMatches;
 use comfy_table::Table;
 
 
+use crate::cache::MarketSnapshot;
 use crate::cli::Args;
-use crate::config::ConfigWrapper;
+use crate::commands::{command, CommandResult, CommandSpec, Value};
+use crate::config::{AgentProfile, ConfigOverrides, ConfigWrapper};
+use crate::contracts::ContractRunner;
+use crate::convoy::Convoy;
+use crate::explorer::Explorer;
+use crate::ledger::Grouping;
+use crate::refinery::Refinery;
+use crate::scheduler::Task;
 
 // fn handle_error(msg: String, error: Box<dyn std::error::Error>) -> ReplResult<Option<String>> {
 //     Ok(Some(format!(msg, error)))
 // }
 
-fn check_server(_args: HashMap<String, Value>, _context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
+fn check_server(_args: HashMap<String, Value>, _context: &mut ConfigWrapper) -> CommandResult {
     let res = reqwest::blocking::get("https://api.spacetraders.io/v2/").unwrap();
 
     let status = match res.status() {
@@ -39,29 +65,35 @@ fn check_server(_args: HashMap<String, Value>, _context: &mut ConfigWrapper) ->
     Ok(Some(status))
 }
 
-fn register(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
-    let call_sign = args.get("callsign").unwrap();
+fn register(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let call_sign = factions::callsign(&args.get("callsign").unwrap().to_string())?;
+    let faction = factions::registrable(&args.get("faction").unwrap().to_string())?;
 
-    let req = RegisterRequest::new(Faction::Cosmic, call_sign.to_string());
+    let req = RegisterRequest::new(faction, call_sign.clone());
 
     match spacetraders_sdk::apis::default_api::register(&context.api_config, Some(req)) {
         Ok(resp) => {
+            // Keep the agent we were playing as in its own profile, and make the new one active
+            context.user_config.store_active_profile();
             context.user_config.access_token = resp.data.token.clone();
             context.user_config.call_sign = call_sign.to_string();
             context.user_config.faction = resp.data.faction.name;
+            context.user_config.headquarters = String::new();
+            context.user_config.active_profile = call_sign.to_string().to_lowercase();
+            context.user_config.store_active_profile();
 
-            context.clone().update();
+            context.update();
 
-            Ok(Some(format!("Successfully registered. Got token ( this will be saved on exit ) {}", resp.data.token)))
+            Ok(Some("Successfully registered. Got token ( this will be saved on exit )".to_string()))
         },
         Err(e) => {
-            Ok(Some(format!("Failed to register player {}", e)))
+            Err(format!("Failed to register player {}", e).into())
         }
     }
 }
 
-fn exit(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
-    GameConfig::save(&context.user_config);
+fn exit(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    context.save();
     std::process::exit(0);
 }
 
@@ -80,7 +112,7 @@ fn _exit(msg: String) {
         .expect("").expect("");
 }
 
-fn read_config(config_file: String) -> Result<GameConfig, std::io::Error> {
+fn read_config(config_file: String) -> Result<GameConfig, String> {
 
     let config_path = Path::new(&config_file);
 
@@ -104,19 +136,10 @@ fn read_config(config_file: String) -> Result<GameConfig, std::io::Error> {
         }
     }
 
-    let file = File::open(config_path)?;
-
-    let reader = BufReader::new(file);
-
-    match serde_json::from_reader(reader) {
-        Ok(c) => Ok(c),
-        Err(e) => {
-            Err(e.into())
-        }
-    }
+    GameConfig::load(config_path)
 }
 
-fn new_config(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
+fn new_config(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
 
     let confirmation = Confirm::new("Are you sure you want to create a new config? (Existing config will be backed up) ")
         .with_default(false)
@@ -125,48 +148,132 @@ fn new_config(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> Rep
     match confirmation {
         Ok(true) => {
             // Save the current config
-            GameConfig::save(&context.user_config);
+            context.save();
+            let path = context.user_config.path.clone();
             let now: DateTime<Utc> = Utc::now();
             let formatted_now = now.format("%Y%m%d%H%M%S");
-            std::fs::rename("spacetraders.json", format!("spacetraders.json.{}", formatted_now)).unwrap();
-            GameConfig::save(&GameConfig::default());
-            Ok(Some("Success".to_string()))
+            if let Err(e) = std::fs::rename(&path, format!("{}.{}", path.display(), formatted_now)) {
+                return Err(format!("Failed to back up {} {}", path.display(), e).into());
+            }
+            // Carry on with the new config, or the next save would write the old one back over it
+            let fresh = context.user_config.fresh();
+            let started = format!("Started a new config, the old one is at {}.{}", path.display(), formatted_now);
+            match context.replace_config(fresh) {
+                Ok(()) => Ok(Some(started)),
+                Err(e) => Err(format!("{}, but {}", started, e).into())
+            }
         }
         Ok(false) => {
             Ok(Some("Existing config unchanged".to_string()))
         }
-        Err(e) => Ok(Some(format!("Failed to read prompt {}", e)))
+        Err(e) => Err(format!("Failed to read prompt {}", e).into())
     }
 
 }
 
-fn save_config(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
-    GameConfig::save(&context.user_config);
+fn save_config(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    context.save();
 
     Ok(Some("Saved Config".to_string()))
 }
 
-fn get_agent(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
+fn show_profiles(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    context.user_config.store_active_profile();
+
+    let mut table = Table::new();
+    table.set_header(vec!["Profile", "Call Sign", "Faction", "Headquarters", "Active"]);
+
+    for (name, profile) in &context.user_config.profiles {
+        let active = if *name == context.user_config.active_profile { "*" } else { "" };
+        table.add_row(vec![name.clone(), profile.call_sign.clone(), profile.faction.clone(), profile.headquarters.clone(), active.to_string()]);
+    }
+
+    // Built from the table rather than the profiles, which hold tokens
+    Ok(Some(output::render_table(context.output, table)))
+}
+
+fn add_profile(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let name = args.get("name").unwrap().to_string();
+    let token = args.get("token").unwrap().to_string();
+    let faction = args.get("faction").map(|f| f.to_string()).unwrap_or_default();
+
+    // Check the token works, and fill in the agent's details while we're at it
+    let mut api_config = context.api_config.clone();
+    api_config.bearer_access_token = Some(token.clone());
+
+    let agent = match spacetraders_sdk::apis::agents_api::get_my_agent(&api_config) {
+        Ok(resp) => resp.data,
+        Err(e) => return Err(format!("Failed to get agent data for that token {}", e).into())
+    };
+
+    let profile = AgentProfile { access_token: token, call_sign: agent.symbol.clone(), faction, headquarters: agent.headquarters };
+
+    match context.user_config.add_profile(&name, profile) {
+        Ok(()) => Ok(Some(format!("Added profile {} for {} ( this will be saved on exit )", name, agent.symbol))),
+        Err(e) => Err(e.into())
+    }
+}
+
+fn switch_profile(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let name = args.get("name").unwrap().to_string();
+
+    match context.switch_profile(&name) {
+        Ok(()) => Ok(Some(format!("Now playing as {}", context.user_config.call_sign))),
+        Err(e) => Err(e.into())
+    }
+}
+
+fn remove_profile(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let name = args.get("name").unwrap().to_string();
+
+    match context.remove_profile(&name) {
+        Ok(()) => Ok(Some(format!("Removed profile {}", name))),
+        Err(e) => Err(e.into())
+    }
+}
+
+fn set_token_store(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let backend = match args.get("backend") {
+        Some(backend) => backend.to_string().parse::<secrets::Backend>()?,
+        None => return Ok(Some(format!("Tokens are kept in the {} store", context.user_config.backend())))
+    };
+
+    context.set_token_store(backend)?;
+    Ok(Some(format!("Tokens are now kept in the {} store", backend)))
+}
+
+fn set_output(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    match args.get("format") {
+        Some(format) => {
+            context.output = format.to_string().parse::<output::Format>()?;
+            Ok(Some(format!("Printing results as {}", context.output)))
+        },
+        None => Ok(Some(format!("Printing results as {}", context.output)))
+    }
+}
+
+fn get_agent(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
 
     match spacetraders_sdk::apis::agents_api::get_my_agent(&context.api_config) {
         Ok(resp) => {
+            context.ledger.observe_balance(resp.data.credits);
 
             let mut table = Table::new();
             table.set_header(vec!["Account ID", "Symbol", "Headquarters", "Credits"]);
-            table.add_row(vec![resp.data.account_id, resp.data.symbol, resp.data.headquarters, resp.data.credits.to_string()] );
-            Ok(Some(table.to_string()))
+            table.add_row(vec![resp.data.account_id.clone(), resp.data.symbol.clone(), resp.data.headquarters.clone(), resp.data.credits.to_string()] );
+            Ok(Some(output::render(context.output, table, &[resp.data])))
         },
         Err(e) => {
-            Ok(Some(format!("Failed to get agent data {}", e)))
+            Err(format!("Failed to get agent data {}", e).into())
         }
     }
 }
 
-fn dump_config(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
-    Ok(Some(serde_json::to_string_pretty(&context.user_config).unwrap()))
+fn dump_config(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    Ok(Some(serde_json::to_string_pretty(&context.user_config.redacted()).unwrap()))
 }
 
-fn get_ship_nav(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
+fn get_ship_nav(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
 
     let ship_symbol = args.get("symbol").unwrap();
     match spacetraders_sdk::apis::fleet_api::get_ship_nav(&context.api_config, ship_symbol.to_string().as_str())
@@ -175,62 +282,90 @@ fn get_ship_nav(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> Re
             let ship = resp.data;
             let mut nav_table = Table::new();
             nav_table.set_header(vec!["System Symbol", "Waypoint Symbol", "From", "To", "Arrival", "Status", "Flight Mode"]);
-            nav_table.add_row(vec![ship.system_symbol, ship.waypoint_symbol, format!("{}//{}", ship.route.departure.system_symbol, ship.route.departure.symbol),
+            nav_table.add_row(vec![ship.system_symbol.clone(), ship.waypoint_symbol.clone(), format!("{}//{}", ship.route.departure.system_symbol, ship.route.departure.symbol),
                                    format!("{}//{}", ship.route.destination.system_symbol, ship.route.destination.symbol),
-                                    ship.route.arrival,
+                                    ship.route.arrival.clone(),
                                    ship.status.to_string(), ship.flight_mode.to_string()]);
 
-            Ok(Some(nav_table.to_string()))
+            Ok(Some(output::render(context.output, nav_table, &[ship])))
         },
         Err(e) => {
-            Ok(Some(format!("Failed to get nav status of {} - {}", ship_symbol, e)))
+            Err(format!("Failed to get nav status of {} - {}", ship_symbol, e).into())
         }
     }
 
 }
-fn show_ships(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
+fn show_ships(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
 
     match spacetraders_sdk::apis::fleet_api::get_my_ships(&context.api_config, None, None) {
         Ok(resp) => {
             let mut table = Table::new();
             table.set_header(vec!["Symbol", "Registration", "Crew", "Frame", "Reactor", "Engine", "Modules", "Mounts", "Cargo", "Fuel"]);
-            for ship in resp.data {
-                table.add_row(vec![ship.symbol, ship.registration.name,  format!("{}/{}",  ship.crew.current, ship.crew.capacity),
-                                   ship.frame.name, ship.reactor.name, ship.engine.name, ship.modules.len().to_string(), ship.mounts.len().to_string(),
+            for ship in &resp.data {
+                table.add_row(vec![ship.symbol.clone(), ship.registration.name.clone(),  format!("{}/{}",  ship.crew.current, ship.crew.capacity),
+                                   ship.frame.name.clone(), ship.reactor.name.clone(), ship.engine.name.clone(), ship.modules.len().to_string(), ship.mounts.len().to_string(),
                                    format!("{}/{}", ship.cargo.units, ship.cargo.capacity), format!("{}/{}", ship.fuel.current, ship.fuel.capacity)]);
             }
-            Ok(Some(table.to_string()))
+            Ok(Some(output::render(context.output, table, &resp.data)))
         },
         Err(e) => {
-            Ok(Some(format!("Failed to get ships {}", e)))
+            Err(format!("Failed to get ships {}", e).into())
         }
     }
 }
 
-fn show_contracts(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
+fn show_contracts(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
     match spacetraders_sdk::apis::contracts_api::get_contracts(&context.api_config, None, None) {
         Ok(resp) => {
             let contracts = resp.data;
+            // The estimates are only shown in the table, so don't spend requests on them otherwise
+            if context.output != output::Format::Table {
+                return Ok(Some(output::render(context.output, Table::new(), &contracts)));
+            }
+
+            // Estimating looks up markets and waypoints for every contract, so only when asked for
+            let ship = match args.get("ship") {
+                Some(ship) => Some(contracts::evaluation_ship(&context.api_config, Some(ship.to_string()))?),
+                None => None
+            };
+
             let mut table = Table::new();
-            table.set_header(vec!["ID", "Faction", "Type", "Accepted", "Fulfilled", "Expiration"]);
+            let mut header = vec!["ID", "Faction", "Type", "Accepted", "Fulfilled", "Expiration"];
+            if ship.is_some() {
+                header.extend(["Profit", "Profit/hr"]);
+            }
+            table.set_header(header);
+
             for contract in contracts {
-                let _ctype = contract.r#type.type_id();
-                table.add_row(vec![contract.id, contract.faction_symbol, "".to_string(), contract.accepted.to_string(), contract.fulfilled.to_string(), contract.expiration]);
+                let mut row = vec![contract.id.clone(), contract.faction_symbol.clone(), format!("{:?}", contract.r#type).to_uppercase(),
+                                   contract.accepted.to_string(), contract.fulfilled.to_string(), contract.expiration.clone()];
+
+                if let Some(ship) = &ship {
+                    match contracts::evaluate_contract(&mut context.cache, &context.api_config, &contract, ship) {
+                        Ok(evaluation) if !contract.fulfilled => row.extend([evaluation.profit().to_string(), format!("{:.0}", evaluation.profit_per_hour())]),
+                        _ => row.extend(["?".to_string(), "?".to_string()])
+                    }
+                }
+                table.add_row(row);
             }
 
             Ok(Some(table.to_string()))
         },
-        Err(e) => Ok(Some(format!("Failed to get contracts - {}", e)))
+        Err(e) => Err(format!("Failed to get contracts - {}", e).into())
     }
 }
 
-fn show_contract(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
+fn show_contract(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
     let contract = args.get("contract").unwrap();
 
     match spacetraders_sdk::apis::contracts_api::get_contract(&context.api_config, contract.to_string().as_str()) {
         Ok(resp) => {
             let contract = resp.data;
 
+            if context.output != output::Format::Table {
+                return Ok(Some(output::render(context.output, Table::new(), &[contract])));
+            }
+
             let deadline = contract.terms.deadline;
             let on_accept = contract.terms.payment.on_accepted;
             let on_complete = contract.terms.payment.on_fulfilled;
@@ -251,33 +386,443 @@ fn show_contract(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> R
 
             Ok(Some(res))
         },
-        Err(e) => Ok(Some(format!("Failed to get contract details {}", e)))
+        Err(e) => Err(format!("Failed to get contract details {}", e).into())
     }
 }
 
-fn accept_contract(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
+fn accept_contract(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
     let contract = args.get("contract").unwrap();
 
     match spacetraders_sdk::apis::contracts_api::accept_contract(&context.api_config, contract.to_string().as_str()) {
-        Ok(_) => {
+        Ok(resp) => {
+            context.ledger.record_contract_payment(&resp.data.contract.id, None, resp.data.contract.terms.payment.on_accepted, resp.data.agent.credits);
+
+            if context.output != output::Format::Table {
+                return Ok(Some(output::render(context.output, Table::new(), &[resp.data.contract])));
+            }
             Ok(Some("Contract accepted".to_string()))
         },
-        Err(e) => Ok(Some(format!("Failed to accept contract {}", e)))
+        Err(e) => Err(format!("Failed to accept contract {}", e).into())
+    }
+}
+
+fn evaluate_contract(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let contract = args.get("contract").unwrap();
+    let ship = args.get("ship").map(|s| s.to_string());
+
+    let contract = match spacetraders_sdk::apis::contracts_api::get_contract(&context.api_config, contract.to_string().as_str()) {
+        Ok(resp) => resp.data,
+        Err(e) => return Err(format!("Failed to get contract details {}", e).into())
+    };
+
+    let evaluation = contracts::evaluation_ship(&context.api_config, ship)
+        .and_then(|ship| contracts::evaluate_contract(&mut context.cache, &context.api_config, &contract, &ship));
+
+    match evaluation {
+        Ok(evaluation) => {
+            let mut table = Table::new();
+            table.set_header(vec!["Payment", "Goods Cost", "Fuel Cost", "Profit", "Duration", "Profit/hr", "Meets Deadline", "Mined"]);
+            table.add_row(vec![evaluation.payment.to_string(), evaluation.sourcing_cost.to_string(), evaluation.fuel_cost.to_string(),
+                               evaluation.profit().to_string(), format!("{}m", evaluation.duration.num_minutes()),
+                               format!("{:.0}", evaluation.profit_per_hour()), evaluation.meets_deadline.to_string(), evaluation.mined.join(", ")]);
+            Ok(Some(output::render_table(context.output, table)))
+        },
+        Err(e) => Err(format!("Failed to evaluate contract {}", e).into())
+    }
+}
+
+fn accept_profitable_contracts(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let min_profit_per_hour: f64 = args.get("min_profit_per_hour").unwrap().convert()?;
+
+    let contracts = match spacetraders_sdk::apis::contracts_api::get_contracts(&context.api_config, None, None) {
+        Ok(resp) => resp.data,
+        Err(e) => return Err(format!("Failed to get contracts - {}", e).into())
+    };
+
+    let ship = match contracts::evaluation_ship(&context.api_config, None) {
+        Ok(ship) => ship,
+        Err(e) => return Err(format!("Failed to evaluate contracts {}", e).into())
+    };
+
+    let mut table = Table::new();
+    table.set_header(vec!["ID", "Profit", "Profit/hr", "Meets Deadline", "Decision"]);
+
+    for contract in contracts.iter().filter(|c| !c.accepted) {
+        let decision = match contracts::evaluate_contract(&mut context.cache, &context.api_config, contract, &ship) {
+            Ok(evaluation) => {
+                let worth_it = evaluation.meets_deadline && evaluation.profit() > 0 && evaluation.profit_per_hour() >= min_profit_per_hour;
+
+                let decision = if !worth_it {
+                    "Skipped".to_string()
+                } else {
+                    match spacetraders_sdk::apis::contracts_api::accept_contract(&context.api_config, &contract.id) {
+                        Ok(resp) => {
+                            context.ledger.record_contract_payment(&contract.id, None, contract.terms.payment.on_accepted, resp.data.agent.credits);
+                            "Accepted".to_string()
+                        },
+                        Err(e) => format!("Failed to accept {}", e)
+                    }
+                };
+
+                vec![contract.id.clone(), evaluation.profit().to_string(), format!("{:.0}", evaluation.profit_per_hour()),
+                     evaluation.meets_deadline.to_string(), decision]
+            },
+            Err(e) => vec![contract.id.clone(), "?".to_string(), "?".to_string(), "?".to_string(), format!("Skipped - {}", e)]
+        };
+        table.add_row(decision);
     }
+
+    Ok(Some(output::render_table(context.output, table)))
 }
 
-fn get_waypoints(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
+fn run_contract(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let contract = args.get("contract").unwrap();
+    let ship = args.get("ship").unwrap();
 
-    let system = args.get("system").unwrap();
+    match ContractRunner::new(context, contract.to_string().as_str(), ship.to_string().as_str()).and_then(|mut runner| runner.run()) {
+        Ok(summary) => Ok(Some(summary)),
+        Err(e) => Err(format!("Failed to run contract {}", e).into())
+    }
+}
 
+fn schedule_task(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = args.get("ship").unwrap().to_string();
+    let priority: i32 = args.get("priority").unwrap().convert()?;
+    let repeat: bool = args.get("repeat").unwrap().convert()?;
+
+    match args.get("task").unwrap().to_string().parse::<Task>() {
+        Ok(task) => {
+            let id = context.schedule.enqueue(&ship, task, priority, repeat);
+            Ok(Some(format!("Scheduled task {} for {}", id, ship)))
+        },
+        Err(e) => Err(format!("Failed to schedule task {}", e).into())
+    }
+}
+
+fn show_schedule(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let mut table = Table::new();
+    table.set_header(vec!["Ship", "ID", "Task", "Priority", "Repeat", "Busy Until"]);
+
+    let mut ships: Vec<&String> = context.schedule.queues.keys().collect();
+    ships.sort();
+
+    for ship in ships {
+        let busy_until = context.schedule.busy_until.get(ship).cloned().unwrap_or_default();
+        for task in context.schedule.ordered(ship) {
+            table.add_row(vec![ship.clone(), task.id.to_string(), task.task.to_string(), task.priority.to_string(), task.repeat.to_string(), busy_until.clone()]);
+        }
+    }
+
+    Ok(Some(output::render_table(context.output, table)))
+}
+
+fn unschedule_task(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let id: u64 = args.get("id").unwrap().convert()?;
+
+    if context.schedule.remove(id) {
+        Ok(Some(format!("Removed task {}", id)))
+    } else {
+        Err(format!("No task with id {}", id).into())
+    }
+}
+
+fn clear_schedule(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = args.get("ship").map(|s| s.to_string());
+    context.schedule.clear(ship.as_deref());
+
+    Ok(Some("Schedule cleared".to_string()))
+}
+
+fn run_schedule(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let minutes: i64 = args.get("minutes").unwrap().convert()?;
+    let limit = if minutes > 0 { Some(chrono::Duration::minutes(minutes)) } else { None };
+
+    Ok(Some(context.schedule.run(&context.api_config, &mut context.ledger, &context.cache, limit)))
+}
+
+fn refinery_plan(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship_symbol = args.get("ship").unwrap().to_string();
+
+    let ship = match spacetraders_sdk::apis::fleet_api::get_my_ship(&context.api_config, &ship_symbol) {
+        Ok(resp) => *resp.data,
+        Err(e) => return Err(format!("Failed to get ship {}", e).into())
+    };
+
+    let mut table = Table::new();
+    table.set_header(vec!["Produce", "Input", "Output", "Cycles", "Gain/Cycle"]);
+
+    for option in refinery::refine_options(&context.cache, &ship.nav.system_symbol, &ship.cargo) {
+        let recipe = option.recipe;
+        table.add_row(vec![
+            recipe.output.to_string(),
+            format!("{} {}", recipe.input_units, recipe.input),
+            format!("{} {}", recipe.output_units, recipe.output),
+            option.cycles.to_string(),
+            option.gain_per_cycle.map(|g| g.to_string()).unwrap_or_else(|| "?".to_string()),
+        ]);
+    }
+
+    Ok(Some(output::render_table(context.output, table)))
+}
+
+fn run_refinery(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = args.get("ship").unwrap().to_string();
+    let cycles: i32 = args.get("cycles").unwrap().convert()?;
+    let miners: Vec<String> = args.get("miners")
+        .map(|m| m.to_string().split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    match Refinery::new(context, &ship, miners).and_then(|mut refinery| refinery.run(cycles)) {
+        Ok(summary) => Ok(Some(summary)),
+        Err(e) => Err(format!("Failed to run refinery {}", e).into())
+    }
+}
+
+fn run_convoy(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let hauler = args.get("hauler").unwrap().to_string();
+    let miners: Vec<String> = args.get("miners").unwrap().to_string()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    let field = args.get("field").map(|f| f.to_string());
+    let hauls: i32 = args.get("hauls").unwrap().convert()?;
+
+    match Convoy::new(context, &hauler, &miners, field).and_then(|mut convoy| convoy.run(hauls)) {
+        Ok(summary) => Ok(Some(summary)),
+        Err(e) => Err(format!("Failed to run convoy {}", e).into())
+    }
+}
+
+fn show_uncharted(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let system = match args.get("system") {
+        Some(system) => system.to_string(),
+        None => context.home_system()?
+    };
+
+    let api_config = context.api_config.clone();
+    let waypoints = context.cache.uncharted_waypoints(&api_config, &system)?;
+
+    let mut table = Table::new();
+    table.set_header(vec!["Waypoint", "Type", "X", "Y"]);
+
+    for waypoint in &waypoints {
+        table.add_row(vec![waypoint.symbol.clone(), waypoint.r#type.to_string(), waypoint.x.to_string(), waypoint.y.to_string()]);
+    }
+
+    Ok(Some(output::render(context.output, table, &waypoints)))
+}
+
+fn explore(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let ship = args.get("ship").unwrap().to_string();
+    let limit: usize = args.get("limit").unwrap().convert()?;
+
+    match Explorer::new(context, &ship).and_then(|mut explorer| explorer.run(limit)) {
+        Ok(summary) => Ok(Some(summary)),
+        Err(e) => Err(format!("Failed to explore {}", e).into())
+    }
+}
+
+fn show_pnl(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let grouping = args.get("by").unwrap().to_string().parse::<Grouping>()?;
+
+    let mut table = Table::new();
+    table.set_header(vec!["Group", "Income", "Expenses", "Net", "Entries"]);
+
+    let mut total = ledger::ProfitAndLoss::default();
+    for (key, pnl) in context.ledger.report(grouping) {
+        table.add_row(vec![key, pnl.income.to_string(), pnl.expenses.to_string(), pnl.net().to_string(), pnl.entries.to_string()]);
+        total.income += pnl.income;
+        total.expenses += pnl.expenses;
+        total.entries += pnl.entries;
+    }
+    table.add_row(vec!["Total".to_string(), total.income.to_string(), total.expenses.to_string(), total.net().to_string(), total.entries.to_string()]);
+
+    Ok(Some(output::render_table(context.output, table)))
+}
+
+fn show_ledger(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let limit: usize = args.get("limit").unwrap().convert()?;
+
+    let mut table = Table::new();
+    table.set_header(vec!["Time", "Cause", "Ship", "Activity", "Good", "Units", "Amount", "Balance"]);
+
+    let skip = context.ledger.entries.len().saturating_sub(limit);
+    let entries = &context.ledger.entries[skip..];
+    for entry in entries {
+        table.add_row(vec![
+            entry.timestamp.clone(),
+            entry.cause.to_string(),
+            entry.ship.clone().unwrap_or_default(),
+            entry.activity.clone(),
+            entry.trade_symbol.clone().unwrap_or_default(),
+            entry.units.to_string(),
+            if entry.estimated { format!("~{}", entry.amount) } else { entry.amount.to_string() },
+            entry.balance.to_string(),
+        ]);
+    }
+
+    Ok(Some(output::render(context.output, table, entries)))
+}
+
+fn show_net_worth(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let snapshot = networth::calculate(context)?;
+
+    let mut table = Table::new();
+    table.set_header(vec!["Credits", "Cargo", "Ships", "Total"]);
+    table.add_row(vec![snapshot.credits.to_string(), snapshot.cargo_value.to_string(),
+                       format!("{} ({} ships)", snapshot.ship_value, snapshot.ships), snapshot.total().to_string()]);
+
+    let mut rendered = output::render(context.output, table, std::slice::from_ref(&snapshot));
+    if context.output == output::Format::Table && !snapshot.unpriced.is_empty() {
+        rendered.push_str(&format!("\nNo known price for {}", snapshot.unpriced.join(", ")));
+    }
+
+    context.net_worth.record(snapshot);
+    Ok(Some(rendered))
+}
+
+fn show_net_worth_history(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let chart: bool = args.get("chart").unwrap().convert()?;
+
+    if context.net_worth.snapshots.is_empty() {
+        return Ok(Some("No net worth snapshots yet, take one with net_worth".to_string()));
+    }
+
+    if chart {
+        return Ok(Some(context.net_worth.chart()));
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["Time", "Credits", "Cargo", "Ships", "Total", "Change"]);
+
+    let mut previous: Option<i64> = None;
+    for snapshot in &context.net_worth.snapshots {
+        let change = previous.map(|p| format!("{:+}", snapshot.total() - p)).unwrap_or_default();
+        table.add_row(vec![snapshot.timestamp.clone(), snapshot.credits.to_string(), snapshot.cargo_value.to_string(),
+                           snapshot.ship_value.to_string(), snapshot.total().to_string(), change]);
+        previous = Some(snapshot.total());
+    }
 
-    match spacetraders_sdk::apis::systems_api::get_system_waypoints(&context.api_config, system.to_string().as_str(), None, None) {
+    Ok(Some(output::render(context.output, table, &context.net_worth.snapshots)))
+}
+
+fn track_net_worth(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let minutes: u64 = args.get("minutes").unwrap().convert()?;
+    let snapshots: usize = args.get("snapshots").unwrap().convert()?;
+
+    let mut taken = 0;
+    while snapshots == 0 || taken < snapshots {
+        if taken > 0 {
+            std::thread::sleep(std::time::Duration::from_secs(minutes.max(1) * 60));
+        }
+
+        match networth::calculate(context) {
+            Ok(snapshot) => {
+                println!("[{}] Net worth {}", snapshot.timestamp, snapshot.total());
+                context.net_worth.record(snapshot);
+            },
+            Err(e) => println!("{}", e)
+        }
+        taken += 1;
+    }
+
+    Ok(Some(format!("Took {} net worth snapshots", taken)))
+}
+
+fn run_script(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let file = args.get("file").unwrap().to_string();
+    let script_args: Vec<String> = args.get("args")
+        .map(|a| a.to_string().split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    let source = std::fs::read_to_string(&file).map_err(|e| format!("Failed to read {} - {}", file, e))?;
+
+    script_result(script::run(context, &source, script_args))
+}
+
+fn eval_script(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let code = args.get("code").unwrap().to_string();
+
+    script_result(script::run(context, &code, Vec::new()))
+}
+
+fn show_dashboard(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let interval = args.get("interval").unwrap().convert::<u64>()?;
+
+    Ok(Some(dashboard::Dashboard::new(context, interval).run()?))
+}
+
+fn show_map(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let system = match args.get("system") {
+        Some(system) => system.to_string(),
+        None => context.home_system()?
+    };
+
+    let api_config = context.api_config.clone();
+    let waypoints = context.cache.system_waypoints(&api_config, &system)?;
+    if waypoints.is_empty() {
+        return Err(format!("No waypoints found in {}", system).into());
+    }
+    let ships = map::ships_in_system(context, &system)?;
+
+    // Piped output has no terminal to fit, so fall back to a standard size
+    let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+
+    Ok(Some(map::render(&system, &waypoints, &ships, width, height)))
+}
+
+fn export_map(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let view = args.get("view").unwrap().to_string();
+    let file = args.get("file").unwrap().to_string();
+
+    let scene = match view.as_str() {
+        "galaxy" => mapexport::galaxy(context)?,
+        "system" => {
+            let system = match args.get("system") {
+                Some(system) => system.to_string(),
+                None => context.home_system()?
+            };
+            mapexport::system(context, &system)?
+        },
+        _ => return Err(format!("Unknown map {}, expected galaxy or system", view).into())
+    };
+
+    Ok(Some(mapexport::write(&scene, &file)?))
+}
+
+fn sync_galaxy(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let pages: i32 = args.get("pages").unwrap().convert()?;
+
+    let api_config = context.api_config.clone();
+    let known = context.cache.sync_galaxy(&api_config, pages)?;
+
+    Ok(Some(format!("{} systems cached", known)))
+}
+
+fn script_result(result: Result<Option<serde_json::Value>, String>) -> CommandResult {
+    match result {
+        Ok(Some(serde_json::Value::String(s))) => Ok(Some(s)),
+        Ok(Some(value)) => Ok(Some(serde_json::to_string_pretty(&value).unwrap())),
+        Ok(None) => Ok(None),
+        Err(e) => Err(format!("Script failed {}", e).into())
+    }
+}
+
+fn get_waypoints(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+
+    let system = match args.get("system") {
+        Some(system) => system.to_string(),
+        None => context.home_system()?
+    };
+
+    match spacetraders_sdk::apis::systems_api::get_system_waypoints(&context.api_config, system.as_str(), None, None) {
         Ok(resp) => {
 
             let mut table = Table::new();
             table.set_header(vec!["Symbol", "Type", "Traits", "Orbitals"]);
 
-            for waypoint in resp.data {
+            for waypoint in &resp.data {
 
                 let mut orbitals_str = String::new();
                 waypoint.orbitals.iter().map(|o| o.symbol.clone()).for_each(|o| orbitals_str.push_str(( o.as_str().to_owned() + ",\n" ).as_str() ));
@@ -285,84 +830,571 @@ fn get_waypoints(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> R
                 let mut traits_str = String::new();
                 waypoint.traits.iter().map(|o| o.name.clone()).for_each(|o| traits_str.push_str(( o + ",\n" ).as_str() ));
 
-                table.add_row(vec![waypoint.symbol, waypoint.r#type.to_string(), traits_str, orbitals_str]);
+                table.add_row(vec![waypoint.symbol.clone(), waypoint.r#type.to_string(), traits_str, orbitals_str]);
             }
 
-            Ok(Some(table.to_string()))
+            Ok(Some(output::render(context.output, table, &resp.data)))
+
+        },
+        Err(e) => {
+            Err(format!("Failed to get system waypoints {e}").into())
+        }
+    }
+}
+
+fn show_shipyards(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let system = match args.get("system") {
+        Some(system) => system.to_string(),
+        None => context.home_system()?
+    };
+
+    let api_config = context.api_config.clone();
+    let waypoints = context.cache.shipyard_waypoints(&api_config, &system)?;
+
+    let mut table = Table::new();
+    table.set_header(vec!["Waypoint", "Type", "Ship Types"]);
+
+    let mut shipyards = Vec::new();
+    for waypoint in waypoints {
+        let ship_types = match context.cache.shipyard(&api_config, &waypoint.symbol) {
+            Ok(shipyard) => {
+                let ship_types = shipyard.ship_types.iter()
+                    .filter_map(|t| t.r#type.map(|t| t.to_string()))
+                    .collect::<Vec<String>>()
+                    .join(",\n");
+                shipyards.push(shipyard);
+                ship_types
+            },
+            Err(e) => e
+        };
+        table.add_row(vec![waypoint.symbol, waypoint.r#type.to_string(), ship_types]);
+    }
+
+    Ok(Some(output::render(context.output, table, &shipyards)))
+}
+
+fn cargo_capacity(modules: &[ShipModule]) -> i32 {
+    modules.iter()
+        .filter(|m| m.symbol == ShipModuleSymbol::CargoHoldI)
+        .filter_map(|m| m.capacity)
+        .sum()
+}
+
+fn show_available_ships(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let waypoint = args.get("waypoint").unwrap().to_string();
+
+    let api_config = context.api_config.clone();
+    let shipyard = context.cache.shipyard(&api_config, &waypoint)?;
+
+    let mut table = Table::new();
+
+    match shipyard.ships {
+        Some(ships) => {
+            table.set_header(vec!["Type", "Name", "Price", "Frame", "Reactor", "Engine", "Modules", "Mounts", "Cargo", "Fuel"]);
+            for ship in &ships {
+                table.add_row(vec![ship.r#type.map(|t| t.to_string()).unwrap_or_default(), ship.name.clone(), ship.purchase_price.to_string(),
+                                   ship.frame.name.clone(), ship.reactor.name.clone(), ship.engine.name.clone(), ship.modules.len().to_string(), ship.mounts.len().to_string(),
+                                   cargo_capacity(&ship.modules).to_string(), ship.frame.fuel_capacity.to_string()]);
+            }
+            Ok(Some(output::render(context.output, table, &ships)))
+        },
+        None => {
+            table.set_header(vec!["Type"]);
+            for ship_type in shipyard.ship_types.iter().filter_map(|t| t.r#type) {
+                table.add_row(vec![ship_type.to_string()]);
+            }
+            if context.output != output::Format::Table {
+                return Ok(Some(output::render(context.output, table, &shipyard.ship_types)));
+            }
+            Ok(Some(format!("{table}\nPrices and specs are only listed while one of your ships is at {waypoint}")))
+        }
+    }
+}
+
+// How many of a market's transactions to list, newest first
+const RECENT_TRANSACTIONS: usize = 10;
+
+fn show_market(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let waypoint = args.get("waypoint").unwrap().to_string().trim().to_uppercase();
+
+    // A live fetch only has prices while one of our ships is there, otherwise fall back to the last priced snapshot
+    let api_config = context.api_config.clone();
+    let live = context.cache.market(&api_config, &waypoint);
+    let (snapshot, stale) = match (live, context.cache.markets.get(&waypoint)) {
+        (Ok(market), _) if market.trade_goods.is_some() => (MarketSnapshot { market, fetched_at: Utc::now().to_rfc3339() }, None),
+        (Ok(_), Some(snapshot)) if snapshot.market.trade_goods.is_some() => (snapshot.clone(), Some("no ship present".to_string())),
+        (Err(e), Some(snapshot)) => (snapshot.clone(), Some(e)),
+        (Ok(market), _) => (MarketSnapshot { market, fetched_at: Utc::now().to_rfc3339() }, None),
+        (Err(e), None) => return Err(e.into())
+    };
+
+    if context.output != output::Format::Table {
+        return Ok(Some(output::render(context.output, Table::new(), &[snapshot])));
+    }
+
+    let market = &snapshot.market;
+    let kind = |symbol: &str| {
+        let listed = |goods: &[TradeGood]| goods.iter().any(|g| g.symbol.to_string() == symbol);
+        if listed(&market.exchange) {
+            "exchange"
+        } else if listed(&market.exports) {
+            "export"
+        } else if listed(&market.imports) {
+            "import"
+        } else {
+            ""
+        }
+    };
+
+    let mut out = Vec::new();
+    let mut table = Table::new();
+    match &market.trade_goods {
+        Some(goods) => {
+            table.set_header(vec!["Good", "Kind", "Supply", "Volume", "Buy", "Sell"]);
+            for good in goods {
+                table.add_row(vec![good.symbol.clone(), kind(&good.symbol).to_string(), format!("{:?}", good.supply).to_uppercase(),
+                                   good.trade_volume.to_string(), good.purchase_price.to_string(), good.sell_price.to_string()]);
+            }
+            out.push(match &stale {
+                Some(reason) => format!("Market {} - prices as last seen {} ago ({})", waypoint, snapshot.age(), reason),
+                None => format!("Market {}", waypoint)
+            });
+        },
+        None => {
+            table.set_header(vec!["Good", "Kind"]);
+            for (goods, kind) in [(&market.imports, "import"), (&market.exports, "export"), (&market.exchange, "exchange")] {
+                for good in goods {
+                    table.add_row(vec![good.symbol.to_string(), kind.to_string()]);
+                }
+            }
+            out.push(match &stale {
+                Some(reason) => format!("Market {} - goods as last seen {} ago ({})", waypoint, snapshot.age(), reason),
+                None => format!("Market {} - prices are only listed while one of your ships is there", waypoint)
+            });
+        }
+    }
+    out.push(table.to_string());
+
+    if let Some(transactions) = market.transactions.as_ref().filter(|t| !t.is_empty()) {
+        let mut table = Table::new();
+        table.set_header(vec!["Time", "Ship", "Type", "Good", "Units", "Price", "Total"]);
+        for transaction in transactions.iter().rev().take(RECENT_TRANSACTIONS) {
+            let time = wait::parse_timestamp(&transaction.timestamp)
+                .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| transaction.timestamp.clone());
+            table.add_row(vec![time, transaction.ship_symbol.clone(), format!("{:?}", transaction.r#type).to_uppercase(), transaction.trade_symbol.clone(),
+                               transaction.units.to_string(), transaction.price_per_unit.to_string(), transaction.total_price.to_string()]);
+        }
+        out.push("Recent transactions".to_string());
+        out.push(table.to_string());
+    }
 
+    Ok(Some(out.join("\n")))
+}
+
+fn compare_ships(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let waypoint = args.get("waypoint").unwrap().to_string();
+    let types: Vec<String> = args.get("types")
+        .map(|t| t.to_string().split(',').map(|t| parse_ship_type(t).map(|t| t.to_string()).unwrap_or_default()).collect())
+        .unwrap_or_default();
+
+    let api_config = context.api_config.clone();
+    let ships = match context.cache.shipyard(&api_config, &waypoint) {
+        Ok(shipyard) => match shipyard.ships {
+            Some(ships) => ships,
+            None => return Err(format!("Ship specs are only listed while one of your ships is at {}", waypoint).into())
+        },
+        Err(e) => return Err(e.into())
+    };
+
+    let ships: Vec<ShipyardShip> = ships.into_iter()
+        .filter(|s| types.is_empty() || s.r#type.map(|t| types.contains(&t.to_string())).unwrap_or(false))
+        .collect();
+
+    let labels = ["Name", "Price", "Frame", "Reactor", "Engine", "Modules", "Mounts", "Cargo", "Fuel"];
+    let specs: Vec<Vec<String>> = ships.iter().map(|s| vec![
+        s.name.clone(),
+        s.purchase_price.to_string(),
+        format!("{} ({} modules, {} mounts)", s.frame.name, s.frame.module_slots, s.frame.mounting_points),
+        format!("{} ({} power)", s.reactor.name, s.reactor.power_output),
+        format!("{} (speed {})", s.engine.name, s.engine.speed),
+        s.modules.iter().map(|m| m.name.clone()).collect::<Vec<String>>().join(",\n"),
+        s.mounts.iter().map(|m| m.name.clone()).collect::<Vec<String>>().join(",\n"),
+        cargo_capacity(&s.modules).to_string(),
+        s.frame.fuel_capacity.to_string(),
+    ]).collect();
+
+    let mut table = Table::new();
+    let mut header = vec!["".to_string()];
+    header.extend(ships.iter().map(|s| s.r#type.map(|t| t.to_string()).unwrap_or_default()));
+    table.set_header(header);
+
+    // One column per ship, one row per attribute
+    for (i, label) in labels.iter().enumerate() {
+        let mut row = vec![label.to_string()];
+        row.extend(specs.iter().map(|spec| spec[i].clone()));
+        table.add_row(row);
+    }
+
+    Ok(Some(output::render(context.output, table, &ships)))
+}
+
+// Accepts the API symbol (SHIP_MINING_DRONE) or the short form (mining_drone)
+fn parse_ship_type(ship_type: &str) -> Option<ShipType> {
+    let mut symbol = ship_type.trim().to_uppercase();
+    if !symbol.starts_with("SHIP_") {
+        symbol = format!("SHIP_{}", symbol);
+    }
+
+    serde_json::from_value(serde_json::Value::String(symbol)).ok()
+}
+
+fn buy_ship(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let waypoint = args.get("waypoint").unwrap().to_string();
+    let ship_type = match parse_ship_type(&args.get("type").unwrap().to_string()) {
+        Some(ship_type) => ship_type,
+        None => return Err(format!("Unknown ship type {}", args.get("type").unwrap()).into())
+    };
+
+    let api_config = context.api_config.clone();
+    let price = match context.cache.shipyard(&api_config, &waypoint) {
+        Ok(shipyard) => shipyard.ships.unwrap_or_default().into_iter()
+            .find(|s| s.r#type == Some(ship_type))
+            .map(|s| s.purchase_price),
+        Err(e) => return Err(e.into())
+    };
+
+    let price = match price {
+        Some(price) => price,
+        None => return Err(format!("{} is not for sale at {}, or none of your ships are there to see the price", ship_type.to_string(), waypoint).into())
+    };
+
+    let credits = match spacetraders_sdk::apis::agents_api::get_my_agent(&context.api_config) {
+        Ok(resp) => resp.data.credits,
+        Err(e) => return Err(format!("Failed to get agent data {}", e).into())
+    };
+
+    if credits < price {
+        return Err(format!("Can't afford a {} for {} credits, you have {}", ship_type.to_string(), price, credits).into());
+    }
+
+    let confirmation = Confirm::new(format!("Buy a {} for {} credits? ({} credits remaining) ", ship_type.to_string(), price, credits - price).as_str())
+        .with_default(false)
+        .prompt();
+
+    match confirmation {
+        Ok(true) => {
+            match spacetraders_sdk::apis::fleet_api::purchase_ship(&context.api_config, Some(PurchaseShipRequest::new(ship_type, waypoint))) {
+                Ok(resp) => {
+                    context.ledger.record_ship_purchase("manual", &resp.data.ship.symbol, &resp.data.transaction, resp.data.agent.credits);
+                    Ok(Some(format!("Bought {} for {} credits. Credits now {}", resp.data.ship.symbol, resp.data.transaction.price, resp.data.agent.credits)))
+                },
+                Err(e) => Err(format!("Failed to buy ship {}", e).into())
+            }
+        },
+        Ok(false) => Ok(Some("Purchase cancelled".to_string())),
+        Err(e) => Err(format!("Failed to read prompt {}", e).into())
+    }
+}
+
+fn commands() -> Vec<CommandSpec> {
+    let mut specs = vec![
+        command!("check_server", check_server)
+            .with_path("check-server")
+            .with_help("Check the server status")
+            .read_only(),
+        command!("exit", exit)
+            .repl_only()
+            .with_help("Exit"),
+        command!("register", register)
+            .with_help("Register as a new user in the given faction, see `factions` for the ones that can be joined")
+            .required("callsign")
+            .with_default("faction", "COSMIC"),
+        command!("new_config", new_config)
+            .with_path("config new")
+            .with_help("Backup the existing config and create a new one"),
+        command!("save_config", save_config)
+            .with_path("config save")
+            .with_help("Save your config"),
+        command!("profiles", show_profiles)
+            .with_path("profile list")
+            .with_help("List the agent profiles in this config")
+            .read_only(),
+        command!("add_profile", add_profile)
+            .with_path("profile add")
+            .with_help("Add a profile for an existing agent from its token")
+            .required("name")
+            .required("token")
+            .secret()
+            .optional("faction"),
+        command!("switch_profile", switch_profile)
+            .with_path("profile switch")
+            .with_help("Play as the agent in another profile")
+            .required("name"),
+        command!("remove_profile", remove_profile)
+            .with_path("profile remove")
+            .with_help("Remove a profile other than the active one")
+            .required("name"),
+        command!("token_store", set_token_store)
+            .with_path("config token-store")
+            .with_help("Show where agent tokens are kept, or move them to plain (the config file), keyring (the OS secret service) or encrypted (a passphrase protected file)")
+            .optional("backend"),
+        command!("dump_config", dump_config)
+            .with_path("config show")
+            .with_help("Show current config"),
+        command!("output", set_output)
+            .repl_only()
+            .with_help("Show or change how results are printed: table, json, ndjson or csv. Use --output outside the REPL")
+            .optional("format"),
+        command!("get_agent", get_agent)
+            .with_path("agent")
+            .with_help("Show the current agent status")
+            .read_only(),
+        command!("show_ships", show_ships)
+            .with_path("ships")
+            .with_help("Show all ships")
+            .read_only(),
+        command!("show_ship_nav", get_ship_nav)
+            .with_path("nav")
+            .with_help("Get the navigation for a given ship")
+            .read_only()
+            .required("symbol"),
+        command!("show_contracts", show_contracts)
+            .with_path("contract list")
+            .with_help("Show available contracts, with profit estimates for a ship if one is given")
+            .read_only()
+            .optional("ship"),
+        command!("show_contract", show_contract)
+            .with_path("contract show")
+            .with_help("Show the terms of a contract")
+            .read_only()
+            .required("contract"),
+        command!("accept_contract", accept_contract)
+            .with_path("contract accept")
+            .with_help("Accept a contract")
+            .required("contract"),
+        command!("evaluate_contract", evaluate_contract)
+            .with_path("contract evaluate")
+            .with_help("Estimate the cost, duration and profit of a contract for a ship (defaults to your first ship)")
+            .read_only()
+            .required("contract")
+            .optional("ship"),
+        command!("accept_profitable_contracts", accept_profitable_contracts)
+            .with_path("contract accept-profitable")
+            .with_help("Accept every open contract that can be completed before its deadline at a profit")
+            .with_default("min_profit_per_hour", "0"),
+        command!("run_contract", run_contract)
+            .with_path("contract run")
+            .with_help("Source, haul and deliver the goods for an accepted procurement contract, then fulfil it")
+            .required("contract")
+            .required("ship"),
+        command!("schedule_task", schedule_task)
+            .with_path("schedule add")
+            .with_help("Queue a task for a ship, e.g. navigate:<waypoint>, dock, orbit, refuel, extract, survey, buy:<good>:<units>, sell:<good>:<units>, deliver:<contract>:<good>:<units>")
+            .required("ship")
+            .required("task")
+            .with_default("priority", "0")
+            .with_default("repeat", "false"),
+        command!("show_schedule", show_schedule)
+            .with_path("schedule list")
+            .with_help("Show queued tasks for every ship")
+            .read_only(),
+        command!("unschedule_task", unschedule_task)
+            .with_path("schedule remove")
+            .with_help("Remove a queued task")
+            .required("id"),
+        command!("clear_schedule", clear_schedule)
+            .with_path("schedule clear")
+            .with_help("Remove all queued tasks, or just those for one ship")
+            .optional("ship"),
+        command!("run_schedule", run_schedule)
+            .with_path("schedule run")
+            .with_help("Work through the queued tasks, waiting out arrivals and cooldowns. Runs until the queues are empty unless a number of minutes is given")
+            .with_default("minutes", "0"),
+        command!("refinery_plan", refinery_plan)
+            .with_path("refinery plan")
+            .with_help("Show what a refinery ship could refine from its cargo and the gain per cycle at known market prices")
+            .read_only()
+            .required("ship"),
+        command!("run_refinery", run_refinery)
+            .with_path("refinery run")
+            .with_help("Refine the most profitable ore on a ship, pulling ore from the given comma separated miners at the same waypoint. Runs until nothing is worth refining unless a number of cycles is given")
+            .required("ship")
+            .optional("miners")
+            .with_default("cycles", "0"),
+        command!("run_convoy", run_convoy)
+            .with_path("convoy")
+            .with_help("Keep the given comma separated miners extracting at an asteroid field (defaults to the one nearest the hauler) while the hauler takes their cargo to market. Runs for one haul unless told otherwise, 0 for no limit")
+            .required("hauler")
+            .required("miners")
+            .optional("field")
+            .with_default("hauls", "1"),
+        command!("uncharted", show_uncharted)
+            .with_help("List the waypoints in a system (defaults to your headquarters) that nobody has charted yet")
+            .read_only()
+            .optional("system"),
+        command!("explore", explore)
+            .with_help("Tour the uncharted waypoints in a ship's system, charting each and scanning along the way. Visits them all unless a limit is given")
+            .required("ship")
+            .with_default("limit", "0"),
+        command!("ledger", show_ledger)
+            .with_help("Show the most recent credit changes and what caused them")
+            .read_only()
+            .with_default("limit", "20"),
+        command!("pnl", show_pnl)
+            .with_help("Show profit and loss by ship, good, day or activity")
+            .read_only()
+            .with_default("by", "ship"),
+        command!("net_worth", show_net_worth)
+            .with_path("net-worth")
+            .with_help("Work out what the agent is worth in credits, cargo and ships, and save a snapshot"),
+        command!("net_worth_history", show_net_worth_history)
+            .with_path("net-worth-history")
+            .with_help("Show saved net worth snapshots as a table, or as a chart")
+            .read_only()
+            .with_default("chart", "false"),
+        command!("track_net_worth", track_net_worth)
+            .with_path("track-net-worth")
+            .with_help("Snapshot net worth every given number of minutes, forever unless a number of snapshots is given")
+            .required("minutes")
+            .with_default("snapshots", "0"),
+        command!("shipyards", show_shipyards)
+            .with_help("List the shipyards in a system (defaults to your headquarters) and the ship types they sell")
+            .read_only()
+            .optional("system"),
+        command!("shipyard", show_available_ships)
+            .with_help("Show the ships for sale at a shipyard")
+            .read_only()
+            .required("waypoint"),
+        command!("market", show_market)
+            .with_help("Show a market's goods with prices, supply and trade volume, and its recent transactions. Without a ship there, shows the last prices seen")
+            .read_only()
+            .required("waypoint"),
+        command!("compare_ships", compare_ships)
+            .with_path("compare-ships")
+            .with_help("Compare the ships at a shipyard side by side, optionally only the given comma separated types")
+            .read_only()
+            .required("waypoint")
+            .optional("types"),
+        command!("buy_ship", buy_ship)
+            .with_path("buy-ship")
+            .with_help("Buy a ship from a shipyard where one of your ships is present")
+            .required("waypoint")
+            .required("type"),
+        command!("run_script", run_script)
+            .with_path("script run")
+            .with_help("Run a fleet script from a file, passing it optional comma separated args")
+            .required("file")
+            .optional("args"),
+        command!("script", eval_script)
+            .with_path("script eval")
+            .with_help("Run a snippet of fleet script, e.g. script \"for s in ships() { print(`${s.symbol} ${s.nav.status}`) }\"")
+            .required("code"),
+        command!("dashboard", show_dashboard)
+            .with_help("Show a live view of the agent, ships, contracts and recent activity, refreshed every given number of seconds (at least 10)")
+            .with_default("interval", "30"),
+        command!("map", show_map)
+            .with_help("Draw a system (defaults to your headquarters) as a map of its waypoints, markets, shipyards and our ships")
+            .read_only()
+            .optional("system"),
+        command!("export_map", export_map)
+            .with_path("export-map")
+            .with_help("Write the galaxy, or a system (defaults to your headquarters), as an .svg or .png map from cached data")
+            .required("view")
+            .required("file")
+            .optional("system"),
+        command!("sync_galaxy", sync_galaxy)
+            .with_path("sync-galaxy")
+            .with_help("Cache the next given number of pages of the galaxy's systems for the galaxy map")
+            .with_default("pages", "10"),
+        command!("system_waypoints", get_waypoints)
+            .with_path("waypoints")
+            .with_help("Show system waypoints (defaults to your headquarters system)")
+            .read_only()
+            .optional("system"),
+    ];
+
+    specs.extend(actions::commands());
+    specs.extend(shell::commands());
+    specs.extend(factions::commands());
+    specs
+}
+
+// Run a single command from the command line, returning the process exit code
+fn run_command(spec: &CommandSpec, given: HashMap<String, String>, context: &mut ConfigWrapper) -> i32 {
+    let before = serde_json::to_value(context.user_config.persisted()).ok();
+
+    let result = (spec.handler)(spec.arguments(given), context);
+
+    // There's no exit command to save on, so keep anything the command changed, like a new profile
+    if serde_json::to_value(context.user_config.persisted()).ok() != before {
+        context.save();
+    }
+
+    match result {
+        Ok(output) => {
+            if let Some(output) = output {
+                println!("{}", output);
+            }
+            0
         },
         Err(e) => {
-            Ok(Some(format!("Failed to get system waypoints {e}")))
+            eprintln!("{}", e);
+            1
         }
     }
 }
 
-fn show_available_ships(_args: HashMap<String, Value>, _context: &mut ConfigWrapper) -> ReplResult<Option<String>> {
-    Ok(Some(String::new()))
-}
-
-fn main() -> ReplResult<()>{
-
-    let args = Args::parse();
-
-    if let Ok(c) = read_config(args.config_file) {
-
-        let config = ConfigWrapper::new(c);
-
-        let mut repl = Repl::new(config)
-            .with_name("Spacetraders")
-            .with_description("REPL CLI for spacetraders")
-            .use_completion(true)
-            .add_command(
-                Command::new("check_server", check_server)
-                    .with_help("Check the server status")
-            )
-            .add_command(
-                Command::new("exit", exit)
-                    .with_help("Exit")
-            )
-            .add_command(
-                Command::new("register", register)
-                    .with_help("Register as a new user. The default faction is \"Comsic\", see the docs for all options")
-                    .with_parameter(Parameter::new("callsign").set_required(true)?)?
-                    .with_parameter(Parameter::new("faction").set_required(false)?.set_default("Cosmic")?)?
-            ).add_command(
-            Command::new("new_config", new_config)
-                .with_help("Backup the existing config and create a new one")
-        ).add_command(
-            Command::new("save_config", save_config)
-                .with_help("Save your config")
-        )
-            .add_command(Command::new("dump_config", dump_config)
-                             .with_help("Show current config")
-            ).add_command(
-            Command::new("get_agent", get_agent)
-                .with_help("Show the current agent status")
-        )
-            .add_command(
-                Command::new("show_ships", show_ships)
-                    .with_help("Show all ships")
-            ).add_command(
-            Command::new("show_ship_nav", get_ship_nav)
-                .with_parameter(Parameter::new("symbol").set_required(true)?)?
-                .with_help("Get the navigation for a given ship")
-        )
-            .add_command(Command::new("show_contracts", show_contracts)
-                             .with_help("Show available contracts"))
-            .add_command(Command::new("show_contract", show_contract)
-                             .with_parameter(Parameter::new("contract").set_required(true)?)?
-            )
-            .add_command(Command::new("accept_contract", accept_contract)
-                .with_help("Accept a contract")
-                .with_parameter(Parameter::new("contract").set_required(true)?)?)
-            .add_command(Command::new("system_waypoints", get_waypoints)
-            .with_help("Show system waypoints")
-            .with_parameter(Parameter::new("system").set_required(false)?)?);
-
-        repl.run()
+fn watch_command(spec: &CommandSpec, given: HashMap<String, String>, interval: u64, context: &mut ConfigWrapper) -> i32 {
+    let result = watch::Watch::new(context, spec, spec.arguments(given), spec.path.join(" "), interval)
+        .and_then(|mut watch| watch.run().map_err(|e| e.into()));
+
+    match result {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn main() {
+
+    let specs = commands();
+    let matches = cli::command(&specs).get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let subcommand = cli::subcommand_of(&specs, &matches);
+
+    let config = read_config(args.config_file())
+        .and_then(|mut c| {
+            c.apply_overrides(ConfigOverrides::from_env().merge(args.overrides()))?;
+            Ok(c)
+        })
+        .map_err(|e| println!("{}", e));
+
+    if let Ok(c) = config {
+
+        let mut config = ConfigWrapper::new(c);
+        config.output = args.output.unwrap_or_default();
+
+        if let Some((spec, given)) = subcommand {
+            if let Some(interval) = args.watch {
+                std::process::exit(watch_command(spec, given, interval, &mut config));
+            }
+            std::process::exit(run_command(spec, given, &mut config));
+        }
+
+        if let Err(e) = shell::Shell::new(&specs, config).run() {
+            exit!(e);
+        }
+    }
+    else if subcommand.is_some() {
+        std::process::exit(1);
     }
     else {
         exit!("Failed to read config file");
-        Ok(())
     }
 }
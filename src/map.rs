@@ -0,0 +1,246 @@
+use chrono::Utc;
+use spacetraders_sdk::models::waypoint_trait::Symbol as WaypointTraitSymbol;
+use spacetraders_sdk::models::{Ship, ShipNavStatus, Waypoint, WaypointType};
+use spacetraders_sdk::wait;
+
+use crate::cache::has_trait;
+use crate::config::ConfigWrapper;
+use crate::networth;
+
+// Terminal cells are about twice as tall as they are wide
+const CELL_ASPECT: f64 = 2.0;
+
+// Rows left below the grid for the key and the ship list
+const RESERVED_ROWS: u16 = 6;
+
+/// The part of a system's coordinate space that a map covers, and how it maps onto a grid
+pub struct Projection {
+    min_x: f64,
+    min_y: f64,
+    scale_x: f64,
+    scale_y: f64,
+    offset_x: f64,
+    offset_y: f64,
+}
+
+impl Projection {
+    /// Fit `points` into a `width` by `height` grid, keeping the x/y proportions when each
+    /// column is `aspect` times narrower than a row is tall
+    pub fn fit(points: &[(i32, i32)], width: f64, height: f64, aspect: f64) -> Projection {
+        let min_x = points.iter().map(|p| p.0).min().unwrap_or(0) as f64;
+        let max_x = points.iter().map(|p| p.0).max().unwrap_or(0) as f64;
+        let min_y = points.iter().map(|p| p.1).min().unwrap_or(0) as f64;
+        let max_y = points.iter().map(|p| p.1).max().unwrap_or(0) as f64;
+
+        // Points all in a line have no extent that way, so they're scaled as if they had some and centred
+        let scale_y = (height / (max_y - min_y).max(1.0)).min(width / ((max_x - min_x).max(1.0) * aspect));
+        let scale_x = scale_y * aspect;
+
+        Projection {
+            min_x,
+            min_y,
+            scale_x,
+            scale_y,
+            offset_x: (width - (max_x - min_x) * scale_x) / 2.0,
+            offset_y: (height - (max_y - min_y) * scale_y) / 2.0,
+        }
+    }
+
+    pub fn project(&self, x: f64, y: f64) -> (f64, f64) {
+        ((x - self.min_x) * self.scale_x + self.offset_x, (y - self.min_y) * self.scale_y + self.offset_y)
+    }
+}
+
+/// How far along its route a ship in transit is, from 0 at departure to 1 on arrival
+pub fn route_progress(ship: &Ship) -> f64 {
+    let departure = wait::parse_timestamp(&ship.nav.route.departure_time);
+    let arrival = wait::parse_timestamp(&ship.nav.route.arrival);
+
+    match (departure, arrival) {
+        (Some(departure), Some(arrival)) if arrival > departure => {
+            let total = (arrival - departure).num_milliseconds() as f64;
+            let flown = (Utc::now() - departure).num_milliseconds() as f64;
+            (flown / total).clamp(0.0, 1.0)
+        },
+        _ => 1.0
+    }
+}
+
+/// Our ships in a system, fetched fresh so positions are current
+pub fn ships_in_system(context: &mut ConfigWrapper, system: &str) -> Result<Vec<Ship>, String> {
+    Ok(networth::fetch_all_ships(context)?
+        .into_iter()
+        .filter(|s| s.nav.system_symbol == system || s.nav.route.destination.system_symbol == system)
+        .collect())
+}
+
+struct Grid {
+    cells: Vec<Vec<char>>,
+    // Drawing priority of what's in each cell, so a jump gate isn't hidden behind its moon
+    ranks: Vec<Vec<u8>>,
+}
+
+impl Grid {
+    fn new(width: usize, height: usize) -> Grid {
+        Grid { cells: vec![vec![' '; width]; height], ranks: vec![vec![0; width]; height] }
+    }
+
+    fn put(&mut self, (x, y): (f64, f64), c: char, rank: u8) {
+        let (col, row) = (x.round(), y.round());
+        if col < 0.0 || row < 0.0 {
+            return;
+        }
+        let (col, row) = (col as usize, row as usize);
+        if row < self.cells.len() && col < self.cells[row].len() && self.ranks[row][col] <= rank {
+            self.cells[row][col] = c;
+            self.ranks[row][col] = rank;
+        }
+    }
+
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), c: char, rank: u8) {
+        let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil() as usize;
+        for i in 0..=steps {
+            let t = if steps == 0 { 0.0 } else { i as f64 / steps as f64 };
+            self.put((from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t), c, rank);
+        }
+    }
+
+    fn render(&self) -> String {
+        let border = format!("+{}+", "-".repeat(self.cells.first().map(|r| r.len()).unwrap_or(0)));
+        let mut lines = vec![border.clone()];
+        lines.extend(self.cells.iter().map(|row| format!("|{}|", row.iter().collect::<String>())));
+        lines.push(border);
+        lines.join("\n")
+    }
+}
+
+// The character a waypoint is drawn with and how much it matters when several share a cell
+fn marker(waypoint_type: &WaypointType) -> (char, u8) {
+    match waypoint_type {
+        WaypointType::JumpGate => ('J', 9),
+        WaypointType::Planet => ('P', 8),
+        WaypointType::GasGiant => ('G', 8),
+        WaypointType::AsteroidField => ('A', 7),
+        WaypointType::OrbitalStation => ('S', 6),
+        WaypointType::Moon => ('m', 5),
+        WaypointType::Nebula => ('N', 4),
+        WaypointType::DebrisField => ('D', 4),
+        WaypointType::GravityWell => ('W', 4),
+    }
+}
+
+/// Draw a system's waypoints and our ships on a character grid of the given size
+pub fn render(system: &str, waypoints: &[Waypoint], ships: &[Ship], width: u16, height: u16) -> String {
+    let grid_width = width.saturating_sub(2).max(20) as usize;
+    let grid_height = height.saturating_sub(RESERVED_ROWS + 3).max(10) as usize;
+
+    let mut points: Vec<(i32, i32)> = waypoints.iter().map(|w| (w.x, w.y)).collect();
+    for ship in ships {
+        points.push((ship.nav.route.departure.x, ship.nav.route.departure.y));
+        points.push((ship.nav.route.destination.x, ship.nav.route.destination.y));
+    }
+
+    let projection = Projection::fit(&points, (grid_width - 2) as f64, (grid_height - 1) as f64, CELL_ASPECT);
+    let mut grid = Grid::new(grid_width, grid_height);
+    let place = |x: i32, y: i32| {
+        let (px, py) = projection.project(x as f64, y as f64);
+        // One column of margin on the left for ship markers
+        (px + 1.0, py)
+    };
+
+    for ship in ships.iter().filter(|s| s.nav.status == ShipNavStatus::InTransit) {
+        let route = &ship.nav.route;
+        grid.line(place(route.departure.x, route.departure.y), place(route.destination.x, route.destination.y), '.', 1);
+    }
+
+    for waypoint in waypoints {
+        let (c, rank) = marker(&waypoint.r#type);
+        let at = place(waypoint.x, waypoint.y);
+        grid.put(at, c, rank);
+
+        let market = has_trait(waypoint, WaypointTraitSymbol::Marketplace);
+        let shipyard = has_trait(waypoint, WaypointTraitSymbol::Shipyard);
+        let trade = match (market, shipyard) {
+            (true, true) => Some('+'),
+            (true, false) => Some('$'),
+            (false, true) => Some('Y'),
+            (false, false) => None
+        };
+        if let Some(trade) = trade {
+            grid.put((at.0 + 1.0, at.1), trade, 2);
+        }
+    }
+
+    let mut ship_lines = Vec::new();
+    for ship in ships {
+        let route = &ship.nav.route;
+        if ship.nav.status == ShipNavStatus::InTransit {
+            let from = place(route.departure.x, route.departure.y);
+            let to = place(route.destination.x, route.destination.y);
+            let progress = route_progress(ship);
+            grid.put((from.0 + (to.0 - from.0) * progress, from.1 + (to.1 - from.1) * progress), '@', 10);
+            ship_lines.push(format!("  {} in transit {} -> {} ({:.0}%)", ship.symbol, route.departure.symbol, route.destination.symbol, progress * 100.0));
+        } else {
+            let at = place(route.destination.x, route.destination.y);
+            grid.put((at.0 - 1.0, at.1), '@', 10);
+            ship_lines.push(format!("  {} {} at {}", ship.symbol, ship.nav.status.to_string().to_lowercase(), ship.nav.waypoint_symbol));
+        }
+    }
+
+    let mut out = vec![
+        format!("{} - {} waypoints, {} of our ships", system, waypoints.len(), ships.len()),
+        grid.render(),
+        "P planet  G gas giant  m moon  S station  A asteroid field  J jump gate  N/D/W nebula, debris, gravity well".to_string(),
+        "$ market  Y shipyard  + both  @ our ship  . route".to_string(),
+    ];
+    out.extend(ship_lines);
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projection_fills_the_grid_keeping_proportions() {
+        // 100 wide by 50 tall, on cells twice as tall as they are wide
+        let projection = Projection::fit(&[(-50, 0), (50, 50)], 40.0, 10.0, 2.0);
+        assert_eq!(projection.project(-50.0, 0.0), (0.0, 0.0));
+        assert_eq!(projection.project(50.0, 50.0), (40.0, 10.0));
+
+        // A narrower span is centred rather than stretched
+        let projection = Projection::fit(&[(0, 0), (10, 10)], 40.0, 10.0, 2.0);
+        assert_eq!(projection.project(0.0, 0.0), (10.0, 0.0));
+        assert_eq!(projection.project(10.0, 10.0), (30.0, 10.0));
+    }
+
+    #[test]
+    fn projection_of_points_with_no_extent_is_centred() {
+        let projection = Projection::fit(&[(7, 7)], 20.0, 10.0, 2.0);
+        assert_eq!(projection.project(7.0, 7.0), (10.0, 5.0));
+        assert_eq!(Projection::fit(&[], 20.0, 10.0, 2.0).project(0.0, 0.0), (10.0, 5.0));
+
+        // Points along a line are centred across it
+        let projection = Projection::fit(&[(0, 3), (10, 3)], 20.0, 10.0, 2.0);
+        assert_eq!(projection.project(0.0, 3.0), (0.0, 5.0));
+        assert_eq!(projection.project(10.0, 3.0), (20.0, 5.0));
+    }
+
+    #[test]
+    fn grid_keeps_the_most_important_marker() {
+        let mut grid = Grid::new(3, 1);
+        grid.put((1.0, 0.0), 'J', 9);
+        grid.put((1.2, 0.0), 'm', 5);
+        grid.put((-1.0, 0.0), 'x', 10);
+        grid.put((3.0, 0.0), 'x', 10);
+        assert_eq!(grid.render(), "+---+\n| J |\n+---+");
+    }
+
+    #[test]
+    fn grid_lines_cover_every_cell_between_the_ends() {
+        let mut grid = Grid::new(4, 2);
+        grid.line((0.0, 0.0), (3.0, 1.0), '.', 1);
+        grid.put((3.0, 1.0), 'P', 8);
+        assert_eq!(grid.render(), "+----+\n|..  |\n|  .P|\n+----+");
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{self, Options, Tree};
+use spacetraders_sdk::models::waypoint_trait::Symbol as WaypointTraitSymbol;
+use spacetraders_sdk::models::{ShipNavStatus, SystemType, WaypointType};
+
+use crate::cache::{has_trait, system_symbol};
+use crate::config::ConfigWrapper;
+use crate::map::{self, Projection};
+
+const GALAXY_SIZE: u32 = 1600;
+const SYSTEM_SIZE: u32 = 1200;
+const MARGIN: f64 = 60.0;
+
+// Above this many systems the galaxy map only labels our headquarters
+const GALAXY_LABEL_LIMIT: usize = 150;
+
+const BACKGROUND: Colour = Colour(11, 14, 26);
+const TEXT: Colour = Colour(220, 224, 236);
+const LINK: Colour = Colour(70, 90, 140);
+const ROUTE: Colour = Colour(120, 255, 160);
+
+// Outline colours handed out to factions in the order we meet them
+const FACTION_COLOURS: [Colour; 8] = [
+    Colour(255, 99, 132), Colour(54, 162, 235), Colour(255, 206, 86), Colour(75, 192, 192),
+    Colour(153, 102, 255), Colour(255, 159, 64), Colour(201, 203, 207), Colour(120, 220, 120),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Colour(u8, u8, u8);
+
+impl Colour {
+    fn hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+enum Shape {
+    Circle { x: f64, y: f64, r: f64, fill: Colour, stroke: Option<Colour> },
+    Line { from: (f64, f64), to: (f64, f64), colour: Colour, width: f64, dashed: bool },
+    Text { x: f64, y: f64, text: String, colour: Colour, size: f64 },
+}
+
+/// Everything drawn on a map, kept independent of the file format it's written to
+pub struct Scene {
+    width: u32,
+    height: u32,
+    shapes: Vec<Shape>,
+}
+
+impl Scene {
+    fn new(width: u32, height: u32) -> Scene {
+        Scene { width, height, shapes: Vec::new() }
+    }
+
+    fn circle(&mut self, (x, y): (f64, f64), r: f64, fill: Colour, stroke: Option<Colour>) {
+        self.shapes.push(Shape::Circle { x, y, r, fill, stroke });
+    }
+
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), colour: Colour, width: f64, dashed: bool) {
+        self.shapes.push(Shape::Line { from, to, colour, width, dashed });
+    }
+
+    fn text(&mut self, (x, y): (f64, f64), text: &str, colour: Colour, size: f64) {
+        self.shapes.push(Shape::Text { x, y, text: text.to_string(), colour, size });
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"monospace\">\n<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+            BACKGROUND.hex(), w = self.width, h = self.height);
+
+        for shape in &self.shapes {
+            let element = match shape {
+                Shape::Circle { x, y, r, fill, stroke } => {
+                    let stroke = stroke.map(|s| format!(" stroke=\"{}\" stroke-width=\"2\"", s.hex())).unwrap_or_default();
+                    format!("<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\"{}/>", x, y, r, fill.hex(), stroke)
+                },
+                Shape::Line { from, to, colour, width, dashed } => {
+                    let dash = if *dashed { " stroke-dasharray=\"6 4\"" } else { "" };
+                    format!("<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"{}\"{}/>",
+                            from.0, from.1, to.0, to.1, colour.hex(), width, dash)
+                },
+                Shape::Text { x, y, text, colour, size } => {
+                    format!("<text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"{}\">{}</text>", x, y, colour.hex(), size, escape(text))
+                },
+            };
+            svg.push_str(&element);
+            svg.push('\n');
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Render the SVG, labels and all
+    fn to_png(&self) -> Result<Vec<u8>, String> {
+        let tree = Tree::from_str(&self.to_svg(), &render_options()).map_err(|e| format!("Failed to render the map - {}", e))?;
+        let mut pixmap = Pixmap::new(self.width, self.height).ok_or_else(|| format!("Can't make a {}x{} image", self.width, self.height))?;
+        resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+        pixmap.encode_png().map_err(|e| format!("Failed to encode the map - {}", e))
+    }
+}
+
+fn render_options() -> Options<'static> {
+    let mut options = Options::default();
+    let fonts = options.fontdb_mut();
+    fonts.load_system_fonts();
+
+    // Without fontconfig to say otherwise "monospace" means Courier New, so fall back to any fixed width face
+    let query = usvg::fontdb::Query { families: &[usvg::fontdb::Family::Monospace], ..Default::default() };
+    if fonts.query(&query).is_none() {
+        let family = fonts.faces().find(|f| f.monospaced).and_then(|f| f.families.first()).map(|(name, _)| name.clone());
+        if let Some(family) = family {
+            fonts.set_monospace_family(family);
+        }
+    }
+    options
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn system_colour(system_type: &SystemType) -> Colour {
+    match system_type {
+        SystemType::NeutronStar => Colour(159, 211, 255),
+        SystemType::RedStar => Colour(255, 90, 74),
+        SystemType::OrangeStar => Colour(255, 160, 64),
+        SystemType::BlueStar => Colour(90, 140, 255),
+        SystemType::YoungStar => Colour(255, 224, 128),
+        SystemType::WhiteDwarf => Colour(240, 240, 240),
+        SystemType::BlackHole => Colour(138, 92, 255),
+        SystemType::Hypergiant => Colour(255, 112, 192),
+        SystemType::Nebula => Colour(96, 208, 160),
+        SystemType::Unstable => Colour(192, 192, 64),
+    }
+}
+
+// Fill colour and radius of a waypoint in the system view
+fn waypoint_style(waypoint_type: &WaypointType) -> (Colour, f64) {
+    match waypoint_type {
+        WaypointType::Planet => (Colour(80, 150, 255), 12.0),
+        WaypointType::GasGiant => (Colour(230, 170, 90), 16.0),
+        WaypointType::Moon => (Colour(180, 180, 190), 6.0),
+        WaypointType::OrbitalStation => (Colour(120, 230, 230), 5.0),
+        WaypointType::JumpGate => (Colour(200, 110, 255), 10.0),
+        WaypointType::AsteroidField => (Colour(160, 130, 100), 9.0),
+        WaypointType::Nebula => (Colour(96, 208, 160), 11.0),
+        WaypointType::DebrisField => (Colour(140, 120, 110), 8.0),
+        WaypointType::GravityWell => (Colour(90, 70, 160), 11.0),
+    }
+}
+
+struct GalaxySystem {
+    system_type: SystemType,
+    x: i32,
+    y: i32,
+    faction: Option<String>,
+}
+
+/// Every system we know the position of, from listed systems, scans and jump gates, with the links between gates
+pub fn galaxy(context: &mut ConfigWrapper) -> Result<Scene, String> {
+    // Gates in systems we've visited. Uncharted ones can't be looked up, so those are left out
+    let api_config = context.api_config.clone();
+    let gates: Vec<String> = context.cache.waypoints.values()
+        .flatten()
+        .filter(|w| w.r#type == WaypointType::JumpGate)
+        .map(|w| w.symbol.clone())
+        .collect();
+    for gate in gates {
+        let _ = context.cache.jump_gate(&api_config, &gate);
+    }
+
+    let mut systems: HashMap<String, GalaxySystem> = HashMap::new();
+
+    for (symbol, s) in &context.cache.systems {
+        systems.insert(symbol.clone(), GalaxySystem { system_type: s.r#type, x: s.x, y: s.y, faction: None });
+    }
+    for gate in context.cache.jump_gates.values() {
+        for s in &gate.connected_systems {
+            systems.insert(s.symbol.clone(), GalaxySystem { system_type: s.r#type, x: s.x, y: s.y, faction: s.faction_symbol.clone() });
+        }
+    }
+    // Listed systems go last as they're the only ones that always carry their faction
+    for (symbol, s) in &context.cache.galaxy {
+        systems.insert(symbol.clone(), GalaxySystem {
+            system_type: s.r#type, x: s.x, y: s.y, faction: s.factions.first().map(|f| f.symbol.clone())
+        });
+    }
+
+    let home = context.home_system()?;
+    if !systems.contains_key(&home) {
+        let s = context.cache.system(&api_config, &home)?;
+        systems.insert(home.clone(), GalaxySystem { system_type: s.r#type, x: s.x, y: s.y, faction: s.factions.first().map(|f| f.symbol.clone()) });
+    }
+
+    let mut scene = Scene::new(GALAXY_SIZE, GALAXY_SIZE);
+    let points: Vec<(i32, i32)> = systems.values().map(|s| (s.x, s.y)).collect();
+    let size = GALAXY_SIZE as f64 - 2.0 * MARGIN;
+    let projection = Projection::fit(&points, size, size, 1.0);
+    let place = |x: i32, y: i32| {
+        let (px, py) = projection.project(x as f64, y as f64);
+        (px + MARGIN, py + MARGIN)
+    };
+
+    for (waypoint, gate) in &context.cache.jump_gates {
+        if let Some(from) = systems.get(&system_symbol(waypoint)) {
+            for to in &gate.connected_systems {
+                scene.line(place(from.x, from.y), place(to.x, to.y), LINK, 1.0, false);
+            }
+        }
+    }
+
+    let mut factions: Vec<String> = systems.values().filter_map(|s| s.faction.clone()).collect::<HashSet<String>>().into_iter().collect();
+    factions.sort();
+    let faction_colour = |faction: &Option<String>| {
+        faction.as_ref()
+            .and_then(|f| factions.iter().position(|known| known == f))
+            .map(|i| FACTION_COLOURS[i % FACTION_COLOURS.len()])
+    };
+
+    let mut symbols: Vec<&String> = systems.keys().collect();
+    symbols.sort();
+    for symbol in symbols {
+        let s = &systems[symbol];
+        let at = place(s.x, s.y);
+        scene.circle(at, 4.0, system_colour(&s.system_type), faction_colour(&s.faction));
+
+        if *symbol == home || systems.len() <= GALAXY_LABEL_LIMIT {
+            scene.text((at.0 + 6.0, at.1 - 6.0), symbol, TEXT, 11.0);
+        }
+    }
+
+    let at = place(systems[&home].x, systems[&home].y);
+    scene.circle(at, 9.0, BACKGROUND, Some(TEXT));
+    scene.circle(at, 4.0, system_colour(&systems[&home].system_type), faction_colour(&systems[&home].faction));
+
+    scene.text((MARGIN, 30.0), &format!("Galaxy - {} known systems, {} jump gates", systems.len(), context.cache.jump_gates.len()), TEXT, 18.0);
+    for (i, faction) in factions.iter().enumerate() {
+        let at = (GALAXY_SIZE as f64 - 220.0, 30.0 + i as f64 * 18.0);
+        scene.circle(at, 5.0, BACKGROUND, faction_colour(&Some(faction.clone())));
+        scene.text((at.0 + 12.0, at.1 + 4.0), faction, TEXT, 12.0);
+    }
+
+    Ok(scene)
+}
+
+/// A system's waypoints with their orbitals, markets, shipyards and our ships' routes
+pub fn system(context: &mut ConfigWrapper, system: &str) -> Result<Scene, String> {
+    let api_config = context.api_config.clone();
+    let waypoints = context.cache.system_waypoints(&api_config, system)?;
+    if waypoints.is_empty() {
+        return Err(format!("No waypoints found in {}", system));
+    }
+    let ships = map::ships_in_system(context, system)?;
+
+    let mut scene = Scene::new(SYSTEM_SIZE, SYSTEM_SIZE);
+    let points: Vec<(i32, i32)> = waypoints.iter().map(|w| (w.x, w.y)).collect();
+    let size = SYSTEM_SIZE as f64 - 2.0 * MARGIN;
+    let projection = Projection::fit(&points, size, size, 1.0);
+    let place = |x: i32, y: i32| {
+        let (px, py) = projection.project(x as f64, y as f64);
+        (px + MARGIN, py + MARGIN)
+    };
+
+    // Orbitals share their parent's coordinates, so they're fanned out around it
+    let orbiting: HashSet<&String> = waypoints.iter().flat_map(|w| w.orbitals.iter().map(|o| &o.symbol)).collect();
+    let mut positions: HashMap<&String, (f64, f64)> = HashMap::new();
+
+    for waypoint in waypoints.iter().filter(|w| !orbiting.contains(&w.symbol)) {
+        let at = place(waypoint.x, waypoint.y);
+        positions.insert(&waypoint.symbol, at);
+
+        let count = waypoint.orbitals.len() as f64;
+        for (i, orbital) in waypoint.orbitals.iter().enumerate() {
+            let angle = std::f64::consts::TAU * i as f64 / count;
+            positions.insert(&orbital.symbol, (at.0 + 26.0 * angle.cos(), at.1 + 26.0 * angle.sin()));
+        }
+    }
+
+    for waypoint in &waypoints {
+        let at = match positions.get(&waypoint.symbol) {
+            Some(at) => *at,
+            None => place(waypoint.x, waypoint.y)
+        };
+        let (fill, r) = waypoint_style(&waypoint.r#type);
+        let market = has_trait(waypoint, WaypointTraitSymbol::Marketplace);
+        let shipyard = has_trait(waypoint, WaypointTraitSymbol::Shipyard);
+        scene.circle(at, r, fill, if shipyard { Some(Colour(255, 255, 255)) } else { None });
+
+        let mut label = waypoint.symbol.rsplit('-').next().unwrap_or(&waypoint.symbol).to_string();
+        if market {
+            label.push_str(" $");
+        }
+        if shipyard {
+            label.push_str(" Y");
+        }
+        scene.text((at.0 + r + 3.0, at.1 + 4.0), &label, TEXT, 11.0);
+    }
+
+    for ship in &ships {
+        let route = &ship.nav.route;
+        let from = place(route.departure.x, route.departure.y);
+        let to = place(route.destination.x, route.destination.y);
+
+        let at = if ship.nav.status == ShipNavStatus::InTransit {
+            scene.line(from, to, ROUTE, 2.0, true);
+            let progress = map::route_progress(ship);
+            (from.0 + (to.0 - from.0) * progress, from.1 + (to.1 - from.1) * progress)
+        } else {
+            positions.get(&ship.nav.waypoint_symbol).copied().unwrap_or(to)
+        };
+
+        scene.circle(at, 4.0, ROUTE, Some(BACKGROUND));
+        scene.text((at.0 - 10.0, at.1 - 10.0), &ship.symbol, ROUTE, 11.0);
+    }
+
+    scene.text((MARGIN, 30.0), &format!("{} - {} waypoints, {} of our ships", system, waypoints.len(), ships.len()), TEXT, 18.0);
+    scene.text((MARGIN, SYSTEM_SIZE as f64 - 20.0), "$ market   Y shipyard (white outline)   green: our ships and routes", TEXT, 12.0);
+
+    Ok(scene)
+}
+
+/// Write a scene to `path`, as SVG or PNG depending on the extension
+pub fn write(scene: &Scene, path: &str) -> Result<String, String> {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+
+    match extension.as_deref() {
+        Some("svg") => {
+            std::fs::write(path, scene.to_svg()).map_err(|e| format!("Failed to write {} - {}", path, e))?;
+            Ok(format!("Wrote {}", path))
+        },
+        Some("png") => {
+            std::fs::write(path, scene.to_png()?).map_err(|e| format!("Failed to write {} - {}", path, e))?;
+            Ok(format!("Wrote {}", path))
+        },
+        _ => Err(format!("Don't know how to write {}, expected a .svg or .png file", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svg_has_every_shape_with_labels_escaped() {
+        let mut scene = Scene::new(100, 50);
+        scene.circle((10.0, 20.0), 4.0, Colour(255, 0, 0), Some(TEXT));
+        scene.line((0.0, 0.0), (10.0, 20.0), ROUTE, 2.0, true);
+        scene.text((12.5, 20.0), "X1-AB <HQ> & co", TEXT, 12.0);
+
+        let svg = scene.to_svg();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"100\" height=\"50\" viewBox=\"0 0 100 50\""));
+        assert!(svg.contains("<rect width=\"100%\" height=\"100%\" fill=\"#0b0e1a\"/>"));
+        assert!(svg.contains("<circle cx=\"10.0\" cy=\"20.0\" r=\"4.0\" fill=\"#ff0000\" stroke=\"#dce0ec\" stroke-width=\"2\"/>"));
+        assert!(svg.contains("<line x1=\"0.0\" y1=\"0.0\" x2=\"10.0\" y2=\"20.0\" stroke=\"#78ffa0\" stroke-width=\"2\" stroke-dasharray=\"6 4\"/>"));
+        assert!(svg.contains(">X1-AB &lt;HQ&gt; &amp; co</text>"));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    fn pixel(png: &[u8], x: u32, y: u32) -> [u8; 3] {
+        let pixmap = Pixmap::decode_png(png).unwrap();
+        let p = pixmap.pixel(x, y).unwrap();
+        [p.red(), p.green(), p.blue()]
+    }
+
+    #[test]
+    fn png_renders_the_scene() {
+        let mut scene = Scene::new(40, 20);
+        scene.circle((10.0, 10.0), 4.0, Colour(255, 0, 0), None);
+        let png = scene.to_png().unwrap();
+
+        let pixmap = Pixmap::decode_png(&png).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (40, 20));
+        assert_eq!(pixel(&png, 10, 10), [255, 0, 0]);
+        assert_eq!(pixel(&png, 30, 10), [BACKGROUND.0, BACKGROUND.1, BACKGROUND.2]);
+    }
+
+    #[test]
+    fn png_includes_labels() {
+        if render_options().fontdb.is_empty() {
+            return;
+        }
+
+        let blank = Scene::new(120, 30).to_png().unwrap();
+        let mut scene = Scene::new(120, 30);
+        scene.text((5.0, 20.0), "X1-AB12", TEXT, 16.0);
+        assert_ne!(scene.to_png().unwrap(), blank);
+    }
+}
@@ -0,0 +1,100 @@
+use chrono::Duration;
+use spacetraders_sdk::models::ShipNavFlightMode;
+
+use crate::cache::distance;
+
+// Fixed docking/undocking overhead the server adds to every trip
+const BASE_FLIGHT_SECONDS: i64 = 15;
+
+// One unit of FUEL bought at a market fills 100 units of a ship's tank
+pub const FUEL_UNITS_PER_MARKET_UNIT: i32 = 100;
+
+// Both are free for a leg that goes nowhere, e.g. when the ship is already at a contract's source,
+// or between a planet and a moon at the same coordinates
+pub fn flight_time(distance: f64, engine_speed: f32, mode: ShipNavFlightMode) -> Duration {
+    if distance.round() <= 0.0 {
+        return Duration::zero();
+    }
+
+    let multiplier = match mode {
+        ShipNavFlightMode::Cruise => 25.0,
+        ShipNavFlightMode::Drift => 250.0,
+        ShipNavFlightMode::Burn => 12.5,
+        ShipNavFlightMode::Stealth => 30.0,
+    };
+
+    let speed = f64::from(engine_speed).max(1.0);
+    let travel = (distance.round() * multiplier / speed).round() as i64;
+
+    Duration::seconds(BASE_FLIGHT_SECONDS + travel)
+}
+
+pub fn fuel_required(distance: f64, mode: ShipNavFlightMode) -> i32 {
+    let distance = distance.round() as i32;
+    if distance <= 0 {
+        return 0;
+    }
+
+    match mode {
+        ShipNavFlightMode::Cruise | ShipNavFlightMode::Stealth => distance,
+        ShipNavFlightMode::Burn => distance * 2,
+        ShipNavFlightMode::Drift => 1,
+    }
+}
+
+/// Where to stop for fuel on the way to `destination` when the tank won't get there in one go.
+/// The stop has to be in reach on what's in the tank, with the destination in reach of a full one
+/// from there. The shortest detour wins.
+pub fn refuel_stop(here: (i32, i32), destination: (i32, i32), fuel: i32, capacity: i32, stations: &[(String, (i32, i32))]) -> Option<&str> {
+    let trip = |at: (i32, i32)| distance(here, at) + distance(at, destination);
+
+    stations.iter()
+        .filter(|(_, at)| fuel_required(distance(here, *at), ShipNavFlightMode::Cruise) <= fuel)
+        .filter(|(_, at)| fuel_required(distance(*at, destination), ShipNavFlightMode::Cruise) <= capacity)
+        .min_by(|a, b| trip(a.1).total_cmp(&trip(b.1)))
+        .map(|(symbol, _)| symbol.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stations() -> Vec<(String, (i32, i32))> {
+        vec![("NEAR".to_string(), (30, 0)), ("OFF_ROUTE".to_string(), (30, 40)), ("FAR".to_string(), (90, 0))]
+    }
+
+    #[test]
+    fn flights_cost_fuel_and_time_by_mode() {
+        assert_eq!(fuel_required(41.6, ShipNavFlightMode::Cruise), 42);
+        assert_eq!(fuel_required(42.0, ShipNavFlightMode::Burn), 84);
+        assert_eq!(fuel_required(42.0, ShipNavFlightMode::Drift), 1);
+        assert_eq!(flight_time(100.0, 10.0, ShipNavFlightMode::Cruise), Duration::seconds(15 + 250));
+        assert_eq!(flight_time(100.0, 10.0, ShipNavFlightMode::Burn), Duration::seconds(15 + 125));
+    }
+
+    #[test]
+    fn same_place_legs_are_free() {
+        for mode in [ShipNavFlightMode::Cruise, ShipNavFlightMode::Burn, ShipNavFlightMode::Drift] {
+            assert_eq!(fuel_required(0.0, mode), 0);
+            assert_eq!(flight_time(0.3, 10.0, mode), Duration::zero());
+        }
+    }
+
+    #[test]
+    fn refuels_at_the_shortest_detour() {
+        assert_eq!(refuel_stop((0, 0), (100, 0), 50, 100, &stations()), Some("NEAR"));
+    }
+
+    #[test]
+    fn skips_stations_out_of_reach() {
+        // FAR would mean the shortest trip, but the tank only reaches NEAR or OFF_ROUTE
+        assert_eq!(refuel_stop((0, 0), (120, 0), 50, 100, &stations()), Some("NEAR"));
+        assert_eq!(refuel_stop((0, 0), (100, 0), 20, 100, &stations()), None);
+    }
+
+    #[test]
+    fn destination_must_be_in_reach_of_a_full_tank() {
+        assert_eq!(refuel_stop((0, 0), (200, 0), 100, 100, &stations()), None);
+        assert_eq!(refuel_stop((0, 0), (200, 0), 100, 120, &stations()), Some("FAR"));
+    }
+}
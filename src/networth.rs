@@ -0,0 +1,189 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use spacetraders_sdk::apis::{agents_api, fleet_api};
+use spacetraders_sdk::models::Ship;
+
+use crate::config::{profile_file, ConfigWrapper};
+
+pub const NET_WORTH_FILE: &str = "spacetraders_net_worth.json";
+
+// The API caps page sizes at 20
+const PAGE_LIMIT: i32 = 20;
+
+// Width in characters of the longest bar in the history chart
+const CHART_WIDTH: usize = 50;
+
+/// What the agent was worth at a point in time. Cargo is valued at the best price a known
+/// market in the ship's system would pay, ships at the cheapest price we've seen them listed for.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NetWorth {
+    pub timestamp: String,
+    pub credits: i64,
+    pub cargo_value: i64,
+    pub ship_value: i64,
+    pub ships: usize,
+    // Ships or goods we had no price for, so the total is a lower bound
+    pub unpriced: Vec<String>,
+}
+
+impl NetWorth {
+    pub fn total(&self) -> i64 {
+        self.credits + self.cargo_value + self.ship_value
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NetWorthHistory {
+    pub snapshots: Vec<NetWorth>,
+    // The profile's own file, see `profile_file`
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl NetWorthHistory {
+    pub fn load(profile: &str) -> NetWorthHistory {
+        let path = profile_file(NET_WORTH_FILE, profile);
+
+        let mut loaded = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file)).unwrap_or_default(),
+            Err(_) => NetWorthHistory::default()
+        };
+        loaded.path = path;
+        loaded
+    }
+
+    pub fn save(&self) {
+        if self.path.as_os_str().is_empty() {
+            return;
+        }
+
+        if let Err(e) = std::fs::write(&self.path, serde_json::to_string(self).unwrap()) {
+            println!("Failed to save net worth history {}", e);
+        }
+    }
+
+    pub fn record(&mut self, snapshot: NetWorth) {
+        self.snapshots.push(snapshot);
+        self.save();
+    }
+
+    /// One bar per snapshot, scaled so the largest total fills the chart width
+    pub fn chart(&self) -> String {
+        let max = self.snapshots.iter().map(|s| s.total()).max().unwrap_or(0).max(1);
+
+        self.snapshots.iter()
+            .map(|s| {
+                let width = (s.total().max(0) as f64 / max as f64 * CHART_WIDTH as f64).round() as usize;
+                format!("{} | {:<width$} {}", &s.timestamp[..s.timestamp.len().min(16)], "#".repeat(width), s.total(), width = CHART_WIDTH)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+pub fn calculate(context: &mut ConfigWrapper) -> Result<NetWorth, String> {
+    let credits = match agents_api::get_my_agent(&context.api_config) {
+        Ok(resp) => resp.data.credits,
+        Err(e) => return Err(format!("Failed to get agent data {}", e))
+    };
+    context.ledger.observe_balance(credits);
+
+    let ships = fetch_all_ships(context)?;
+    let mut unpriced = Vec::new();
+
+    let mut cargo_value = 0;
+    for ship in &ships {
+        for item in &ship.cargo.inventory {
+            match context.cache.best_sell_price(&ship.nav.system_symbol, &item.symbol) {
+                Some((price, _)) => cargo_value += i64::from(price) * i64::from(item.units),
+                None => unpriced.push(format!("{} on {}", item.symbol, ship.symbol))
+            }
+        }
+    }
+
+    let mut ship_value = 0;
+    for ship in &ships {
+        match context.cache.lowest_ship_price(ship.frame.symbol) {
+            Some(price) => ship_value += i64::from(price),
+            None => unpriced.push(ship.symbol.clone())
+        }
+    }
+
+    Ok(NetWorth {
+        timestamp: Utc::now().to_rfc3339(),
+        credits: i64::from(credits),
+        cargo_value,
+        ship_value,
+        ships: ships.len(),
+        unpriced,
+    })
+}
+
+pub fn fetch_all_ships(context: &ConfigWrapper) -> Result<Vec<Ship>, String> {
+    let mut ships = Vec::new();
+    let mut page = 1;
+
+    loop {
+        match fleet_api::get_my_ships(&context.api_config, Some(page), Some(PAGE_LIMIT)) {
+            Ok(resp) => {
+                let fetched = resp.data.len();
+                ships.extend(resp.data);
+
+                if fetched == 0 || ships.len() as i32 >= resp.meta.total {
+                    return Ok(ships);
+                }
+                page += 1;
+            },
+            Err(e) => return Err(format!("Failed to get ships {}", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(timestamp: &str, credits: i64, cargo_value: i64, ship_value: i64) -> NetWorth {
+        NetWorth { timestamp: timestamp.to_string(), credits, cargo_value, ship_value, ships: 1, unpriced: Vec::new() }
+    }
+
+    fn history(totals: &[i64]) -> NetWorthHistory {
+        let snapshots = totals.iter().enumerate()
+            .map(|(i, total)| snapshot(&format!("2026-01-0{}T12:00:00+00:00", i + 1), *total, 0, 0))
+            .collect();
+        NetWorthHistory { snapshots, path: PathBuf::new() }
+    }
+
+    // The bar of each line in a chart
+    fn bars(chart: &str) -> Vec<usize> {
+        chart.lines().map(|l| l.matches('#').count()).collect()
+    }
+
+    #[test]
+    fn total_is_credits_cargo_and_ships() {
+        assert_eq!(snapshot("", 150_000, 12_500, 80_000).total(), 242_500);
+        // Debt isn't hidden by what the fleet is worth
+        assert_eq!(snapshot("", -5_000, 1_000, 0).total(), -4_000);
+    }
+
+    #[test]
+    fn empty_history_charts_nothing() {
+        assert_eq!(history(&[]).chart(), "");
+    }
+
+    #[test]
+    fn chart_scales_to_the_largest_total() {
+        let chart = history(&[1000, 500, 0, 740]).chart();
+        assert_eq!(bars(&chart), vec![CHART_WIDTH, 25, 0, 37]);
+        assert!(chart.lines().next().unwrap().starts_with("2026-01-01T12:00 | "));
+        assert!(chart.lines().next().unwrap().ends_with(" 1000"));
+
+        // Below zero draws no bar, and all zeros doesn't divide by zero
+        assert_eq!(bars(&history(&[-200, 100]).chart()), vec![0, CHART_WIDTH]);
+        assert_eq!(bars(&history(&[0, 0]).chart()), vec![0, 0]);
+    }
+}
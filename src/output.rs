@@ -0,0 +1,194 @@
+use std::fmt;
+use std::str::FromStr;
+
+use comfy_table::Table;
+use serde::Serialize;
+use serde_json::{Map, Value as JsonValue};
+
+/// How commands print what they found. Everything but `Table` serializes the underlying
+/// records, usually the SDK models, so output can be piped into `jq` or a spreadsheet.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Format {
+    #[default]
+    Table,
+    Json,
+    Ndjson,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("Unknown output format {}, expected table, json, ndjson or csv", s))
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Table => write!(f, "table"),
+            Format::Json => write!(f, "json"),
+            Format::Ndjson => write!(f, "ndjson"),
+            Format::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+/// The table as is, or `records` in the chosen format
+pub fn render<T: Serialize>(format: Format, table: Table, records: &[T]) -> String {
+    if format == Format::Table {
+        return table.to_string();
+    }
+
+    let records = records.iter().map(|r| serde_json::to_value(r).unwrap_or(JsonValue::Null)).collect();
+    serialize(format, records)
+}
+
+/// For output with no model behind it, e.g. reports we work out ourselves, each row of the table
+/// becomes a record keyed by the column headers
+pub fn render_table(format: Format, table: Table) -> String {
+    if format == Format::Table {
+        return table.to_string();
+    }
+
+    let headers: Vec<String> = table.header()
+        .map(|h| h.cell_iter().map(|c| c.content()).collect())
+        .unwrap_or_default();
+
+    let records = table.row_iter()
+        .map(|row| {
+            let mut record = Map::new();
+            for (i, cell) in row.cell_iter().enumerate() {
+                let key = headers.get(i).cloned().unwrap_or_else(|| i.to_string());
+                record.insert(key, JsonValue::String(cell.content()));
+            }
+            JsonValue::Object(record)
+        })
+        .collect();
+
+    serialize(format, records)
+}
+
+fn serialize(format: Format, records: Vec<JsonValue>) -> String {
+    match format {
+        Format::Table | Format::Json => serde_json::to_string_pretty(&records).unwrap(),
+        Format::Ndjson => records.iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<String>>()
+            .join("\n"),
+        Format::Csv => csv(&records),
+    }
+}
+
+// One column per field, nested objects flattened to `a.b` and lists kept as JSON
+fn csv(records: &[JsonValue]) -> String {
+    let rows: Vec<Vec<(String, String)>> = records.iter()
+        .map(|r| {
+            let mut fields = Vec::new();
+            flatten("", r, &mut fields);
+            fields
+        })
+        .collect();
+
+    // Columns in the order they first appear, since optional fields can be missing from some records
+    let mut columns: Vec<String> = Vec::new();
+    for row in &rows {
+        for (key, _) in row {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+    }
+
+    let mut lines = vec![columns.iter().map(|c| csv_field(c)).collect::<Vec<String>>().join(",")];
+    for row in rows {
+        lines.push(columns.iter()
+            .map(|c| row.iter().find(|(key, _)| key == c).map(|(_, v)| csv_field(v)).unwrap_or_default())
+            .collect::<Vec<String>>()
+            .join(","));
+    }
+
+    lines.join("\n")
+}
+
+fn flatten(prefix: &str, value: &JsonValue, fields: &mut Vec<(String, String)>) {
+    match value {
+        JsonValue::Object(map) => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                flatten(&key, value, fields);
+            }
+        },
+        JsonValue::String(s) => fields.push((column(prefix), s.clone())),
+        JsonValue::Null => fields.push((column(prefix), String::new())),
+        other => fields.push((column(prefix), other.to_string())),
+    }
+}
+
+// Records that aren't objects get a single column
+fn column(prefix: &str) -> String {
+    if prefix.is_empty() { "value".to_string() } else { prefix.to_string() }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn formats_parse_regardless_of_case() {
+        assert_eq!("JSON".parse::<Format>().unwrap(), Format::Json);
+        assert_eq!("ndjson".parse::<Format>().unwrap(), Format::Ndjson);
+        assert_eq!("Csv".parse::<Format>().unwrap(), Format::Csv);
+        assert_eq!("xml".parse::<Format>().unwrap_err(), "Unknown output format xml, expected table, json, ndjson or csv");
+        assert_eq!(Format::Ndjson.to_string(), "ndjson");
+    }
+
+    #[test]
+    fn ndjson_is_one_record_a_line() {
+        let records = [json!({"symbol": "SHIP-1"}), json!({"symbol": "SHIP-2"})];
+        assert_eq!(render(Format::Ndjson, Table::new(), &records), "{\"symbol\":\"SHIP-1\"}\n{\"symbol\":\"SHIP-2\"}");
+    }
+
+    #[test]
+    fn csv_flattens_nested_fields() {
+        let records = [
+            json!({"symbol": "SHIP-1", "nav": {"status": "DOCKED"}, "modules": [1, 2]}),
+            json!({"symbol": "SHIP-2", "nav": {"status": "IN_ORBIT", "flightMode": "CRUISE"}, "modules": null}),
+        ];
+        assert_eq!(render(Format::Csv, Table::new(), &records),
+                   "symbol,nav.status,modules,nav.flightMode\nSHIP-1,DOCKED,\"[1,2]\",\nSHIP-2,IN_ORBIT,,CRUISE");
+    }
+
+    #[test]
+    fn csv_quotes_awkward_fields() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(render(Format::Csv, Table::new(), &[1, 2]), "value\n1\n2");
+    }
+
+    #[test]
+    fn tables_become_records_keyed_by_header() {
+        let mut table = Table::new();
+        table.set_header(vec!["Ship", "Credits"]);
+        table.add_row(vec!["SHIP-1", "120"]);
+        assert_eq!(render_table(Format::Ndjson, table.clone()), "{\"Ship\":\"SHIP-1\",\"Credits\":\"120\"}");
+        assert_eq!(render_table(Format::Csv, table), "Ship,Credits\nSHIP-1,120");
+    }
+}
@@ -0,0 +1,234 @@
+use spacetraders_sdk::apis::fleet_api;
+use spacetraders_sdk::models::ship_module::Symbol as ShipModuleSymbol;
+use spacetraders_sdk::models::ship_refine_request::Produce;
+use spacetraders_sdk::models::{Ship, ShipCargo, ShipRefineRequest, TransferCargoRequest};
+use spacetraders_sdk::wait::{self, ClockSkew};
+
+use crate::cache::UniverseCache;
+use crate::config::ConfigWrapper;
+
+pub struct Recipe {
+    pub produce: Produce,
+    pub input: &'static str,
+    pub input_units: i32,
+    pub output: &'static str,
+    pub output_units: i32,
+}
+
+// Each refine cycle turns 30 units of ore into 10 units of refined goods
+pub const RECIPES: [Recipe; 9] = [
+    Recipe { produce: Produce::Iron, input: "IRON_ORE", input_units: 30, output: "IRON", output_units: 10 },
+    Recipe { produce: Produce::Copper, input: "COPPER_ORE", input_units: 30, output: "COPPER", output_units: 10 },
+    Recipe { produce: Produce::Silver, input: "SILVER_ORE", input_units: 30, output: "SILVER", output_units: 10 },
+    Recipe { produce: Produce::Gold, input: "GOLD_ORE", input_units: 30, output: "GOLD", output_units: 10 },
+    Recipe { produce: Produce::Aluminum, input: "ALUMINUM_ORE", input_units: 30, output: "ALUMINUM", output_units: 10 },
+    Recipe { produce: Produce::Platinum, input: "PLATINUM_ORE", input_units: 30, output: "PLATINUM", output_units: 10 },
+    Recipe { produce: Produce::Uranite, input: "URANITE_ORE", input_units: 30, output: "URANITE", output_units: 10 },
+    Recipe { produce: Produce::Meritium, input: "MERITIUM_ORE", input_units: 30, output: "MERITIUM", output_units: 10 },
+    Recipe { produce: Produce::Fuel, input: "HYDROCARBON", input_units: 30, output: "FUEL", output_units: 10 },
+];
+
+/// What a refine cycle would be worth at the best known sell prices in the ship's system.
+pub struct RefineOption {
+    pub recipe: &'static Recipe,
+    pub cycles: i32,
+    // Credits gained per cycle by selling the output instead of the ore, if both prices are known
+    pub gain_per_cycle: Option<i32>,
+}
+
+pub fn has_refinery(ship: &Ship) -> bool {
+    ship.modules.iter().any(|m| matches!(m.symbol, ShipModuleSymbol::OreRefineryI | ShipModuleSymbol::FuelRefineryI | ShipModuleSymbol::MicroRefineryI))
+}
+
+pub fn cargo_units(cargo: &ShipCargo, trade_symbol: &str) -> i32 {
+    cargo.inventory.iter()
+        .filter(|item| item.symbol == trade_symbol)
+        .map(|item| item.units)
+        .sum()
+}
+
+/// Every recipe there's enough ore in `cargo` for, most profitable first at the prices seen in `system`.
+pub fn refine_options(cache: &UniverseCache, system: &str, cargo: &ShipCargo) -> Vec<RefineOption> {
+    let mut options: Vec<RefineOption> = RECIPES.iter()
+        .filter_map(|recipe| {
+            let cycles = cargo_units(cargo, recipe.input) / recipe.input_units;
+            if cycles == 0 {
+                return None;
+            }
+
+            // Ore nobody here buys is worth nothing to us, so an unknown input price counts as zero
+            let gain_per_cycle = cache.best_sell_price(system, recipe.output).map(|(output_price, _)| {
+                let input_price = cache.best_sell_price(system, recipe.input).map(|(p, _)| p).unwrap_or(0);
+                output_price * recipe.output_units - input_price * recipe.input_units
+            });
+
+            Some(RefineOption { recipe, cycles, gain_per_cycle })
+        })
+        .collect();
+
+    options.sort_by_key(|o| std::cmp::Reverse(o.gain_per_cycle.unwrap_or(i32::MIN)));
+    options
+}
+
+/// Runs refine cycles on a refinery ship, topping it up with ore from miners at the same waypoint.
+pub struct Refinery<'a> {
+    context: &'a mut ConfigWrapper,
+    ship: Ship,
+    miners: Vec<String>,
+    skew: ClockSkew,
+}
+
+impl<'a> Refinery<'a> {
+    pub fn new(context: &'a mut ConfigWrapper, ship_symbol: &str, miners: Vec<String>) -> Result<Refinery<'a>, String> {
+        let ship = fetch_ship(context, ship_symbol)?;
+
+        if !has_refinery(&ship) {
+            return Err(format!("{} has no refinery module", ship_symbol));
+        }
+
+        let skew = ClockSkew::measure(&context.api_config).unwrap_or_default();
+
+        Ok(Refinery { context, ship, miners, skew })
+    }
+
+    /// Refine until there's nothing worth refining, or `max_cycles` have run (0 for no limit)
+    pub fn run(&mut self, max_cycles: i32) -> Result<String, String> {
+        let mut cycles = 0;
+
+        while max_cycles == 0 || cycles < max_cycles {
+            self.collect_ore()?;
+
+            let option = refine_options(&self.context.cache, &self.ship.nav.system_symbol, &self.ship.cargo).into_iter()
+                .find(|o| o.gain_per_cycle.map(|g| g > 0).unwrap_or(false));
+
+            let option = match option {
+                Some(option) => option,
+                None => break
+            };
+
+            self.refine(option.recipe)?;
+            cycles += 1;
+        }
+
+        Ok(format!("Ran {} refine cycles on {}", cycles, self.ship.symbol))
+    }
+
+    // Pull refinable ore from any of our miners parked at the same waypoint
+    fn collect_ore(&mut self) -> Result<(), String> {
+        for miner_symbol in self.miners.clone() {
+            let miner = fetch_ship(self.context, &miner_symbol)?;
+
+            if miner.nav.waypoint_symbol != self.ship.nav.waypoint_symbol {
+                continue;
+            }
+
+            for recipe in RECIPES.iter() {
+                let free = self.ship.cargo.capacity - self.ship.cargo.units;
+                let units = cargo_units(&miner.cargo, recipe.input).min(free);
+                if units <= 0 {
+                    continue;
+                }
+
+                let req = TransferCargoRequest::new(recipe.input.to_string(), units, self.ship.symbol.clone());
+                match fleet_api::transfer_cargo(&self.context.api_config, &miner_symbol, Some(req)) {
+                    Ok(_) => {
+                        println!("[{}] Received {} {} from {}", self.ship.symbol, units, recipe.input, miner_symbol);
+                        self.ship = fetch_ship(self.context, &self.ship.symbol)?;
+                    },
+                    Err(e) => println!("[{}] Failed to transfer {} from {} - {}", self.ship.symbol, recipe.input, miner_symbol, e)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn refine(&mut self, recipe: &Recipe) -> Result<(), String> {
+        if let Ok(Some(cooldown)) = wait::current_cooldown(&self.context.api_config, &self.ship.symbol) {
+            wait::wait_for_cooldown(&self.context.api_config, &cooldown, &self.skew)
+                .map_err(|e| format!("Failed to get cooldown of {} - {}", self.ship.symbol, e))?;
+        }
+
+        match fleet_api::ship_refine(&self.context.api_config, &self.ship.symbol, Some(ShipRefineRequest::new(recipe.produce))) {
+            Ok(resp) => {
+                let summary = |goods: &Vec<spacetraders_sdk::models::ShipRefine200ResponseDataProducedInner>| goods.iter()
+                    .map(|g| format!("{} {}", g.units.unwrap_or(0), g.trade_symbol.clone().unwrap_or_default()))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+
+                println!("[{}] Refined {} into {}", self.ship.symbol, summary(&resp.data.consumed), summary(&resp.data.produced));
+                self.ship.cargo = resp.data.cargo;
+                Ok(())
+            },
+            Err(e) => Err(format!("Failed to refine {} - {}", recipe.output, e))
+        }
+    }
+}
+
+fn fetch_ship(context: &ConfigWrapper, ship_symbol: &str) -> Result<Ship, String> {
+    match fleet_api::get_my_ship(&context.api_config, ship_symbol) {
+        Ok(resp) => Ok(*resp.data),
+        Err(e) => Err(format!("Failed to get ship {} - {}", ship_symbol, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use spacetraders_sdk::models::market_trade_good::Supply;
+    use spacetraders_sdk::models::{Market, MarketTradeGood, ShipCargoItem};
+
+    use super::*;
+    use crate::cache::MarketSnapshot;
+
+    fn cargo(items: &[(&str, i32)]) -> ShipCargo {
+        let inventory: Vec<ShipCargoItem> = items.iter()
+            .map(|(symbol, units)| ShipCargoItem::new(symbol.to_string(), symbol.to_string(), String::new(), *units))
+            .collect();
+        ShipCargo::new(120, items.iter().map(|(_, units)| units).sum(), inventory)
+    }
+
+    // A market in X1-AB paying these prices
+    fn cache(sell_prices: &[(&str, i32)]) -> UniverseCache {
+        let mut market = Market::new("X1-AB-A1".to_string(), Vec::new(), Vec::new(), Vec::new());
+        market.trade_goods = Some(sell_prices.iter()
+            .map(|(good, price)| MarketTradeGood::new(good.to_string(), 100, Supply::Moderate, price + 5, *price))
+            .collect());
+
+        let mut cache = UniverseCache::default();
+        cache.markets.insert(market.symbol.clone(), MarketSnapshot { market, fetched_at: Utc::now().to_rfc3339() });
+        cache
+    }
+
+    fn outputs(options: &[RefineOption]) -> Vec<(&str, i32, Option<i32>)> {
+        options.iter().map(|o| (o.recipe.output, o.cycles, o.gain_per_cycle)).collect()
+    }
+
+    #[test]
+    fn cargo_units_add_up_every_stack() {
+        let cargo = cargo(&[("IRON_ORE", 20), ("COPPER_ORE", 5), ("IRON_ORE", 15)]);
+        assert_eq!(cargo_units(&cargo, "IRON_ORE"), 35);
+        assert_eq!(cargo_units(&cargo, "GOLD_ORE"), 0);
+    }
+
+    #[test]
+    fn options_need_a_whole_cycle_of_ore() {
+        let cache = cache(&[]);
+        let options = refine_options(&cache, "X1-AB", &cargo(&[("IRON_ORE", 65), ("COPPER_ORE", 29), ("QUARTZ_SAND", 40)]));
+        assert_eq!(outputs(&options), vec![("IRON", 2, None)]);
+    }
+
+    #[test]
+    fn most_profitable_first_and_unpriced_last() {
+        let cargo = cargo(&[("IRON_ORE", 30), ("COPPER_ORE", 30), ("GOLD_ORE", 30)]);
+        let cache = cache(&[("IRON", 50), ("IRON_ORE", 10), ("COPPER", 40), ("GOLD", 20), ("GOLD_ORE", 8)]);
+
+        // Iron gains 10 * 50 - 30 * 10, copper ore nobody buys counts as free, gold loses money
+        let options = refine_options(&cache, "X1-AB", &cargo);
+        assert_eq!(outputs(&options), vec![("COPPER", 1, Some(400)), ("IRON", 1, Some(200)), ("GOLD", 1, Some(-40))]);
+
+        // Prices from another system don't count
+        let options = refine_options(&cache, "X1-CD", &cargo);
+        assert!(options.iter().all(|o| o.gain_per_cycle.is_none()));
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use spacetraders_sdk::apis::configuration::Configuration;
+use spacetraders_sdk::apis::{contracts_api, fleet_api};
+use spacetraders_sdk::models::{DeliverContractRequest, ExtractResourcesRequest, NavigateShipRequest, PurchaseCargoRequest,
+                               SellCargoRequest, ShipNavStatus};
+use spacetraders_sdk::wait::{self, ClockSkew};
+
+use crate::cache::UniverseCache;
+use crate::config::profile_file;
+use crate::ledger::Ledger;
+
+pub const SCHEDULE_FILE: &str = "spacetraders_schedule.json";
+
+// How long a failed repeating task waits before its first retry, doubling with each failure after that
+const RETRY_BACKOFF_SECS: i64 = 30;
+const MAX_BACKOFF_SECS: i64 = 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Task {
+    Navigate { waypoint: String },
+    Dock,
+    Orbit,
+    Refuel,
+    Extract,
+    Survey,
+    Purchase { trade_symbol: String, units: i32 },
+    Sell { trade_symbol: String, units: i32 },
+    Deliver { contract: String, trade_symbol: String, units: i32 },
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Task::Navigate { waypoint } => write!(f, "navigate:{}", waypoint),
+            Task::Dock => write!(f, "dock"),
+            Task::Orbit => write!(f, "orbit"),
+            Task::Refuel => write!(f, "refuel"),
+            Task::Extract => write!(f, "extract"),
+            Task::Survey => write!(f, "survey"),
+            Task::Purchase { trade_symbol, units } => write!(f, "buy:{}:{}", trade_symbol, units),
+            Task::Sell { trade_symbol, units } => write!(f, "sell:{}:{}", trade_symbol, units),
+            Task::Deliver { contract, trade_symbol, units } => write!(f, "deliver:{}:{}:{}", contract, trade_symbol, units),
+        }
+    }
+}
+
+// Tasks are written as `name[:arg[:arg]]`, e.g. `navigate:X1-DF55-20250Z` or `sell:IRON_ORE:10`
+impl FromStr for Task {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+
+        let units = |i: usize| -> Result<i32, String> {
+            parts.get(i)
+                .ok_or(format!("Task {} is missing a unit count", s))?
+                .parse::<i32>()
+                .map_err(|e| format!("Invalid unit count in {} - {}", s, e))
+        };
+        let arg = |i: usize| -> Result<String, String> {
+            parts.get(i).map(|a| a.to_string()).ok_or(format!("Task {} is missing an argument", s))
+        };
+
+        match parts[0].to_lowercase().as_str() {
+            "navigate" => Ok(Task::Navigate { waypoint: arg(1)? }),
+            "dock" => Ok(Task::Dock),
+            "orbit" => Ok(Task::Orbit),
+            "refuel" => Ok(Task::Refuel),
+            "extract" => Ok(Task::Extract),
+            "survey" => Ok(Task::Survey),
+            "buy" => Ok(Task::Purchase { trade_symbol: arg(1)?, units: units(2)? }),
+            "sell" => Ok(Task::Sell { trade_symbol: arg(1)?, units: units(2)? }),
+            "deliver" => Ok(Task::Deliver { contract: arg(1)?, trade_symbol: arg(2)?, units: units(3)? }),
+            other => Err(format!("Unknown task {}", other))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduledTask {
+    pub id: u64,
+    pub priority: i32,
+    // Repeating tasks go back on the queue once they complete, e.g. to keep a miner extracting
+    pub repeat: bool,
+    pub task: Task,
+    // Failures in a row, for backing off a repeating task that keeps failing
+    #[serde(default)]
+    pub failures: u32,
+}
+
+/// Per-ship task queues, persisted so the fleet picks up where it left off when the CLI restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Schedule {
+    next_id: u64,
+    pub queues: HashMap<String, Vec<ScheduledTask>>,
+    // When each ship is next free to act, from its arrival time or cooldown expiry
+    pub busy_until: HashMap<String, String>,
+    // The profile's own file, see `profile_file`
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Schedule {
+    pub fn load(profile: &str) -> Schedule {
+        let path = profile_file(SCHEDULE_FILE, profile);
+
+        let mut loaded = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file)).unwrap_or_default(),
+            Err(_) => Schedule::default()
+        };
+        loaded.path = path;
+        loaded
+    }
+
+    pub fn save(&self) {
+        if self.path.as_os_str().is_empty() {
+            return;
+        }
+
+        if let Err(e) = std::fs::write(&self.path, serde_json::to_string_pretty(self).unwrap()) {
+            println!("Failed to save schedule {}", e);
+        }
+    }
+
+    pub fn enqueue(&mut self, ship: &str, task: Task, priority: i32, repeat: bool) -> u64 {
+        self.next_id += 1;
+        let id = self.next_id;
+
+        self.queues.entry(ship.to_string()).or_default().push(ScheduledTask { id, priority, repeat, task, failures: 0 });
+        self.save();
+
+        id
+    }
+
+    pub fn remove(&mut self, id: u64) -> bool {
+        let mut removed = false;
+        for queue in self.queues.values_mut() {
+            let before = queue.len();
+            queue.retain(|t| t.id != id);
+            removed |= queue.len() != before;
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+        self.save();
+
+        removed
+    }
+
+    pub fn clear(&mut self, ship: Option<&str>) {
+        match ship {
+            Some(ship) => {
+                self.queues.remove(ship);
+                self.busy_until.remove(ship);
+            },
+            None => {
+                self.queues.clear();
+                self.busy_until.clear();
+            }
+        }
+        self.save();
+    }
+
+    // Highest priority first, oldest first within a priority
+    pub fn ordered(&self, ship: &str) -> Vec<ScheduledTask> {
+        let mut tasks = self.queues.get(ship).cloned().unwrap_or_default();
+        tasks.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.id.cmp(&b.id)));
+        tasks
+    }
+
+    fn ready_at(&self, ship: &str) -> Option<DateTime<Utc>> {
+        self.busy_until.get(ship).and_then(|t| wait::parse_timestamp(t))
+    }
+
+    fn take_next(&mut self, ship: &str) -> Option<ScheduledTask> {
+        let next = self.ordered(ship).into_iter().next()?;
+
+        if let Some(queue) = self.queues.get_mut(ship) {
+            queue.retain(|t| t.id != next.id);
+        }
+
+        Some(next)
+    }
+
+    fn requeue(&mut self, ship: &str, task: ScheduledTask) {
+        self.next_id += 1;
+        self.queues.entry(ship.to_string()).or_default().push(ScheduledTask { id: self.next_id, ..task });
+    }
+
+    /// Run queued tasks until every queue is empty, or until `limit` has passed.
+    /// Ships that are travelling or cooling down are left alone until they're free.
+    pub fn run(&mut self, api_config: &Configuration, ledger: &mut Ledger, cache: &UniverseCache, limit: Option<Duration>) -> String {
+        // Busy times come from the server, so all comparisons happen on the server's clock
+        let skew = ClockSkew::measure(api_config).unwrap_or_default();
+        let stop_at = limit.map(|l| skew.server_now() + l);
+        let mut completed = 0;
+        let mut failed = 0;
+
+        for ship in self.queues.keys().cloned().collect::<Vec<String>>() {
+            if let Entry::Vacant(entry) = self.busy_until.entry(ship.clone()) {
+                if let Some(until) = current_busy_until(api_config, &ship) {
+                    entry.insert(until.to_rfc3339());
+                }
+            }
+        }
+
+        loop {
+            self.queues.retain(|_, queue| !queue.is_empty());
+
+            if self.queues.is_empty() {
+                break;
+            }
+
+            let now = skew.server_now();
+            if stop_at.map(|s| now >= s).unwrap_or(false) {
+                break;
+            }
+
+            let ready = self.queues.keys()
+                .find(|ship| self.ready_at(ship).map(|t| t <= now).unwrap_or(true))
+                .cloned();
+
+            let ship = match ready {
+                Some(ship) => ship,
+                None => {
+                    // Everyone is busy, sleep until the first ship frees up
+                    let mut wake = self.queues.keys()
+                        .filter_map(|ship| self.ready_at(ship))
+                        .min()
+                        .unwrap_or(now);
+                    if let Some(stop_at) = stop_at {
+                        wake = wake.min(stop_at);
+                    }
+                    thread::sleep(skew.until(wake));
+                    continue;
+                }
+            };
+
+            // We slept on a timestamp, so check with the server that the ship really is free
+            if self.busy_until.remove(&ship).is_some() {
+                if let Some(until) = current_busy_until(api_config, &ship).filter(|until| *until > skew.server_now()) {
+                    self.busy_until.insert(ship, until.to_rfc3339());
+                    continue;
+                }
+            }
+
+            let scheduled = match self.take_next(&ship) {
+                Some(task) => task,
+                None => continue
+            };
+
+            println!("[{}] Running {}", ship, scheduled.task);
+
+            match execute(api_config, ledger, cache, &ship, &scheduled.task) {
+                Ok(busy_until) => {
+                    completed += 1;
+                    match busy_until {
+                        Some(until) => {
+                            println!("[{}] Busy until {}", ship, until.to_rfc3339());
+                            self.busy_until.insert(ship.clone(), until.to_rfc3339());
+                        },
+                        None => {
+                            self.busy_until.remove(&ship);
+                        }
+                    }
+                    if scheduled.repeat {
+                        self.requeue(&ship, ScheduledTask { failures: 0, ..scheduled });
+                    }
+                },
+                Err(e) => {
+                    failed += 1;
+                    println!("[{}] {} failed - {}", ship, scheduled.task, e);
+
+                    // Retrying straight away would most likely fail the same way, e.g. with a full hold,
+                    // so park the ship for a while before trying again
+                    if scheduled.repeat {
+                        let failures = scheduled.failures + 1;
+                        let delay = backoff(failures);
+                        println!("[{}] Retrying {} in {}s", ship, scheduled.task, delay.num_seconds());
+                        self.busy_until.insert(ship.clone(), (skew.server_now() + delay).to_rfc3339());
+                        self.requeue(&ship, ScheduledTask { failures, ..scheduled });
+                    }
+                }
+            }
+
+            self.save();
+        }
+
+        self.save();
+
+        let remaining: usize = self.queues.values().map(|q| q.len()).sum();
+        format!("Completed {} tasks, {} failed, {} still queued", completed, failed, remaining)
+    }
+}
+
+// How long to hold off a repeating task after it has failed `failures` times in a row
+fn backoff(failures: u32) -> Duration {
+    let doublings = failures.saturating_sub(1).min(16);
+    Duration::seconds((RETRY_BACKOFF_SECS << doublings).min(MAX_BACKOFF_SECS))
+}
+
+// Work out whether a ship is mid-flight or cooling down before we give it anything to do
+fn current_busy_until(api_config: &Configuration, ship: &str) -> Option<DateTime<Utc>> {
+    let mut until = None;
+
+    if let Ok(resp) = fleet_api::get_ship_nav(api_config, ship) {
+        if resp.data.status == ShipNavStatus::InTransit {
+            until = wait::parse_timestamp(&resp.data.route.arrival);
+        }
+    }
+
+    if let Ok(Some(cooldown)) = wait::current_cooldown(api_config, ship) {
+        until = until.max(wait::parse_timestamp(&cooldown.expiration));
+    }
+
+    until
+}
+
+fn execute(api_config: &Configuration, ledger: &mut Ledger, cache: &UniverseCache, ship: &str, task: &Task) -> Result<Option<DateTime<Utc>>, String> {
+    match task {
+        Task::Navigate { waypoint } => {
+            orbit(api_config, ship)?;
+            match fleet_api::navigate_ship(api_config, ship, Some(NavigateShipRequest::new(waypoint.clone()))) {
+                Ok(resp) => Ok(wait::parse_timestamp(&resp.data.nav.route.arrival)),
+                Err(e) => Err(format!("Failed to navigate to {} - {}", waypoint, e))
+            }
+        },
+        Task::Dock => dock(api_config, ship).map(|_| None),
+        Task::Orbit => orbit(api_config, ship).map(|_| None),
+        Task::Refuel => {
+            // The tank beforehand, to cost the refuel from the fuel it adds
+            let before = fleet_api::get_my_ship(api_config, ship)
+                .map_err(|e| format!("Failed to get ship - {}", e))?
+                .data;
+            dock_from(api_config, ship, before.nav.status)?;
+
+            match fleet_api::refuel_ship(api_config, ship) {
+                Ok(resp) => {
+                    let price = cache.purchase_price(&before.nav.waypoint_symbol, "FUEL");
+                    ledger.record_refuel("schedule", ship, Some(resp.data.fuel.current - before.fuel.current), price, resp.data.agent.credits);
+                    println!("[{}] Fuel {}/{}", ship, resp.data.fuel.current, resp.data.fuel.capacity);
+                    Ok(None)
+                },
+                Err(e) => Err(format!("Failed to refuel - {}", e))
+            }
+        },
+        Task::Extract => {
+            orbit(api_config, ship)?;
+            match fleet_api::extract_resources(api_config, ship, Some(ExtractResourcesRequest::new())) {
+                Ok(resp) => {
+                    let extracted = &resp.data.extraction.r#yield;
+                    println!("[{}] Extracted {} {}, cargo {}/{}", ship, extracted.units, extracted.symbol,
+                             resp.data.cargo.units, resp.data.cargo.capacity);
+                    Ok(wait::parse_timestamp(&resp.data.cooldown.expiration))
+                },
+                Err(e) => Err(format!("Failed to extract resources - {}", e))
+            }
+        },
+        Task::Survey => {
+            orbit(api_config, ship)?;
+            match fleet_api::create_survey(api_config, ship) {
+                Ok(resp) => {
+                    println!("[{}] Created {} surveys", ship, resp.data.surveys.len());
+                    Ok(wait::parse_timestamp(&resp.data.cooldown.expiration))
+                },
+                Err(e) => Err(format!("Failed to survey - {}", e))
+            }
+        },
+        Task::Purchase { trade_symbol, units } => {
+            dock(api_config, ship)?;
+            match fleet_api::purchase_cargo(api_config, ship, Some(PurchaseCargoRequest::new(trade_symbol.clone(), *units))) {
+                Ok(resp) => {
+                    ledger.record_trade("schedule", &resp.data.transaction, resp.data.agent.credits);
+                    println!("[{}] Bought {} {} for {} credits", ship, units, trade_symbol, resp.data.transaction.total_price);
+                    Ok(None)
+                },
+                Err(e) => Err(format!("Failed to buy {} - {}", trade_symbol, e))
+            }
+        },
+        Task::Sell { trade_symbol, units } => {
+            dock(api_config, ship)?;
+            match fleet_api::sell_cargo(api_config, ship, Some(SellCargoRequest::new(trade_symbol.clone(), *units))) {
+                Ok(resp) => {
+                    ledger.record_trade("schedule", &resp.data.transaction, resp.data.agent.credits);
+                    println!("[{}] Sold {} {} for {} credits", ship, units, trade_symbol, resp.data.transaction.total_price);
+                    Ok(None)
+                },
+                Err(e) => Err(format!("Failed to sell {} - {}", trade_symbol, e))
+            }
+        },
+        Task::Deliver { contract, trade_symbol, units } => {
+            dock(api_config, ship)?;
+            let req = DeliverContractRequest::new(ship.to_string(), trade_symbol.clone(), *units);
+            match contracts_api::deliver_contract(api_config, contract, Some(req)) {
+                Ok(_) => {
+                    println!("[{}] Delivered {} {} to contract {}", ship, units, trade_symbol, contract);
+                    Ok(None)
+                },
+                Err(e) => Err(format!("Failed to deliver {} - {}", trade_symbol, e))
+            }
+        },
+    }
+}
+
+// Orbit and dock are skipped when the ship is already there, and refused while it's still flying
+fn orbit(api_config: &Configuration, ship: &str) -> Result<(), String> {
+    match nav_status(api_config, ship)? {
+        ShipNavStatus::InOrbit => Ok(()),
+        ShipNavStatus::InTransit => Err("Failed to orbit - the ship is still in transit".to_string()),
+        ShipNavStatus::Docked => fleet_api::orbit_ship(api_config, ship)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to orbit - {}", e))
+    }
+}
+
+fn dock(api_config: &Configuration, ship: &str) -> Result<(), String> {
+    dock_from(api_config, ship, nav_status(api_config, ship)?)
+}
+
+fn dock_from(api_config: &Configuration, ship: &str, status: ShipNavStatus) -> Result<(), String> {
+    match status {
+        ShipNavStatus::Docked => Ok(()),
+        ShipNavStatus::InTransit => Err("Failed to dock - the ship is still in transit".to_string()),
+        ShipNavStatus::InOrbit => fleet_api::dock_ship(api_config, ship)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to dock - {}", e))
+    }
+}
+
+fn nav_status(api_config: &Configuration, ship: &str) -> Result<ShipNavStatus, String> {
+    fleet_api::get_ship_nav(api_config, ship)
+        .map(|resp| resp.data.status)
+        .map_err(|e| format!("Failed to get nav status - {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tasks_round_trip_through_text() {
+        for text in ["navigate:X1-DF55-20250Z", "dock", "orbit", "refuel", "extract", "survey", "buy:FUEL:5", "sell:IRON_ORE:10",
+                     "deliver:abc123:COPPER_ORE:20"] {
+            assert_eq!(text.parse::<Task>().unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn bad_tasks_are_rejected() {
+        assert_eq!("fly".parse::<Task>().unwrap_err(), "Unknown task fly");
+        assert_eq!("navigate".parse::<Task>().unwrap_err(), "Task navigate is missing an argument");
+        assert_eq!("sell:IRON".parse::<Task>().unwrap_err(), "Task sell:IRON is missing a unit count");
+        assert!("sell:IRON:lots".parse::<Task>().unwrap_err().starts_with("Invalid unit count in sell:IRON:lots"));
+    }
+
+    #[test]
+    fn highest_priority_then_oldest_first() {
+        let mut schedule = Schedule::default();
+        for (task, priority) in [(Task::Dock, 0), (Task::Refuel, 5), (Task::Orbit, 5)] {
+            schedule.next_id += 1;
+            schedule.queues.entry("SHIP".to_string()).or_default()
+                .push(ScheduledTask { id: schedule.next_id, priority, repeat: false, task, failures: 0 });
+        }
+
+        let order: Vec<Task> = schedule.ordered("SHIP").into_iter().map(|t| t.task).collect();
+        assert_eq!(order, vec![Task::Refuel, Task::Orbit, Task::Dock]);
+        assert_eq!(schedule.take_next("SHIP").unwrap().task, Task::Refuel);
+        assert_eq!(schedule.ordered("SHIP").len(), 2);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_cap() {
+        assert_eq!(backoff(1), Duration::seconds(30));
+        assert_eq!(backoff(2), Duration::seconds(60));
+        assert_eq!(backoff(4), Duration::seconds(240));
+        assert_eq!(backoff(8), Duration::seconds(MAX_BACKOFF_SECS));
+        assert_eq!(backoff(u32::MAX), Duration::seconds(MAX_BACKOFF_SECS));
+    }
+}
@@ -0,0 +1,86 @@
+use std::io::stdout;
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+
+/// A view that takes over the whole terminal and refreshes itself, like `watch` and the dashboard.
+pub trait Screen {
+    // How long to leave between refreshes, which may change after each one
+    fn refresh_interval(&self) -> Duration;
+    fn refresh(&mut self);
+    // `next` is how long until the next refresh, for a countdown
+    fn draw(&self, width: u16, height: u16, next: Duration) -> std::io::Result<()>;
+}
+
+/// Show `screen` in the alternate screen until q, Esc or Ctrl-C, refreshing it every interval
+/// or straight away on r. `name` is what it's called in errors.
+pub fn run(screen: &mut impl Screen, name: &str) -> Result<(), String> {
+    let mut out = stdout();
+    terminal::enable_raw_mode().map_err(|e| format!("Failed to set up the terminal - {}", e))?;
+    execute!(out, EnterAlternateScreen, Hide).map_err(|e| format!("Failed to set up the terminal - {}", e))?;
+
+    let result = event_loop(screen, name);
+
+    // Always hand the terminal back, even if drawing failed
+    let _ = execute!(out, Show, LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+
+    result
+}
+
+fn event_loop(screen: &mut impl Screen, name: &str) -> Result<(), String> {
+    let mut last_refresh: Option<Instant> = None;
+
+    loop {
+        if last_refresh.map(|t| t.elapsed() >= screen.refresh_interval()).unwrap_or(true) {
+            screen.refresh();
+            last_refresh = Some(Instant::now());
+        }
+
+        let next = last_refresh.map(|t| screen.refresh_interval().saturating_sub(t.elapsed())).unwrap_or_default();
+        let (width, height) = size();
+        screen.draw(width, height, next).map_err(|e| format!("Failed to draw {} - {}", name, e))?;
+
+        // Wake every second so countdowns tick
+        if event::poll(Duration::from_secs(1)).map_err(|e| e.to_string())? {
+            if let Event::Key(key) = event::read().map_err(|e| e.to_string())? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                    KeyCode::Char('r') => last_refresh = None,
+                    _ => ()
+                }
+            }
+        }
+    }
+}
+
+/// How long a refresh costing `requests` has to take to keep to the rate limiter's steady rate
+/// on average, so a full-screen view never uses up the burst other commands rely on.
+pub fn rate_floor(requests: u64, per_second: f64) -> Duration {
+    Duration::from_secs_f64(requests as f64 / per_second)
+}
+
+// Some terminals, like a bare pty, report no size at all
+fn size() -> (u16, u16) {
+    terminal::size().ok().filter(|&(w, h)| w > 0 && h > 0).unwrap_or((80, 24))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bigger_refreshes_wait_longer() {
+        assert_eq!(rate_floor(0, 2.0), Duration::ZERO);
+        assert_eq!(rate_floor(4, 2.0), Duration::from_secs(2));
+        // A fleet of 30, with a cooldown each and the agent and contracts besides
+        assert_eq!(rate_floor(33, 2.0), Duration::from_millis(16500));
+    }
+}
@@ -0,0 +1,280 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use chrono::Utc;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope};
+use serde_json::Value as JsonValue;
+use spacetraders_sdk::apis::{agents_api, contracts_api, fleet_api, systems_api};
+use spacetraders_sdk::models::ship_refine_request::Produce;
+use spacetraders_sdk::models::{DeliverContractRequest, ExtractResourcesRequest, JettisonRequest, JumpShipRequest, NavigateShipRequest,
+                               PurchaseCargoRequest, SellCargoRequest, ShipRefineRequest, TransferCargoRequest};
+use spacetraders_sdk::wait::{self, ClockSkew};
+
+use crate::actions;
+use crate::config::ConfigWrapper;
+use crate::networth;
+
+// Fleet scripts are Rhai (https://rhai.rs/book/), with the SDK calls below registered as functions
+// for trying out strategies without recompiling.
+//
+//     // Sell everything the miners are carrying
+//     fn sell_all(ship) {
+//         for item in ship.cargo.inventory {
+//             let sold = sell(ship.symbol, item.symbol, item.units);
+//             print(`${ship.symbol} sold ${item.units} ${item.symbol} for ${sold.transaction.totalPrice}`);
+//         }
+//     }
+//
+//     for ship in ships() {
+//         if ship.nav.status == "DOCKED" && ship.cargo.units > 0 { sell_all(ship) }
+//     }
+//
+// The bindings return the API's models as object maps with the API's field names, and the script's
+// arguments are in `args`.
+
+type ScriptResult = Result<Dynamic, Box<EvalAltResult>>;
+
+// Register a script function with the context to hand. The body gives a `Result<_, String>`, and
+// an error comes back to the script naming the function that failed.
+macro_rules! bind {
+    ($engine:ident, $shared:ident, $name:literal, |$context:ident $(, $arg:ident: $ty:ty)*| $body:block) => {{
+        let shared = Rc::clone(&$shared);
+        $engine.register_fn($name, move |$($arg: $ty),*| -> ScriptResult {
+            let $context: &mut ConfigWrapper = &mut shared.borrow_mut();
+            // A closure gives `?` in the body somewhere to return to
+            #[allow(clippy::redundant_closure_call)]
+            let result = (|| -> Result<_, String> { $body })();
+            match result {
+                Ok(data) => rhai::serde::to_dynamic(data),
+                Err(e) => Err(format!("{} failed - {}", $name, e).into())
+            }
+        });
+    }};
+}
+
+/// Run a script, returning the value of its last statement, if any
+pub fn run(context: &mut ConfigWrapper, source: &str, args: Vec<String>) -> Result<Option<JsonValue>, String> {
+    // Registered functions have to own what they use, so the script works on a copy of the context that's
+    // written back afterwards, keeping what the ledger and cache picked up even if the script failed part way
+    let shared = Rc::new(RefCell::new(context.clone()));
+    let engine = engine(&shared);
+
+    let mut scope = Scope::new();
+    scope.push("args", args.into_iter().map(Dynamic::from).collect::<Array>());
+    let result = engine.eval_with_scope::<Dynamic>(&mut scope, source);
+
+    drop(engine);
+    *context = match Rc::try_unwrap(shared) {
+        Ok(shared) => shared.into_inner(),
+        Err(shared) => shared.borrow().clone()
+    };
+
+    let value = result.map_err(|e| e.to_string())?;
+    if value.is_unit() {
+        return Ok(None);
+    }
+    rhai::serde::from_dynamic::<JsonValue>(&value).map(Some).map_err(|e| e.to_string())
+}
+
+fn engine(shared: &Rc<RefCell<ConfigWrapper>>) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.register_fn("now", || Utc::now().to_rfc3339());
+
+    agent(&mut engine, shared);
+    fleet(&mut engine, shared);
+    contracts(&mut engine, shared);
+    systems(&mut engine, shared);
+
+    engine
+}
+
+fn agent(engine: &mut Engine, shared: &Rc<RefCell<ConfigWrapper>>) {
+    bind!(engine, shared, "agent", |context| {
+        let agent = api(agents_api::get_my_agent(&context.api_config))?.data;
+        context.ledger.observe_balance(agent.credits);
+        Ok(agent)
+    });
+}
+
+fn fleet(engine: &mut Engine, shared: &Rc<RefCell<ConfigWrapper>>) {
+    bind!(engine, shared, "ships", |context| { networth::fetch_all_ships(context) });
+    bind!(engine, shared, "ship", |context, ship: &str| { api(fleet_api::get_my_ship(&context.api_config, ship)).map(|r| r.data) });
+    bind!(engine, shared, "nav", |context, ship: &str| { api(fleet_api::get_ship_nav(&context.api_config, ship)).map(|r| r.data) });
+    bind!(engine, shared, "cargo", |context, ship: &str| { api(fleet_api::get_my_ship_cargo(&context.api_config, ship)).map(|r| r.data) });
+    bind!(engine, shared, "cooldown", |context, ship: &str| { api(wait::current_cooldown(&context.api_config, ship)) });
+    bind!(engine, shared, "orbit", |context, ship: &str| { api(fleet_api::orbit_ship(&context.api_config, ship)).map(|r| r.data) });
+    bind!(engine, shared, "dock", |context, ship: &str| { api(fleet_api::dock_ship(&context.api_config, ship)).map(|r| r.data) });
+
+    bind!(engine, shared, "navigate", |context, ship: &str, waypoint: &str| {
+        let request = NavigateShipRequest::new(waypoint.to_string());
+        api(fleet_api::navigate_ship(&context.api_config, ship, Some(request))).map(|r| r.data)
+    });
+    bind!(engine, shared, "warp", |context, ship: &str, waypoint: &str| {
+        let request = NavigateShipRequest::new(waypoint.to_string());
+        api(fleet_api::warp_ship(&context.api_config, ship, Some(request))).map(|r| r.data)
+    });
+    bind!(engine, shared, "jump", |context, ship: &str, system: &str| {
+        let request = JumpShipRequest::new(system.to_string());
+        api(fleet_api::jump_ship(&context.api_config, ship, Some(request))).map(|r| r.data)
+    });
+    bind!(engine, shared, "refuel", |context, ship: &str| {
+        let before = actions::tank(context, ship);
+        let data = api(fleet_api::refuel_ship(&context.api_config, ship))?.data;
+        let (added, price) = before.map(|(fuel, price)| (Some(data.fuel.current - fuel), price)).unwrap_or_default();
+        context.ledger.record_refuel("script", ship, added, price, data.agent.credits);
+        Ok(data)
+    });
+
+    bind!(engine, shared, "extract", |context, ship: &str| {
+        api(fleet_api::extract_resources(&context.api_config, ship, Some(ExtractResourcesRequest::new()))).map(|r| r.data)
+    });
+    bind!(engine, shared, "survey", |context, ship: &str| { api(fleet_api::create_survey(&context.api_config, ship)).map(|r| r.data) });
+    bind!(engine, shared, "chart", |context, ship: &str| {
+        let data = api(fleet_api::create_chart(&context.api_config, ship))?.data;
+        context.cache.record_waypoint((*data.waypoint).clone());
+        Ok(data)
+    });
+    bind!(engine, shared, "scan_waypoints", |context, ship: &str| {
+        let data = api(fleet_api::create_ship_waypoint_scan(&context.api_config, ship))?.data;
+        context.cache.record_scanned_waypoints(data.waypoints.clone());
+        Ok(data)
+    });
+    bind!(engine, shared, "scan_systems", |context, ship: &str| {
+        let data = api(fleet_api::create_ship_system_scan(&context.api_config, ship))?.data;
+        context.cache.record_scanned_systems(data.systems.clone());
+        Ok(data)
+    });
+    bind!(engine, shared, "scan_ships", |context, ship: &str| { api(fleet_api::create_ship_ship_scan(&context.api_config, ship)).map(|r| r.data) });
+
+    bind!(engine, shared, "buy", |context, ship: &str, good: &str, units: i64| {
+        let request = PurchaseCargoRequest::new(good.to_string(), whole(units)?);
+        let data = api(fleet_api::purchase_cargo(&context.api_config, ship, Some(request)))?.data;
+        context.ledger.record_trade("script", &data.transaction, data.agent.credits);
+        Ok(data)
+    });
+    bind!(engine, shared, "sell", |context, ship: &str, good: &str, units: i64| {
+        let request = SellCargoRequest::new(good.to_string(), whole(units)?);
+        let data = api(fleet_api::sell_cargo(&context.api_config, ship, Some(request)))?.data;
+        context.ledger.record_trade("script", &data.transaction, data.agent.credits);
+        Ok(data)
+    });
+    bind!(engine, shared, "jettison", |context, ship: &str, good: &str, units: i64| {
+        let request = JettisonRequest::new(good.to_string(), whole(units)?);
+        api(fleet_api::jettison(&context.api_config, ship, Some(request))).map(|r| r.data)
+    });
+    bind!(engine, shared, "transfer", |context, ship: &str, to: &str, good: &str, units: i64| {
+        let request = TransferCargoRequest::new(good.to_string(), whole(units)?, to.to_string());
+        api(fleet_api::transfer_cargo(&context.api_config, ship, Some(request))).map(|r| r.data)
+    });
+    bind!(engine, shared, "refine", |context, ship: &str, produce: &str| {
+        let produce: Produce = serde_json::from_value(JsonValue::String(produce.to_uppercase()))
+            .map_err(|_| format!("can't produce {}", produce))?;
+        api(fleet_api::ship_refine(&context.api_config, ship, Some(ShipRefineRequest::new(produce)))).map(|r| r.data)
+    });
+
+    // Both waits need the server's clock, which only has to be measured once a run
+    let skew: Rc<Cell<Option<ClockSkew>>> = Rc::new(Cell::new(None));
+    {
+        let skew = Rc::clone(&skew);
+        bind!(engine, shared, "wait_arrival", |context, ship: &str| {
+            let nav = api(fleet_api::get_ship_nav(&context.api_config, ship))?.data;
+            api(wait::wait_for_arrival(&context.api_config, ship, &nav, &measure(context, &skew)))
+        });
+    }
+    bind!(engine, shared, "wait_cooldown", |context, ship: &str| {
+        if let Some(cooldown) = api(wait::current_cooldown(&context.api_config, ship))? {
+            api(wait::wait_for_cooldown(&context.api_config, &cooldown, &measure(context, &skew)))?;
+        }
+        Ok(())
+    });
+}
+
+fn contracts(engine: &mut Engine, shared: &Rc<RefCell<ConfigWrapper>>) {
+    bind!(engine, shared, "contracts", |context| { api(contracts_api::get_contracts(&context.api_config, None, None)).map(|r| r.data) });
+    bind!(engine, shared, "contract", |context, contract: &str| { api(contracts_api::get_contract(&context.api_config, contract)).map(|r| r.data) });
+    bind!(engine, shared, "accept", |context, contract: &str| {
+        let data = api(contracts_api::accept_contract(&context.api_config, contract))?.data;
+        context.ledger.record_contract_payment(&data.contract.id, None, data.contract.terms.payment.on_accepted, data.agent.credits);
+        Ok(data)
+    });
+    bind!(engine, shared, "deliver", |context, contract: &str, ship: &str, good: &str, units: i64| {
+        let request = DeliverContractRequest::new(ship.to_string(), good.to_string(), whole(units)?);
+        api(contracts_api::deliver_contract(&context.api_config, contract, Some(request))).map(|r| r.data)
+    });
+    bind!(engine, shared, "fulfill", |context, contract: &str| {
+        let data = api(contracts_api::fulfill_contract(&context.api_config, contract))?.data;
+        context.ledger.record_contract_payment(&data.contract.id, None, data.contract.terms.payment.on_fulfilled, data.agent.credits);
+        Ok(data)
+    });
+}
+
+fn systems(engine: &mut Engine, shared: &Rc<RefCell<ConfigWrapper>>) {
+    bind!(engine, shared, "system", |context, system: &str| { api(systems_api::get_system(&context.api_config, system)).map(|r| r.data) });
+    bind!(engine, shared, "waypoints", |context, system: &str| { context.cache.system_waypoints(&context.api_config, system) });
+    bind!(engine, shared, "waypoint", |context, waypoint: &str| { context.cache.waypoint(&context.api_config, waypoint) });
+    bind!(engine, shared, "market", |context, waypoint: &str| { context.cache.market(&context.api_config, waypoint) });
+    bind!(engine, shared, "shipyard", |context, waypoint: &str| { context.cache.shipyard(&context.api_config, waypoint) });
+}
+
+fn api<T, E: std::fmt::Display>(result: Result<T, E>) -> Result<T, String> {
+    result.map_err(|e| e.to_string())
+}
+
+// Script numbers are 64 bit, the API's counts aren't
+fn whole(units: i64) -> Result<i32, String> {
+    i32::try_from(units).map_err(|_| format!("expected a whole number from {} to {}, got {}", i32::MIN, i32::MAX, units))
+}
+
+fn measure(context: &ConfigWrapper, skew: &Cell<Option<ClockSkew>>) -> ClockSkew {
+    let measured = skew.get().unwrap_or_else(|| ClockSkew::measure(&context.api_config).unwrap_or_default());
+    skew.set(Some(measured));
+    measured
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::GameConfig;
+
+    // A context whose requests fail straight away, since nothing listens on the discard port
+    fn context() -> ConfigWrapper {
+        let mut context = ConfigWrapper::new(GameConfig::new());
+        context.api_config.base_path = "http://127.0.0.1:9".to_string();
+        context
+    }
+
+    fn eval(source: &str) -> Result<Option<JsonValue>, String> {
+        run(&mut context(), source, vec!["first".to_string()])
+    }
+
+    #[test]
+    fn values_come_back_as_json() {
+        assert_eq!(eval(r#"#{ symbol: "SHIP-1", cargo: [1, 2.5, true, ()] }"#).unwrap(),
+                   Some(json!({"symbol": "SHIP-1", "cargo": [1, 2.5, true, null]})));
+        assert_eq!(eval("let x = 1;").unwrap(), None);
+        assert_eq!(eval("args[0] + args.len()").unwrap(), Some(json!("first1")));
+    }
+
+    #[test]
+    fn script_errors_are_reported() {
+        let err = eval("1 +").unwrap_err();
+        assert!(err.starts_with("Syntax error"), "{}", err);
+        assert!(eval("throw \"stop\"").unwrap_err().contains("stop"));
+        assert!(eval("9223372036854775807 + 1").unwrap_err().contains("overflow"));
+    }
+
+    #[test]
+    fn bindings_name_themselves_in_errors() {
+        let err = eval(r#"ship("SHIP-1")"#).unwrap_err();
+        assert!(err.contains("ship failed - "), "{}", err);
+
+        let err = eval(r#"buy("SHIP-1", "IRON", 4294967297)"#).unwrap_err();
+        assert!(err.contains("buy failed - expected a whole number from"), "{}", err);
+
+        let err = eval(r#"refine("SHIP-1", "CHEESE")"#).unwrap_err();
+        assert!(err.contains("refine failed - can't produce CHEESE"), "{}", err);
+    }
+}
@@ -0,0 +1,298 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::{Aes256Gcm, Nonce};
+use inquire::Password;
+use keyring::Entry;
+use pbkdf2::pbkdf2_hmac;
+use sha2::Sha256;
+
+// Shown in place of a secret anywhere we print config
+pub const REDACTED: &str = "<redacted>";
+
+// Identifies our entries in the OS secret service
+const KEYRING_SERVICE: &str = "spacetraders";
+
+// Environment variable holding the passphrase for the encrypted store, to avoid the prompt
+const PASSPHRASE_VAR: &str = "SPACETRADERS_PASSPHRASE";
+
+// Work factor for deriving the encryption key from the passphrase
+const PBKDF2_ITERATIONS: u32 = 200_000;
+
+// The encrypted store is the magic, then the salt, the nonce and the sealed tokens
+const MAGIC: &[u8] = b"STTOKENS1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Where agent tokens are kept. `Plain` leaves them in the config file as before.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Plain,
+    Keyring,
+    Encrypted,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "" | "plain" => Ok(Backend::Plain),
+            "keyring" => Ok(Backend::Keyring),
+            "encrypted" => Ok(Backend::Encrypted),
+            _ => Err(format!("Unknown token store {}, expected plain, keyring or encrypted", s))
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Plain => write!(f, "plain"),
+            Backend::Keyring => write!(f, "keyring"),
+            Backend::Encrypted => write!(f, "encrypted"),
+        }
+    }
+}
+
+/// Somewhere other than the config file to keep agent tokens, keyed by profile name.
+pub trait TokenStore {
+    fn load(&self, profiles: &[String]) -> Result<BTreeMap<String, String>, String>;
+    fn store(&self, tokens: &BTreeMap<String, String>) -> Result<(), String>;
+    // Forget these profiles' tokens, e.g. after moving them to another store
+    fn delete(&self, profiles: &[String]) -> Result<(), String>;
+}
+
+/// The store for a backend, or `None` for plain tokens in the config file.
+/// `config_path` decides where the encrypted store lives, alongside the config.
+pub fn token_store(backend: Backend, config_path: &Path) -> Option<Box<dyn TokenStore>> {
+    match backend {
+        Backend::Plain => None,
+        Backend::Keyring => Some(Box::new(KeyringStore)),
+        Backend::Encrypted => Some(Box::new(EncryptedStore {
+            path: config_path.with_extension("tokens.enc"),
+            passphrase: RefCell::new(None),
+        })),
+    }
+}
+
+/// The OS secret service: the keychain on macOS, the credential manager on Windows and
+/// the Secret Service (GNOME Keyring, KWallet) elsewhere.
+pub struct KeyringStore;
+
+impl KeyringStore {
+    fn entry(&self, profile: &str) -> Result<Entry, String> {
+        Entry::new(KEYRING_SERVICE, profile).map_err(|e| format!("Failed to open the keyring entry for {} - {}", profile, e))
+    }
+}
+
+impl TokenStore for KeyringStore {
+    fn load(&self, profiles: &[String]) -> Result<BTreeMap<String, String>, String> {
+        let mut tokens = BTreeMap::new();
+
+        for profile in profiles {
+            match self.entry(profile)?.get_password() {
+                Ok(token) => { tokens.insert(profile.clone(), token); }
+                Err(keyring::Error::NoEntry) => (),
+                Err(e) => return Err(format!("Failed to read the token for {} from the keyring - {}", profile, e))
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    fn store(&self, tokens: &BTreeMap<String, String>) -> Result<(), String> {
+        for (profile, token) in tokens {
+            self.entry(profile)?
+                .set_password(token)
+                .map_err(|e| format!("Failed to save the token for {} to the keyring - {}", profile, e))?;
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self, profiles: &[String]) -> Result<(), String> {
+        for profile in profiles {
+            match self.entry(profile)?.delete_credential() {
+                // A profile with nothing in the keyring is already gone
+                Ok(()) | Err(keyring::Error::NoEntry) => (),
+                Err(e) => return Err(format!("Failed to remove the token for {} from the keyring - {}", profile, e))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A local file of tokens encrypted with AES-256-GCM, under a key derived from a passphrase
+/// with PBKDF2. GCM authenticates as well as encrypts, so a wrong passphrase or a tampered
+/// file fails to open rather than decrypting to garbage.
+pub struct EncryptedStore {
+    path: PathBuf,
+    // Asked for once per session
+    passphrase: RefCell<Option<String>>,
+}
+
+impl EncryptedStore {
+    fn passphrase(&self) -> Result<String, String> {
+        if let Some(passphrase) = self.passphrase.borrow().as_ref() {
+            return Ok(passphrase.clone());
+        }
+
+        let passphrase = match std::env::var(PASSPHRASE_VAR) {
+            Ok(passphrase) if !passphrase.is_empty() => passphrase,
+            _ => Password::new("Token store passphrase:")
+                .without_confirmation()
+                .prompt()
+                .map_err(|e| format!("Failed to read passphrase {}", e))?
+        };
+
+        *self.passphrase.borrow_mut() = Some(passphrase.clone());
+        Ok(passphrase)
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+        let ciphertext = cipher(&self.passphrase()?, &salt)
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| format!("Failed to encrypt {}", self.path.display()))?;
+
+        Ok([MAGIC, &salt[..], &nonce[..], &ciphertext[..]].concat())
+    }
+
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let body = data.strip_prefix(MAGIC)
+            .filter(|body| body.len() >= SALT_LEN + NONCE_LEN)
+            .ok_or_else(|| format!("{} isn't an encrypted token store", self.path.display()))?;
+        let (salt, body) = body.split_at(SALT_LEN);
+        let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+
+        cipher(&self.passphrase()?, salt)
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                // Most likely a wrong passphrase, so let the next attempt ask again
+                *self.passphrase.borrow_mut() = None;
+                format!("Failed to decrypt {}, check the passphrase", self.path.display())
+            })
+    }
+}
+
+// The key for a passphrase, salted so the same passphrase gives a different key in every file
+fn cipher(passphrase: &str, salt: &[u8]) -> Aes256Gcm {
+    let mut key = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ITERATIONS, &mut key);
+    Aes256Gcm::new(&key.into())
+}
+
+impl TokenStore for EncryptedStore {
+    fn load(&self, _profiles: &[String]) -> Result<BTreeMap<String, String>, String> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+
+        let encrypted = std::fs::read(&self.path).map_err(|e| format!("Failed to read {} - {}", self.path.display(), e))?;
+        let decrypted = self.decrypt(&encrypted)?;
+
+        serde_json::from_slice(&decrypted).map_err(|e| format!("Failed to parse {} - {}", self.path.display(), e))
+    }
+
+    fn store(&self, tokens: &BTreeMap<String, String>) -> Result<(), String> {
+        let encrypted = self.encrypt(serde_json::to_string(tokens).unwrap().as_bytes())?;
+
+        // Only readable by us, like an ssh key
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        options.open(&self.path)
+            .and_then(|mut file| {
+                // The mode only applies to new files, so tighten one written before we set it
+                #[cfg(unix)]
+                file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+                file.write_all(&encrypted)
+            })
+            .map_err(|e| format!("Failed to write {} - {}", self.path.display(), e))
+    }
+
+    fn delete(&self, profiles: &[String]) -> Result<(), String> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+
+        let mut tokens = self.load(profiles)?;
+        tokens.retain(|profile, _| !profiles.contains(profile));
+        if tokens.is_empty() {
+            return std::fs::remove_file(&self.path).map_err(|e| format!("Failed to remove {} - {}", self.path.display(), e));
+        }
+        self.store(&tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(path: &Path, passphrase: &str) -> EncryptedStore {
+        EncryptedStore { path: path.to_path_buf(), passphrase: RefCell::new(Some(passphrase.to_string())) }
+    }
+
+    #[test]
+    fn encrypted_store_is_private_and_forgets_deleted_profiles() {
+        let path = std::env::temp_dir().join(format!("spacetraders-test-{}.tokens.enc", std::process::id()));
+        let store = store(&path, "hunter2");
+        let tokens = BTreeMap::from([("main".to_string(), "aaa".to_string()), ("alt".to_string(), "bbb".to_string())]);
+
+        store.store(&tokens).unwrap();
+        assert_eq!(store.load(&[]).unwrap(), tokens);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        store.delete(&["alt".to_string()]).unwrap();
+        assert_eq!(store.load(&[]).unwrap().into_keys().collect::<Vec<String>>(), vec!["main"]);
+
+        store.delete(&["main".to_string()]).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn encrypted_store_rejects_the_wrong_passphrase() {
+        let path = std::env::temp_dir().join(format!("spacetraders-test-{}-wrong.tokens.enc", std::process::id()));
+        let tokens = BTreeMap::from([("main".to_string(), "aaa".to_string())]);
+        store(&path, "hunter2").store(&tokens).unwrap();
+
+        // The file holds neither the token nor the passphrase in the clear
+        let written = std::fs::read(&path).unwrap();
+        assert!(!written.windows(3).any(|w| w == b"aaa"));
+        assert!(!written.windows(7).any(|w| w == b"hunter2"));
+
+        let wrong = store(&path, "hunter3");
+        let error = wrong.load(&[]).unwrap_err();
+        assert!(error.contains("check the passphrase"), "{}", error);
+        // So it's asked for again next time
+        assert!(wrong.passphrase.borrow().is_none());
+
+        // A tampered file fails the same way rather than decrypting to garbage
+        let mut tampered = written.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, tampered).unwrap();
+        assert!(store(&path, "hunter2").load(&[]).is_err());
+
+        std::fs::write(&path, b"not ours").unwrap();
+        assert!(store(&path, "hunter2").load(&[]).unwrap_err().contains("isn't an encrypted token store"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
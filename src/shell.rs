@@ -0,0 +1,713 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+use crossterm::style::Stylize;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use spacetraders_sdk::apis::contracts_api;
+use spacetraders_sdk::models::register_request::Faction;
+use spacetraders_sdk::models::ship_refine_request::Produce;
+use spacetraders_sdk::models::{ShipType, TradeSymbol};
+
+use comfy_table::Table;
+
+use crate::commands::{command, CommandError, CommandResult, CommandSpec, Value};
+use crate::config::{ConfigWrapper, Macro};
+use crate::watch::Watch;
+use crate::{networth, output};
+
+const NAME: &str = "Spacetraders";
+const DESCRIPTION: &str = "REPL CLI for spacetraders";
+
+// How long fetched ship and contract symbols are offered before they're looked up again
+const REFRESH: Duration = Duration::from_secs(60);
+
+const HISTORY_FILE: &str = "spacetraders_history.txt";
+
+// Deep enough for shortcuts built on shortcuts, shallow enough to stop one that calls itself
+const MAX_DEPTH: usize = 8;
+
+// Handled by the shell itself rather than a command spec
+const BUILTINS: [(&str, &[&str], &str); 3] = [
+    ("help", &["command"], "Show every command, or how to use one"),
+    ("source", &["file"], "Run each line of a file as a command, skipping blank lines and # comments"),
+    ("watch", &["interval", "command"], "Re-run a read command every given number of seconds, highlighting what changed"),
+];
+
+/// The interactive prompt. Runs the same command specs as the CLI, and completes
+/// arguments from what we know about the fleet and the universe.
+pub struct Shell<'a> {
+    specs: &'a [CommandSpec],
+    context: ConfigWrapper,
+    fetched_at: Option<Instant>,
+}
+
+impl<'a> Shell<'a> {
+    pub fn new(specs: &'a [CommandSpec], context: ConfigWrapper) -> Shell<'a> {
+        Shell { specs, context, fetched_at: None }
+    }
+
+    pub fn run(&mut self) -> Result<(), String> {
+        // Symbol lists run long, so list the matches as a shell does rather than cycling through them
+        let config = Config::builder().completion_type(CompletionType::List).build();
+        let mut editor: Editor<ShellHelper, DefaultHistory> = Editor::with_config(config).map_err(|e| e.to_string())?;
+        editor.set_helper(Some(ShellHelper::new(self.specs)));
+
+        // No history yet is fine, it's created after the first command
+        let _ = editor.load_history(HISTORY_FILE);
+
+        let prompt = format!("{}> ", NAME.green().bold());
+        println!("Welcome to {}", NAME);
+
+        loop {
+            self.update_candidates(editor.helper_mut().unwrap());
+
+            match editor.readline(&prompt) {
+                Ok(line) => {
+                    // Saved as we go, as `exit` ends the process from inside a command
+                    if editor.add_history_entry(self.history_line(&line)).unwrap_or(false) {
+                        if let Err(e) = editor.save_history(HISTORY_FILE) {
+                            eprintln!("Failed to save history - {}", e);
+                        }
+                    }
+                    if let Err(e) = self.process_line(&line, 0) {
+                        eprintln!("{}", e);
+                    }
+                },
+                Err(ReadlineError::Eof) => return Ok(()),
+                Err(ReadlineError::Interrupted) => (),
+                Err(e) => eprintln!("Error reading line: {}", e)
+            }
+        }
+    }
+
+    // The line as it goes into the history file, which is plain text, so tokens and the like are left out
+    fn history_line(&self, line: &str) -> String {
+        let mut words = split(line);
+        // Follow aliases, so one standing for add_profile is caught too
+        for _ in 0..MAX_DEPTH {
+            match words.first().and_then(|name| self.context.user_config.aliases.get(name)) {
+                Some(alias) => {
+                    let mut expanded = split(alias);
+                    expanded.extend(words.drain(1..));
+                    words = expanded;
+                },
+                None => break
+            }
+        }
+
+        let spec = match words.first().and_then(|name| self.specs.iter().find(|s| s.name == name.as_str())) {
+            Some(spec) if spec.params.iter().any(|p| p.secret) => spec,
+            _ => return line.to_string()
+        };
+
+        let args = spec.redact(&words.split_off(1));
+        words.extend(args);
+        words.iter()
+            .map(|w| if w.is_empty() || w.contains(char::is_whitespace) { format!("\"{}\"", w.replace('"', "\\\"")) } else { w.clone() })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    fn process_line(&mut self, line: &str, depth: usize) -> Result<(), CommandError> {
+        let mut words = split(line);
+        if words.is_empty() {
+            return Ok(());
+        }
+        let name = words.remove(0);
+
+        if depth > MAX_DEPTH {
+            return Err(format!("Gave up at {}, aliases and macros are nested too deeply. Does one call itself?", name).into());
+        }
+
+        match name.as_str() {
+            "help" => {
+                self.help(words.first());
+                return Ok(());
+            },
+            "source" => return self.source(words.first().ok_or("Error: Missing required argument 'file' for command 'source'")?, depth),
+            "watch" => return self.watch(&words),
+            _ => ()
+        }
+
+        if let Some(alias) = self.context.user_config.aliases.get(&name) {
+            // Keep the rest of the line as typed, quotes and all
+            return self.process_line(&format!("{}{}", alias, after_first_word(line)), depth + 1);
+        }
+
+        if let Some(shortcut) = self.context.user_config.macros.get(&name).cloned() {
+            return self.run_macro(&name, &shortcut, &words, depth);
+        }
+
+        let spec = self.specs.iter().find(|s| s.name == name)
+            .ok_or_else(|| CommandError(format!("Error: Unknown command '{}'", name)))?;
+        let args = spec.positional(&words)?;
+
+        let before = serde_json::to_value(self.context.user_config.persisted()).ok();
+        let result = (spec.handler)(args, &mut self.context);
+
+        // Keep new aliases, profiles and the like even if the session ends without `exit`
+        if serde_json::to_value(self.context.user_config.persisted()).ok() != before {
+            self.context.save();
+        }
+
+        if let Some(output) = result? {
+            println!("{}", output);
+        }
+        Ok(())
+    }
+
+    fn run_macro(&mut self, name: &str, shortcut: &Macro, args: &[String], depth: usize) -> Result<(), CommandError> {
+        if args.len() != shortcut.params.len() {
+            return Err(format!("Macro {} takes {} arguments ({}), got {}", name, shortcut.params.len(), shortcut.params.join(" "), args.len()).into());
+        }
+
+        // Longest names first, so $ship_to isn't taken for $ship followed by _to
+        let mut bindings: Vec<(&String, String)> = shortcut.params.iter()
+            .zip(args)
+            .map(|(param, arg)| (param, if arg.contains(char::is_whitespace) { format!("\"{}\"", arg) } else { arg.clone() }))
+            .collect();
+        bindings.sort_by_key(|(param, _)| std::cmp::Reverse(param.len()));
+
+        for command in &shortcut.commands {
+            let mut line = command.clone();
+            for (param, arg) in &bindings {
+                line = line.replace(&format!("${}", param), arg);
+            }
+            self.process_line(&line, depth + 1).map_err(|e| format!("{} in macro {}: {}", line, name, e))?;
+        }
+        Ok(())
+    }
+
+    fn watch(&mut self, words: &[String]) -> Result<(), CommandError> {
+        let interval = words.first().ok_or("Error: Missing required argument 'interval' for command 'watch'")?;
+        let interval: u64 = Value::new(interval).convert()?;
+        if words.len() < 2 {
+            return Err("Error: Missing required argument 'command' for command 'watch'".into());
+        }
+
+        // Follow aliases to the command they stand for, keeping the arguments typed after them
+        let mut command = words[1..].to_vec();
+        for _ in 0..MAX_DEPTH {
+            match self.context.user_config.aliases.get(&command[0]) {
+                Some(alias) => {
+                    let mut expanded = split(alias);
+                    expanded.extend(command.drain(1..));
+                    command = expanded;
+                },
+                None => break
+            }
+        }
+        if command.is_empty() {
+            return Err("Error: Missing required argument 'command' for command 'watch'".into());
+        }
+        if self.context.user_config.macros.contains_key(&command[0]) || BUILTINS.iter().any(|(name, _, _)| *name == command[0]) {
+            return Err(format!("Only a single command can be watched, not {}", command[0]).into());
+        }
+
+        let specs = self.specs;
+        let spec = specs.iter().find(|s| s.name == command[0])
+            .ok_or_else(|| CommandError(format!("Error: Unknown command '{}'", command[0])))?;
+        let args = spec.positional(&command[1..])?;
+
+        let output = Watch::new(&mut self.context, spec, args, command.join(" "), interval)?.run()?;
+        println!("{}", output);
+        Ok(())
+    }
+
+    fn source(&mut self, file: &str, depth: usize) -> Result<(), CommandError> {
+        let contents = std::fs::read_to_string(file).map_err(|e| format!("Failed to read {} - {}", file, e))?;
+
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.process_line(line, depth + 1).map_err(|e| format!("{} line {}: {}", file, i + 1, e))?;
+        }
+        Ok(())
+    }
+
+    fn help(&self, command: Option<&String>) {
+        let config = &self.context.user_config;
+
+        match command {
+            Some(command) => match self.specs.iter().find(|s| s.name == command) {
+                Some(spec) => {
+                    println!("{}: {}", spec.name, spec.help);
+                    println!("Usage:");
+                    println!("\t{}", spec.usage());
+                },
+                None => match BUILTINS.iter().find(|(name, _, _)| name == command) {
+                    Some((name, params, help)) => {
+                        println!("{}: {}", name, help);
+                        println!("Usage:");
+                        println!("\t{} {}", name, params.join(" "));
+                    },
+                    None => match (config.aliases.get(command), config.macros.get(command)) {
+                        (Some(alias), _) => println!("{}: alias for {}", command, alias),
+                        (_, Some(shortcut)) => {
+                            println!("{}: macro running {}", command, shortcut.commands.join("; "));
+                            println!("Usage:");
+                            println!("\t{} {}", command, shortcut.params.join(" "));
+                        },
+                        _ => eprintln!("Help not found for command '{}'", command)
+                    }
+                }
+            },
+            None => {
+                let header = format!("{}: {}", NAME, DESCRIPTION);
+                println!("{}", header);
+                println!("{}", "-".repeat(header.len()));
+
+                let mut entries: Vec<(&str, &str)> = self.specs.iter().map(|s| (s.name, s.help)).collect();
+                entries.extend(BUILTINS.iter().map(|(name, _, help)| (*name, *help)));
+                entries.sort();
+                for (name, help) in entries {
+                    println!("{} - {}", name, help);
+                }
+
+                for (name, alias) in &config.aliases {
+                    println!("{} - alias for {}", name, alias);
+                }
+                for (name, shortcut) in &config.macros {
+                    println!("{} - macro running {}", name, shortcut.commands.join("; "));
+                }
+            }
+        }
+    }
+
+    // Symbols from the cache are cheap to collect every time, ships and contracts cost a request so they're kept a while
+    fn update_candidates(&mut self, helper: &mut ShellHelper) {
+        let cache = &self.context.cache;
+        helper.waypoints = cache.waypoints.values().flatten().map(|w| w.symbol.clone()).collect();
+        helper.systems = cache.waypoints.keys()
+            .chain(cache.systems.keys())
+            .chain(cache.galaxy.keys())
+            .cloned()
+            .collect();
+        helper.profiles = self.context.user_config.profiles.keys().cloned().collect();
+        helper.shortcuts = self.context.user_config.macros.iter()
+            .map(|(name, shortcut)| (name.clone(), shortcut.params.clone()))
+            .collect();
+        // An alias takes whatever parameters are left over from the command it starts
+        for (name, alias) in &self.context.user_config.aliases {
+            let words = split(alias);
+            if let Some((_, params)) = words.first().and_then(|command| helper.commands.iter().find(|(c, _)| c == command)) {
+                let params = params.iter().skip(words.len() - 1).map(|p| p.to_string()).collect();
+                helper.shortcuts.push((name.clone(), params));
+            }
+        }
+
+        if self.fetched_at.map(|t| t.elapsed() < REFRESH).unwrap_or(false) || self.context.user_config.access_token.is_empty() {
+            return;
+        }
+        self.fetched_at = Some(Instant::now());
+
+        if let Ok(ships) = networth::fetch_all_ships(&self.context) {
+            helper.ships = ships.into_iter().map(|s| s.symbol).collect();
+        }
+        if let Ok(resp) = contracts_api::get_contracts(&self.context.api_config, None, None) {
+            helper.contracts = resp.data.into_iter().map(|c| c.id).collect();
+        }
+    }
+}
+
+// Words separated by spaces, with double quotes grouping words into one argument and \" for a quote inside one
+fn split(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim().chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'"') => word.push(chars.next().unwrap()),
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            },
+            c => word.push(c)
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+// Whatever follows the first word of a line, exactly as typed
+fn after_first_word(line: &str) -> &str {
+    let line = line.trim_start();
+    let mut quoted = false;
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if chars.peek().map(|&(_, c)| c) == Some('"') => {
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => return &line[i..],
+            _ => ()
+        }
+    }
+    ""
+}
+
+/// The names an enum is serialized as, e.g. every `TradeSymbol`, read from its `Deserialize` impl
+pub fn variants<T: DeserializeOwned>() -> &'static [&'static str] {
+    struct Names(&'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for &mut Names {
+        type Error = de::value::Error;
+
+        fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, variants: &'static [&'static str], _visitor: V) -> Result<V::Value, Self::Error> {
+            self.0 = variants;
+            Err(de::Error::custom("only after the variant names"))
+        }
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not an enum"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit unit_struct
+            newtype_struct seq tuple tuple_struct map struct identifier ignored_any
+        }
+    }
+
+    let mut names = Names(&[]);
+    let _ = T::deserialize(&mut names);
+    names.0
+}
+
+struct ShellHelper {
+    // Parameter names for each command, in order
+    commands: Vec<(&'static str, Vec<&'static str>)>,
+    // The same for aliases and macros, which can change during a session
+    shortcuts: Vec<(String, Vec<String>)>,
+    ships: Vec<String>,
+    waypoints: BTreeSet<String>,
+    systems: BTreeSet<String>,
+    contracts: Vec<String>,
+    profiles: Vec<String>,
+    files: FilenameCompleter,
+}
+
+impl ShellHelper {
+    fn new(specs: &[CommandSpec]) -> ShellHelper {
+        let mut commands: Vec<(&'static str, Vec<&'static str>)> = specs.iter()
+            .map(|s| (s.name, s.params.iter().map(|p| p.name).collect()))
+            .collect();
+        commands.extend(BUILTINS.iter().map(|(name, params, _)| (*name, params.to_vec())));
+        commands.sort();
+
+        ShellHelper {
+            commands,
+            shortcuts: Vec::new(),
+            ships: Vec::new(),
+            waypoints: BTreeSet::new(),
+            systems: BTreeSet::new(),
+            contracts: Vec::new(),
+            profiles: Vec::new(),
+            files: FilenameCompleter::new(),
+        }
+    }
+
+    // What can go in a parameter, going by its name
+    fn candidates(&self, param: &str) -> Vec<String> {
+        let strings = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+
+        match param {
+            "ship" | "symbol" | "to" | "hauler" | "miners" => self.ships.clone(),
+            "waypoint" | "field" => self.waypoints.iter().cloned().collect(),
+            "system" => self.systems.iter().cloned().collect(),
+            "contract" => self.contracts.clone(),
+            "good" => strings(variants::<TradeSymbol>()),
+            "produce" => strings(variants::<Produce>()),
+            "type" | "types" => strings(variants::<ShipType>()),
+            "faction" => strings(variants::<Faction>()),
+            "name" => self.profiles.clone(),
+            "command" => self.commands.iter().map(|(name, _)| name.to_string())
+                .chain(self.shortcuts.iter().map(|(name, _)| name.clone()))
+                .collect(),
+            "kind" => strings(&["waypoints", "systems", "ships"]),
+            "format" => strings(&["table", "json", "ndjson", "csv"]),
+            "by" => strings(&["ship", "good", "day", "activity"]),
+            "view" => strings(&["galaxy", "system"]),
+            "backend" => strings(&["plain", "keyring", "encrypted"]),
+            "chart" => strings(&["true", "false"]),
+            _ => Vec::new()
+        }
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+        let words = split(&before[..start]);
+        // `watch 10 show_ship_nav ` goes on to complete the watched command and its arguments
+        let words = match words.first() {
+            Some(first) if first == "watch" && words.len() >= 3 => words[2..].to_vec(),
+            _ => words
+        };
+
+        let params: Vec<String> = match words.first() {
+            None => vec!["command".to_string()],
+            Some(command) => self.commands.iter()
+                .find(|(name, _)| name == command)
+                .map(|(_, params)| params.iter().map(|p| p.to_string()).collect())
+                .or_else(|| self.shortcuts.iter().find(|(name, _)| name == command).map(|(_, params)| params.clone()))
+                .unwrap_or_default()
+        };
+        let param = match params.get(words.len().saturating_sub(1)) {
+            Some(param) => param.as_str(),
+            None => return Ok((pos, Vec::new()))
+        };
+
+        if param == "file" {
+            return self.files.complete(line, pos, ctx);
+        }
+
+        // Lists like `miners` are comma separated, so only the last entry is being typed
+        let start = match param {
+            "miners" | "types" => before[start..].rfind(',').map(|i| start + i + 1).unwrap_or(start),
+            _ => start
+        };
+        let typed = before[start..].to_lowercase();
+
+        let matches = self.candidates(param).into_iter()
+            .filter(|c| c.to_lowercase().starts_with(&typed))
+            .map(|c| Pair { display: c.clone(), replacement: c })
+            .collect();
+
+        Ok((start, matches))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(&'s self, prompt: &'p str, _default: bool) -> Cow<'b, str> {
+        Cow::Borrowed(prompt)
+    }
+}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Commands for managing aliases and macros, which live in the config so every session gets them
+pub fn commands() -> Vec<CommandSpec> {
+    vec![
+        command!("aliases", show_aliases)
+            .with_path("alias list")
+            .with_help("List aliases"),
+        command!("add_alias", add_alias)
+            .with_path("alias add")
+            .with_help("Make a word stand for the start of a command line, e.g. add_alias m show_ships")
+            .required("name")
+            .required("command"),
+        command!("remove_alias", remove_alias)
+            .with_path("alias remove")
+            .with_help("Remove an alias")
+            .required("name"),
+        command!("macros", show_macros)
+            .with_path("macro list")
+            .with_help("List macros"),
+        command!("add_macro", add_macro)
+            .with_path("macro add")
+            .with_help("Make a word run several commands separated by ;, with $param replaced by the given comma separated params, e.g. add_macro sellall \"sell $ship IRON_ORE 10; sell $ship COPPER_ORE 10\" ship")
+            .required("name")
+            .required("commands")
+            .optional("params"),
+        command!("remove_macro", remove_macro)
+            .with_path("macro remove")
+            .with_help("Remove a macro")
+            .required("name"),
+    ]
+}
+
+fn show_aliases(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let mut table = Table::new();
+    table.set_header(vec!["Alias", "Command"]);
+
+    for (name, alias) in &context.user_config.aliases {
+        table.add_row(vec![name.clone(), alias.clone()]);
+    }
+
+    Ok(Some(output::render_table(context.output, table)))
+}
+
+fn add_alias(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let name = shortcut_name(&args, context)?;
+    let command = args.get("command").unwrap().to_string();
+
+    if split(&command).is_empty() {
+        return Err(format!("Alias {} needs a command", name).into());
+    }
+
+    context.user_config.aliases.insert(name.clone(), command.trim().to_string());
+    Ok(Some(format!("{} now runs {}", name, command.trim())))
+}
+
+fn remove_alias(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let name = args.get("name").unwrap().to_string();
+
+    match context.user_config.aliases.remove(&name) {
+        Some(_) => Ok(Some(format!("Removed alias {}", name))),
+        None => Err(format!("No alias named {}", name).into())
+    }
+}
+
+fn show_macros(_args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let mut table = Table::new();
+    table.set_header(vec!["Macro", "Params", "Commands"]);
+
+    for (name, shortcut) in &context.user_config.macros {
+        table.add_row(vec![name.clone(), shortcut.params.join(", "), shortcut.commands.join("\n")]);
+    }
+
+    Ok(Some(output::render_table(context.output, table)))
+}
+
+fn add_macro(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let name = shortcut_name(&args, context)?;
+    let commands: Vec<String> = args.get("commands").unwrap().to_string()
+        .split(';')
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect();
+    let params: Vec<String> = args.get("params")
+        .map(|p| p.to_string().split(',').map(|p| p.trim().trim_start_matches('$').to_string()).filter(|p| !p.is_empty()).collect())
+        .unwrap_or_default();
+
+    if commands.is_empty() {
+        return Err(format!("Macro {} needs at least one command", name).into());
+    }
+    if let Some(param) = params.iter().find(|p| !p.chars().all(|c| c.is_alphanumeric() || c == '_')) {
+        return Err(format!("Macro params can only use letters, numbers and _, not {}", param).into());
+    }
+    if let Some(param) = params.iter().find(|p| !commands.iter().any(|c| c.contains(&format!("${}", p)))) {
+        return Err(format!("Macro {} never uses ${}", name, param).into());
+    }
+
+    let summary = format!("{} {} now runs {}", name, params.join(" "), commands.join("; "));
+    context.user_config.macros.insert(name, Macro { params, commands });
+    Ok(Some(summary))
+}
+
+fn remove_macro(args: HashMap<String, Value>, context: &mut ConfigWrapper) -> CommandResult {
+    let name = args.get("name").unwrap().to_string();
+
+    match context.user_config.macros.remove(&name) {
+        Some(_) => Ok(Some(format!("Removed macro {}", name))),
+        None => Err(format!("No macro named {}", name).into())
+    }
+}
+
+// A single word that isn't already a command, alias or macro
+fn shortcut_name(args: &HashMap<String, Value>, context: &ConfigWrapper) -> Result<String, CommandError> {
+    let name = args.get("name").unwrap().to_string();
+
+    if name.is_empty() || name.contains(char::is_whitespace) || name.contains('"') {
+        return Err(format!("{} can't be used as a name, it has to be a single word", name).into());
+    }
+    if crate::commands().iter().any(|s| s.name == name) || BUILTINS.iter().any(|(builtin, _, _)| *builtin == name) {
+        return Err(format!("{} is already a command", name).into());
+    }
+    if context.user_config.aliases.contains_key(&name) || context.user_config.macros.contains_key(&name) {
+        return Err(format!("{} is already an alias or macro, remove it first", name).into());
+    }
+
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use rustyline::history::MemHistory;
+
+    use super::*;
+
+    fn nothing(_args: HashMap<String, Value>, _context: &mut ConfigWrapper) -> CommandResult {
+        Ok(None)
+    }
+
+    fn helper() -> ShellHelper {
+        let specs = vec![
+            command!("navigate", nothing).required("ship").required("waypoint"),
+            command!("run_convoy", nothing).required("hauler").required("miners"),
+        ];
+        let mut helper = ShellHelper::new(&specs);
+        helper.ships = vec!["ACE-1".to_string(), "ACE-2".to_string(), "BOB-1".to_string()];
+        helper.waypoints = ["X1-AB-A1", "X1-AB-B2"].iter().map(|w| w.to_string()).collect();
+        helper
+    }
+
+    // The replacement start and the candidates offered with the cursor at the end of `line`
+    fn complete(line: &str) -> (usize, Vec<String>) {
+        let history = MemHistory::new();
+        let (start, pairs) = helper().complete(line, line.len(), &Context::new(&history)).unwrap();
+        (start, pairs.into_iter().map(|p| p.replacement).collect())
+    }
+
+    #[test]
+    fn split_keeps_quoted_words_together() {
+        assert_eq!(split(r#"  add_alias m "show_ships  now" "#), vec!["add_alias", "m", "show_ships  now"]);
+        assert_eq!(split(r#"say "a \"quote\"""#), vec!["say", r#"a "quote""#]);
+        assert!(split("   ").is_empty());
+    }
+
+    #[test]
+    fn rest_of_line_follows_a_quoted_first_word() {
+        assert_eq!(after_first_word("m SHIP-1"), " SHIP-1");
+        assert_eq!(after_first_word(r#"  "m" "SHIP 1"  "#), r#" "SHIP 1"  "#);
+        assert_eq!(after_first_word(r#""my alias" x"#), " x");
+        assert_eq!(after_first_word("m"), "");
+    }
+
+    #[test]
+    fn commands_complete_first_then_their_parameters() {
+        assert_eq!(complete("nav"), (0, vec!["navigate".to_string()]));
+        assert_eq!(complete("navigate a"), (9, vec!["ACE-1".to_string(), "ACE-2".to_string()]));
+        assert_eq!(complete("navigate ACE-1 x1-ab-b"), (15, vec!["X1-AB-B2".to_string()]));
+        assert_eq!(complete("navigate ACE-1 X1-AB-A1 "), (24, Vec::new()));
+        assert_eq!(complete("unknown "), (8, Vec::new()));
+    }
+
+    #[test]
+    fn lists_complete_their_last_entry() {
+        assert_eq!(complete("run_convoy BOB-1 ACE-1,A"), (23, vec!["ACE-1".to_string(), "ACE-2".to_string()]));
+    }
+
+    #[test]
+    fn watch_completes_the_watched_command() {
+        assert_eq!(complete("watch 10 na"), (9, vec!["navigate".to_string()]));
+        assert_eq!(complete("watch 10 navigate B"), (18, vec!["BOB-1".to_string()]));
+    }
+
+    #[test]
+    fn enum_parameters_offer_every_variant() {
+        let helper = helper();
+        assert!(helper.candidates("good").contains(&"IRON_ORE".to_string()));
+        assert_eq!(helper.candidates("format"), vec!["table", "json", "ndjson", "csv"]);
+        assert!(helper.candidates("command").contains(&"watch".to_string()));
+        assert!(variants::<u32>().is_empty());
+    }
+}
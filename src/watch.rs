@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::io::{stdout, Write};
+use std::time::Duration;
+
+use chrono::Local;
+use crossterm::cursor::MoveTo;
+use crossterm::queue;
+use crossterm::style::{Print, PrintStyledContent, Stylize};
+use crossterm::terminal::{Clear, ClearType};
+
+use crate::commands::{CommandError, CommandSpec, Value};
+use crate::config::ConfigWrapper;
+use crate::screen::{self, Screen};
+
+// Shortest interval we allow, the rate limiter may stretch it further for commands that make many requests
+const MIN_INTERVAL_SECS: u64 = 2;
+
+/// Re-runs a read command every `interval` seconds, drawing its output in place with anything
+/// that changed since the previous run highlighted.
+pub struct Watch<'a> {
+    context: &'a mut ConfigWrapper,
+    spec: &'a CommandSpec,
+    args: HashMap<String, Value>,
+    // The command as typed, for the header
+    label: String,
+    interval: Duration,
+    // The interval after allowing for the requests each run costs
+    effective: Duration,
+    requests: u64,
+    lines: Vec<String>,
+    previous: Vec<String>,
+    refreshed_at: String,
+}
+
+impl<'a> Watch<'a> {
+    pub fn new(context: &'a mut ConfigWrapper, spec: &'a CommandSpec, args: HashMap<String, Value>, label: String, interval_secs: u64)
+        -> Result<Watch<'a>, CommandError> {
+        if !spec.read_only {
+            return Err(format!("{} changes the game, only commands that just show something can be watched", spec.name).into());
+        }
+
+        let interval = Duration::from_secs(interval_secs.max(MIN_INTERVAL_SECS));
+        Ok(Watch {
+            context,
+            spec,
+            args,
+            label,
+            interval,
+            effective: interval,
+            requests: 0,
+            lines: Vec::new(),
+            previous: Vec::new(),
+            refreshed_at: String::new(),
+        })
+    }
+
+    pub fn run(&mut self) -> Result<String, String> {
+        let label = self.label.clone();
+        screen::run(self, &label).map(|_| format!("Stopped watching {}", self.label))
+    }
+}
+
+impl Screen for Watch<'_> {
+    fn refresh_interval(&self) -> Duration {
+        self.effective
+    }
+
+    fn refresh(&mut self) {
+        let before = self.context.api_config.client.rate_limiter().requests();
+
+        let output = match (self.spec.handler)(self.args.clone(), self.context) {
+            Ok(output) => output.unwrap_or_default(),
+            Err(e) => e.to_string()
+        };
+
+        // Commands that make many requests are watched more slowly, see `rate_floor`
+        let limiter = self.context.api_config.client.rate_limiter();
+        self.requests = limiter.requests() - before;
+        self.effective = self.interval.max(screen::rate_floor(self.requests, limiter.per_second()));
+
+        // The first run has nothing to compare against, so nothing is highlighted
+        let first = self.refreshed_at.is_empty();
+        self.previous = std::mem::replace(&mut self.lines, output.lines().map(|l| l.to_string()).collect());
+        if first {
+            self.previous = self.lines.clone();
+        }
+        self.refreshed_at = Local::now().format("%H:%M:%S").to_string();
+    }
+
+    fn draw(&self, width: u16, height: u16, next: Duration) -> std::io::Result<()> {
+        let width = width as usize;
+        let mut out = stdout();
+
+        let mut header = format!(" Every {}s: {}  at {}  {} requests  next in {}s  [r] refresh now  [q] quit",
+                                 self.interval.as_secs(), self.label, self.refreshed_at, self.requests, next.as_secs());
+        if self.effective > self.interval {
+            header.push_str(&format!("  (slowed to {}s for the rate limit)", self.effective.as_secs()));
+        }
+        let header: String = header.chars().take(width).collect();
+        queue!(out, MoveTo(0, 0), Clear(ClearType::UntilNewLine), Print(header))?;
+
+        let rows = (height as usize).saturating_sub(1);
+        for (row, line) in self.lines.iter().take(rows).enumerate() {
+            queue!(out, MoveTo(0, row as u16 + 1), Clear(ClearType::UntilNewLine))?;
+
+            let previous = self.previous.get(row).map(|l| tokens(l)).unwrap_or_default();
+            let mut used = 0;
+            for (i, token) in tokens(line).into_iter().enumerate() {
+                let changed = previous.get(i) != Some(&token) && !token.trim().is_empty();
+                let token: String = token.chars().take(width - used).collect();
+                used += token.chars().count();
+                if changed {
+                    queue!(out, PrintStyledContent(token.black().on_yellow()))?;
+                } else {
+                    queue!(out, Print(token))?;
+                }
+                if used >= width {
+                    break;
+                }
+            }
+        }
+        queue!(out, MoveTo(0, self.lines.len().min(rows) as u16 + 1), Clear(ClearType::FromCursorDown))?;
+        out.flush()
+    }
+}
+
+// A line split into words and the separators between them, so values are compared as a whole
+// rather than character by character and a table border never counts as a change
+fn tokens(line: &str) -> Vec<String> {
+    let word = |c: char| c.is_alphanumeric() || "-_.:/%".contains(c);
+
+    let mut tokens: Vec<String> = Vec::new();
+    for c in line.chars() {
+        match tokens.last_mut() {
+            Some(last) if last.chars().last().map(word) == Some(true) && word(c) => last.push(c),
+            Some(last) if last.chars().last().map(char::is_whitespace) == Some(true) && c.is_whitespace() => last.push(c),
+            _ => tokens.push(c.to_string())
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_single_tokens() {
+        assert_eq!(tokens("| SHIP-1 | 12:30:05 |  95% |"),
+                   vec!["|", " ", "SHIP-1", " ", "|", " ", "12:30:05", " ", "|", "  ", "95%", " ", "|"]);
+        assert_eq!(tokens("X1-AB12/C34 ok."), vec!["X1-AB12/C34", " ", "ok."]);
+        assert!(tokens("").is_empty());
+    }
+
+    #[test]
+    fn borders_and_padding_line_up_between_runs() {
+        // A changed value keeps the tokens around it in the same places, and padding never counts as a change
+        let before = tokens("| DOCKED    | 100 |");
+        let after = tokens("| IN_ORBIT  | 100 |");
+        let changed: Vec<usize> = (0..after.len())
+            .filter(|&i| before.get(i) != after.get(i) && !after[i].trim().is_empty())
+            .collect();
+        assert_eq!(changed, vec![2]);
+    }
+}